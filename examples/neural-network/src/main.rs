@@ -9,7 +9,7 @@ mod network;
 mod test;
 use crate::activations::Activation;
 use crate::loss::Loss;
-use crate::network::Network;
+use crate::network::{Hasher, LshConfig, Network};
 use mnist::{Mnist, MnistBuilder};
 use ndarray::prelude::*;
 use rand::seq::SliceRandom;
@@ -147,15 +147,24 @@ fn main() {
 
     let mut ds = DataSet::new(trn_img, trn_lbl, BATCH_SIZE);
 
+    // "srp" or "dwta"
+    let hasher = std::env::args().nth(1).unwrap_or_else(|| "srp".to_string());
+    println!("hasher: {}", hasher);
+    let hasher: Hasher = hasher.parse().unwrap();
+
     let mut m = Network::new(
         vec![N_PIXELS, 512, 10],
         vec![Activation::ReLU, Activation::Sigmoid],
-        8,
-        50,
+        LshConfig {
+            hasher,
+            n_projections: 8,
+            n_hash_tables: 50,
+        },
         0.01 / BATCH_SIZE as f32,
         0,
         "nll",
-    );
+    )
+    .unwrap();
 
     for epoch in 0..50 {
         println!("epoch {}", epoch);
@@ -2,13 +2,86 @@ use crate::activations::Activation;
 use crate::{activations, loss::Loss};
 use fnv::FnvHashMap;
 use lsh_rs::utils::create_rng;
-use lsh_rs::{DataPoint, DataPointSlice, LshMem, SignRandomProjections};
+use lsh_rs::{Error, LshMem, Result, SignRandomProjections, DWTA};
 use ndarray::prelude::*;
 use ndarray_rand::rand_distr::{StandardNormal, Uniform};
 use ndarray_rand::RandomExt;
+use std::str::FromStr;
 
 pub type Weight = Array1<f32>;
 
+/// Number of dimensions per hash value of DWTA.
+const DWTA_BIN_SIZE: usize = 8;
+
+/// Hash family that samples the neurons of a layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hasher {
+    Srp,
+    Dwta,
+}
+
+impl FromStr for Hasher {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "srp" => Ok(Hasher::Srp),
+            "dwta" => Ok(Hasher::Dwta),
+            _ => Err(Error::Failed(format!(
+                "unknown hasher {}, use srp or dwta",
+                s
+            ))),
+        }
+    }
+}
+
+/// Settings of the hash tables of every layer.
+pub struct LshConfig {
+    pub hasher: Hasher,
+    pub n_projections: usize,
+    pub n_hash_tables: usize,
+}
+
+/// Hash tables of a layer.
+enum LayerLsh {
+    Srp(LshMem<f32, SignRandomProjections<f32>>),
+    Dwta(LshMem<f32, DWTA>),
+}
+
+impl LayerLsh {
+    fn new(config: &LshConfig, in_size: usize) -> Result<Self> {
+        let (k, l) = (config.n_projections, config.n_hash_tables);
+        let lsh = match config.hasher {
+            Hasher::Srp => LayerLsh::Srp(LshMem::new(k, l, in_size).srp()?),
+            Hasher::Dwta => {
+                LayerLsh::Dwta(LshMem::new(k, l, in_size).dwta(DWTA_BIN_SIZE.min(in_size))?)
+            }
+        };
+        Ok(lsh)
+    }
+
+    fn store_vec(&mut self, v: &[f32]) -> Result<u32> {
+        match self {
+            LayerLsh::Srp(lsh) => lsh.store_vec(v),
+            LayerLsh::Dwta(lsh) => lsh.store_vec(v),
+        }
+    }
+
+    fn query_bucket_ids(&self, v: &[f32]) -> Result<Vec<u32>> {
+        match self {
+            LayerLsh::Srp(lsh) => lsh.query_bucket_ids(v),
+            LayerLsh::Dwta(lsh) => lsh.query_bucket_ids(v),
+        }
+    }
+
+    fn update_by_idx(&mut self, idx: u32, new_v: &[f32], old_v: &[f32]) -> Result<()> {
+        match self {
+            LayerLsh::Srp(lsh) => lsh.update_by_idx(idx, new_v, old_v),
+            LayerLsh::Dwta(lsh) => lsh.update_by_idx(idx, new_v, old_v),
+        }
+    }
+}
+
 pub struct MemArena {
     // the weights that constantly get updated
     pub pool: Vec<Weight>,
@@ -56,7 +129,7 @@ pub struct Network {
     // biases for all layers
     lsh2bias: Vec<FnvHashMap<u32, f32>>,
     activations: Vec<Activation>,
-    lsh_store: Vec<Option<LshMem<SignRandomProjections>>>,
+    lsh_store: Vec<Option<LayerLsh>>,
     n_layers: usize,
     pub pool: MemArena,
     lsh2pool: Vec<FnvHashMap<u32, usize>>,
@@ -75,15 +148,16 @@ impl Network {
     ///         ----------------------------------------
     ///         dimensions =  (2,     3,          3)
     ///         activations = (      ReLU,      Sigmoid)
+    ///
+    ///   The neurons of a layer are sampled with the hash tables of `lsh_config`.
     pub fn new(
         dimensions: Vec<usize>,
         activations: Vec<Activation>,
-        n_projections: usize,
-        n_hash_tables: usize,
+        lsh_config: LshConfig,
         lr: f32,
         seed: u64,
         loss: &str,
-    ) -> Self {
+    ) -> Result<Self> {
         let n_layers = dimensions.len();
         let mut w = Vec::with_capacity(n_layers);
         let mut pool = MemArena::new();
@@ -102,9 +176,7 @@ impl Network {
             let n_perceptrons = in_size * out_size;
             let mut w_idx = Vec::with_capacity(n_perceptrons);
 
-            let mut lsh = LshMem::new(n_projections, n_hash_tables, in_size)
-                .srp()
-                .unwrap();
+            let mut lsh = LayerLsh::new(&lsh_config, in_size)?;
 
             // initialize vectors per layer.
             for _ in 0..out_size {
@@ -125,7 +197,7 @@ impl Network {
         }
         pool.freeze();
 
-        Network {
+        Ok(Network {
             w,
             lsh2bias,
             activations,
@@ -136,7 +208,7 @@ impl Network {
             dimensions,
             lr,
             loss: loss.to_string(),
-        }
+        })
     }
 
     fn get_pool_idx(&self, layer: usize, j: &[u32]) -> Vec<usize> {
@@ -327,6 +399,12 @@ impl Network {
         });
     }
 
+    /// Update the hashes of the weights that changed since the last rehash.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a weight is not stored under the hash of its backup, i.e. the backups are out of
+    /// sync with the hash tables.
     pub fn rehash(&mut self) {
         for layer in 0..(self.n_layers - 1) {
             let shape = self.dimensions[layer + 1];
@@ -351,7 +429,8 @@ impl Network {
                         j as u32,
                         &w.as_slice().unwrap(),
                         &w_original.as_slice().unwrap(),
-                    );
+                    )
+                    .expect("weight backup is out of sync with the hash tables");
                     self.set_pool_backup(layer, j);
                 };
             });
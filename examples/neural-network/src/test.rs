@@ -1,6 +1,6 @@
 #![cfg(test)]
 use crate::activations::Activation;
-use crate::network::Network;
+use crate::network::{Hasher, LshConfig, Network};
 use ndarray::prelude::*;
 
 fn get_model(output_size: usize, lr: f32) -> Network {
@@ -8,7 +8,12 @@ fn get_model(output_size: usize, lr: f32) -> Network {
     let act = vec![Activation::ReLU, Activation::None];
     // increase the probability of all neurons being selected by having many hash_tables
     // and the simples hash value, i.e. a bit.
-    let m = Network::new(dim, act, 1, 100, lr, 1, "mse");
+    let lsh_config = LshConfig {
+        hasher: Hasher::Srp,
+        n_projections: 1,
+        n_hash_tables: 100,
+    };
+    let m = Network::new(dim, act, lsh_config, lr, 1, "mse").unwrap();
     m
 }
 
@@ -33,6 +38,26 @@ fn test_flow() {
     // assert![w_before[0] != w_after[0]];
 }
 
+#[test]
+fn test_dwta_flow() {
+    let dim = vec![2, 3, 4];
+    let act = vec![Activation::ReLU, Activation::None];
+    assert!("minhash".parse::<Hasher>().is_err());
+    let hasher = "dwta".parse().unwrap();
+    assert_eq!(hasher, Hasher::Dwta);
+    let lsh_config = LshConfig {
+        hasher,
+        n_projections: 1,
+        n_hash_tables: 100,
+    };
+    let mut m = Network::new(dim, act, lsh_config, 0.01, 1, "mse").unwrap();
+    let (mut comp, _) = m.forward(&[0.2, 0.4]);
+    // all output neurons are computed
+    assert_eq!(comp[1].len(), 4);
+    m.backprop(&mut comp, &[0, 1, 0, 0]);
+    m.rehash();
+}
+
 #[test]
 fn test_gradients() {
     let mut m = get_model(2, 1.);
@@ -2,7 +2,7 @@ use crate::constants::{BREAK_100, DISTANCE_R, IMG_HEIGHT, IMG_WIDTH, N_TOTAL};
 use crate::utils::{read_vec, select_and_scale_vecs, select_vec_by_row_ids, sorted_paths};
 use image::{GenericImage, GenericImageView, ImageResult};
 use lsh_rs::{
-    stats::{estimate_l, l2_ph, optimize_l2_params},
+    stats::{estimate_l, l2_ph, optimize_l2_params_with_queries},
     utils::l2_norm,
     SqlTable, LSH,
 };
@@ -72,7 +72,9 @@ pub fn sample_params(
     let vs = select_and_scale_vecs(0, n, conn).expect("could not get vecs");
     let dim = vs[0].len();
     let k: Vec<usize> = (10..20).map(|a| a as usize).collect();
-    let mut results = optimize_l2_params(delta, dim, &k, &vs)?;
+    // hold out the last 10% as queries to measure recall
+    let (vs, qs) = vs.split_at(vs.len() - vs.len() / 10);
+    let mut results = optimize_l2_params_with_queries(delta, dim, &k, vs, qs, 5)?;
 
     // now only ran on a sample n of N.
     // search_time is expected to increase by N/n (due to duplicates)
@@ -83,7 +85,10 @@ pub fn sample_params(
     });
     for opt_res in results {
         let t = opt_res.hash_time + opt_res.search_time * search_time_factor;
-        println!("{:?}, total time: {}", opt_res, t);
+        println!(
+            "{:?}, total time: {}, recall@5: {}",
+            opt_res, t, opt_res.eval.recall
+        );
     }
     Ok(())
 }
@@ -1,9 +1,21 @@
-from .floky import LshL2, LshSrp, LshSrpMem, LshL2Mem, sort_by_distances
+from .floky import (
+    LshL2,
+    LshL2Mem,
+    LshL2SqlMem,
+    LshMips,
+    LshMipsMem,
+    LshMipsSqlMem,
+    LshSrp,
+    LshSrpMem,
+    LshSrpSqlMem,
+    sort_by_distances,
+)
 from tqdm import tqdm
 import numpy as np
 import os
+import pickle
 from collections import namedtuple
-from typing import Union, List
+from typing import Union, List, Optional
 
 
 QueryResult = namedtuple(
@@ -12,9 +24,11 @@ QueryResult = namedtuple(
 
 
 class Base:
+    # Rust class per backend; "mem", "sqlite" or "sqlite-mem".
+    lsh_builders = {}
+
     def __init__(
         self,
-        lsh: Union[LshL2, LshL2Mem, LshSrp, LshSrpMem],
         n_projections: int,
         n_hash_tables: int,
         dim: int,
@@ -22,32 +36,68 @@ class Base:
         seed: int,
         in_mem: bool,
         log: bool,
+        dtype: str,
+        backend: Optional[str],
+        multi_probe: Optional[int],
     ):
+        if backend is None:
+            backend = "mem" if in_mem else "sqlite"
+        if backend not in self.lsh_builders:
+            raise ValueError(
+                "backend {} is not supported, use one of: {}".format(
+                    backend, ", ".join(self.lsh_builders)
+                )
+            )
         self.n_projection = n_projections
         self.n_hash_tables = n_hash_tables
         self.dim = dim
-        self.lsh = lsh
         self.seed = seed
         self.db_path = db_path
         self.data = None
-        self.in_mem = in_mem
+        self.backend = backend
+        self.in_mem = backend == "mem"
         self.log = log
+        self.dtype = np.dtype(dtype).name
+        self.budget = multi_probe
+        self.lsh = self._build()
+
+    def _params(self) -> tuple:
+        """
+        Parameters of the hash family, passed to the Rust class after `dim`.
+        """
+        return ()
+
+    def _build(self):
+        lsh = self.lsh_builders[self.backend](
+            self.n_projection,
+            self.n_hash_tables,
+            self.dim,
+            *self._params(),
+            self.seed,
+            self.db_path,
+            self.dtype,
+        )
+        if self.budget is not None:
+            lsh.multi_probe(self.budget)
+        return lsh
 
     def base(self):
         """
         Toggle base LSH (In contrast to multi-probe LSH)
         """
+        self.budget = None
         self.lsh.base()
 
     def multi_probe(self, budget: int):
         """
-        Toggle multi-probe LSH.
+        Toggle multi-probe LSH. The setting is kept when `fit` rebuilds the hash tables.
 
         Parameters
         ----------
         budget
             The upper bound on the number of probes.
         """
+        self.budget = budget
         self.lsh.multi_probe(budget)
 
     def describe(self):
@@ -66,7 +116,7 @@ class Base:
             Shape: (dim, )
             Store data point `v`
         """
-        self.lsh.store_vec(v)
+        self.lsh.store_vec(np.ascontiguousarray(v, dtype=self.dtype))
 
     def store_vecs(
         self, vs: Union[np.ndarray, List[List[float]]], chunk_size: int = 250
@@ -90,7 +140,8 @@ class Base:
 
         with tqdm(total=length, disable=not self.log) as pbar:
             while prev_i < length:
-                self.lsh.store_vecs(vs[prev_i:i])
+                chunk = np.ascontiguousarray(vs[prev_i:i], dtype=self.dtype)
+                self.lsh.store_vecs(chunk)
                 prev_i = i
                 i += chunk_size
                 pbar.update(chunk_size)
@@ -118,7 +169,7 @@ class Base:
         -------
         List of data points.
         """
-        return self.lsh.query_bucket(v)
+        return self.lsh.query_bucket(np.ascontiguousarray(v, dtype=self.dtype))
 
     def query_bucket_idx(self, v: Union[np.ndarray, List[float]]) -> List[int]:
         """
@@ -136,7 +187,7 @@ class Base:
         List of ids/ indexes
 
         """
-        return self.lsh.query_bucket_idx(v)
+        return self.lsh.query_bucket_idx(np.ascontiguousarray(v, dtype=self.dtype))
 
     def delete_vec(self, v: Union[np.ndarray, List[float]]):
         """
@@ -149,7 +200,7 @@ class Base:
             Data point `v`
 
         """
-        self.lsh.delete_vec(v)
+        self.lsh.delete_vec(np.ascontiguousarray(v, dtype=self.dtype))
 
     def commit(self):
         """
@@ -171,7 +222,12 @@ class Base:
         self.lsh.index()
 
     def reset(self, dim: int):
-        raise NotImplementedError
+        """
+        Remove all data points and create new hash functions for data points of dimension `dim`.
+        """
+        self.clean()
+        self.dim = dim
+        self.lsh = self._build()
 
     def fit(self, X: Union[np.ndarray, List[List[float]]], chunk_size: int = 250):
         """
@@ -188,7 +244,10 @@ class Base:
         """
         dim = len(X[0])
         self.reset(dim)
-        self.data = np.ascontiguousarray(np.array(X, dtype=np.float32))
+        # no copy if X already is a contiguous array of `dtype`
+        self.data = np.ascontiguousarray(X, dtype=self.dtype)
+        # only the hashers of MIPS depend on the data
+        self.lsh.fit(self.data)
         self.lsh.increase_storage(len(X))
         self.store_vecs(self.data, chunk_size)
 
@@ -209,6 +268,7 @@ class Base:
         distance_f
             - "cosine"
             - "euclidean"
+            - "inner_product"
         only_index
             Only return indexes and not the data points.
         top_k
@@ -222,10 +282,10 @@ class Base:
             raise ValueError("data attribute is not set")
         if not isinstance(x, (list, np.ndarray)):
             raise ValueError("x is not an array")
-        X = np.ascontiguousarray(np.array(x, dtype=np.float32))
-        if len(x.shape) == 1:
-            X = np.array([x])
-        elif len(x.shape) != 2:
+        X = np.ascontiguousarray(x, dtype=self.dtype)
+        if X.ndim == 1:
+            X = X[None, :]
+        elif X.ndim != 2:
             raise ValueError("x should be a 2d array")
 
         qrs = []
@@ -252,23 +312,86 @@ class Base:
         """
         Remove database file
         """
-        if not self.in_mem:
+        if self.backend == "sqlite":
             os.remove(self.db_path)
 
-    def to_mem(self, pages_per_step: int = 100):
+    def to_mem(self):
         """
         SQLite disk based backend to SQLite memory backend.
+        """
+        if self.backend == "sqlite":
+            self.lsh.to_mem()
+
+    def __getstate__(self):
+        state = self.__dict__.copy()
+        if self.backend == "mem":
+            state["lsh"] = self.lsh.dump_bytes()
+        else:
+            # "sqlite" is reopened from `db_path`, "sqlite-mem" is rebuilt from the data.
+            state["lsh"] = None
+        return state
+
+    def __setstate__(self, state):
+        buf = state.pop("lsh")
+        self.__dict__.update(state)
+        self.lsh = self._build()
+        if self.backend == "mem":
+            self.lsh.load_bytes(buf)
+        elif self.data is not None:
+            # the database stores MIPS hashers before they are fitted
+            self.lsh.fit(self.data)
+            if self.backend == "sqlite-mem":
+                self.lsh.increase_storage(len(self.data))
+                self.store_vecs(self.data)
+
+    def dump(self, path: str):
+        """
+        Save the object with pickle. The hash tables of the "mem" backend are serialized with bincode.
+        The "sqlite" backend only stores a reference to `db_path` and the "sqlite-mem" backend is
+        rebuilt from the data when it is loaded.
 
         Parameters
         ----------
-        pages_per_step
-            Number of pages per step
+        path
+            Path of the file to write.
         """
-        if not self.in_mem:
-            self.lsh.to_mem(pages_per_step)
+        with open(path, "wb") as f:
+            pickle.dump(self, f)
+
+    @classmethod
+    def load(cls, path: str):
+        """
+        Load an object saved with `dump`.
+
+        Parameters
+        ----------
+        path
+            Path of the file written by `dump`.
+        """
+        with open(path, "rb") as f:
+            obj = pickle.load(f)
+        if not isinstance(obj, cls):
+            raise TypeError(
+                "{} does not contain a {} object".format(path, cls.__name__)
+            )
+        return obj
+
+    def to_db(self, db_path: Optional[str] = None):
+        """
+        Write the hash tables of the SQLite in memory backend to a database file.
+        Only supported by the "sqlite-mem" backend.
+
+        Parameters
+        ----------
+        db_path
+            Path of the database file. Defaults to `db_path` of the constructor.
+        """
+        self.lsh.to_db(self.db_path if db_path is None else db_path)
 
 
 class L2(Base):
+    lsh_builders = {"mem": LshL2Mem, "sqlite": LshL2, "sqlite-mem": LshL2SqlMem}
+
     def __init__(
         self,
         n_projections: int,
@@ -279,6 +402,9 @@ class L2(Base):
         db_path: str = "./lsh.db3",
         in_mem: bool = True,
         log: bool = True,
+        dtype: str = "float32",
+        backend: Optional[str] = None,
+        multi_probe: Optional[int] = None,
     ):
         """
         L2 LSH. Used to find data points with minimal euclidean distance.
@@ -298,32 +424,35 @@ class L2(Base):
         db_path
             Path to SQLite database file. Only needed for SQLite backend.
         in_mem
-            In memory backend or SQLite backend
+            In memory backend or SQLite backend. Ignored if `backend` is set.
         log
             Print fit information to screen
+        dtype
+            Element type of the hash functions and data points: "float32" or "float64".
+            Other data, i.e. uint8 images, is converted to this type.
+        backend
+            - "mem": in memory hash tables.
+            - "sqlite": SQLite database at `db_path`.
+            - "sqlite-mem": SQLite database in memory. Can be written to disk with `to_db`.
+        multi_probe
+            Budget of multi-probe LSH. If not set, base LSH is used.
         """
-        if in_mem:
-            self.lsh_builder = LshL2Mem
-        else:
-            self.lsh_builder = LshL2
-
-        lsh = self.lsh_builder(n_projections, n_hash_tables, dim, r, seed, db_path)
         self.r = r
         super().__init__(
-            lsh, n_projections, n_hash_tables, dim, db_path, seed, in_mem, log
+            n_projections,
+            n_hash_tables,
+            dim,
+            db_path,
+            seed,
+            in_mem,
+            log,
+            dtype,
+            backend,
+            multi_probe,
         )
 
-    def reset(self, dim: int):
-        self.clean()
-        self.dim = dim
-        self.lsh = self.lsh_builder(
-            self.n_projection,
-            self.n_hash_tables,
-            self.dim,
-            self.r,
-            self.seed,
-            self.db_path,
-        )
+    def _params(self) -> tuple:
+        return (self.r,)
 
     def predict(
         self,
@@ -348,11 +477,12 @@ class L2(Base):
         -------
         Named tuples List[QueryResult]
         """
-
         return self._predict(x, "euclidean", only_index, top_k)
 
 
 class SRP(Base):
+    lsh_builders = {"mem": LshSrpMem, "sqlite": LshSrp, "sqlite-mem": LshSrpSqlMem}
+
     def __init__(
         self,
         n_projections: int,
@@ -362,6 +492,9 @@ class SRP(Base):
         db_path: str = "./lsh.db3",
         in_mem: bool = True,
         log: bool = True,
+        dtype: str = "float32",
+        backend: Optional[str] = None,
+        multi_probe: Optional[int] = None,
     ):
         """
         Signed Random Projections. Used to for cosine similarity.
@@ -379,26 +512,132 @@ class SRP(Base):
         db_path
             Path to SQLite database file. Only needed for SQLite backend.
         in_mem
-            In memory backend or SQLite backend
+            In memory backend or SQLite backend. Ignored if `backend` is set.
         log
             Print fit information to screen
+        dtype
+            Element type of the hash functions and data points: "float32" or "float64".
+            Other data, i.e. uint8 images, is converted to this type.
+        backend
+            - "mem": in memory hash tables.
+            - "sqlite": SQLite database at `db_path`.
+            - "sqlite-mem": SQLite database in memory. Can be written to disk with `to_db`.
+        multi_probe
+            Budget of multi-probe LSH. If not set, base LSH is used.
         """
-        if in_mem:
-            self.lsh_builder = LshSrpMem
-        else:
-            self.lsh_builder = LshSrp
-        lsh = self.lsh_builder(n_projections, n_hash_tables, dim, seed, db_path)
         super().__init__(
-            lsh, n_projections, n_hash_tables, dim, db_path, seed, in_mem, log
+            n_projections,
+            n_hash_tables,
+            dim,
+            db_path,
+            seed,
+            in_mem,
+            log,
+            dtype,
+            backend,
+            multi_probe,
         )
 
-    def reset(self, dim: int):
-        self.clean()
-        self.dim = dim
-        self.lsh = self.lsh_builder(
-            self.n_projection, self.n_hash_tables, self.dim, self.seed, self.db_path
+    def predict(
+        self,
+        x: Union[np.ndarray, List[List[float]]],
+        only_index: bool = False,
+        top_k: int = 5,
+    ):
+        """
+        Query data points.
+
+        Parameters
+        ----------
+        x
+            Shape: (n, dim)
+            Query data points
+        only_index
+            Only return indexes and not the data points.
+        top_k
+            Take the k closest
+
+        Returns
+        -------
+        Named tuples List[QueryResult]
+        """
+        return self._predict(x, "cosine", only_index, top_k)
+
+
+class MIPS(Base):
+    lsh_builders = {"mem": LshMipsMem, "sqlite": LshMips, "sqlite-mem": LshMipsSqlMem}
+
+    def __init__(
+        self,
+        n_projections: int,
+        n_hash_tables: int,
+        dim: int = 10,
+        r: float = 4.0,
+        U: float = 0.83,
+        m: int = 3,
+        seed: int = 0,
+        db_path: str = "./lsh.db3",
+        in_mem: bool = True,
+        log: bool = True,
+        dtype: str = "float32",
+        backend: Optional[str] = None,
+        multi_probe: Optional[int] = None,
+    ):
+        """
+        Maximum Inner Product Search. Used to find data points with a maximal inner product.
+        The data points are scaled by their maximum norm, which is determined by `fit`.
+
+        Parameters
+        ----------
+        n_projections
+            Number of values in the hash; `K` in literature.
+        n_hash_tables
+            Number of hash tables; `L` in literature.
+        dim
+            Dimension of the data points.
+        r
+            Parameter of the underlying L2 LSH. Sets the width of the hashing buckets.
+        U
+            Scaling factor of the data points. Should be smaller than 1.
+        m
+            Number of extra dimensions added to the data points.
+        seed
+            Seed for the hashing functions. If set to zero, the hashing functions are randomly generated.
+        db_path
+            Path to SQLite database file. Only needed for SQLite backend.
+        in_mem
+            In memory backend or SQLite backend. Ignored if `backend` is set.
+        log
+            Print fit information to screen
+        dtype
+            Element type of the hash functions and data points: "float32" or "float64".
+            Other data, i.e. uint8 images, is converted to this type.
+        backend
+            - "mem": in memory hash tables.
+            - "sqlite": SQLite database at `db_path`.
+            - "sqlite-mem": SQLite database in memory. Can be written to disk with `to_db`.
+        multi_probe
+            Budget of multi-probe LSH. If not set, base LSH is used.
+        """
+        self.r = r
+        self.U = U
+        self.m = m
+        super().__init__(
+            n_projections,
+            n_hash_tables,
+            dim,
+            db_path,
+            seed,
+            in_mem,
+            log,
+            dtype,
+            backend,
+            multi_probe,
         )
 
+    def _params(self) -> tuple:
+        return (self.r, self.U, self.m)
+
     def predict(
         self,
         x: Union[np.ndarray, List[List[float]]],
@@ -418,8 +657,10 @@ class SRP(Base):
         top_k
             Take the k closest
 
+        The distances in the results are negative inner products.
+
         Returns
         -------
         Named tuples List[QueryResult]
         """
-        return self._predict(x, "cosine", only_index, top_k)
+        return self._predict(x, "inner_product", only_index, top_k)
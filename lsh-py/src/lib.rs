@@ -1,38 +1,30 @@
 mod dist;
 use crate::dist::sort_by_distance;
-use lsh_rs::{Error as LshError, LshMem, LshSql, SignRandomProjections, L2, MIPS};
+use lsh_rs::{
+    data::Numeric, Error as LshError, LshMem, LshSql, LshSqlMem, SignRandomProjections, SqlTable,
+    L2, MIPS,
+};
+use num::{Float, NumCast};
 use pyo3::exceptions::{RuntimeError, ValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
 use pyo3::wrap_pyfunction;
+use serde::de::DeserializeOwned;
+use std::sync::{Mutex, RwLock};
 use thiserror::Error;
 
 use ndarray::parallel::prelude::*;
 use ndarray::prelude::*;
-use numpy::PyArray2;
-use pyo3::prelude::*;
+use numpy::{PyArray1, PyArray2, TypeNum};
 
-#[pyfunction]
-#[text_signature = "(qs, vs, distance_f, indexes, top_k, /)"]
-pub fn sort_by_distances(
-    qs: &PyArray2<f32>,
-    vs: &PyArray2<f32>,
+fn sort_by_distances_arr<N: Numeric + Float>(
+    qs: ArrayView2<N>,
+    vs: ArrayView2<N>,
     distance_f: &str,
     indexes: Vec<Vec<usize>>,
     top_k: usize,
-) -> PyResult<(Vec<Vec<usize>>, Vec<Vec<f32>>)> {
-    // let gil_guard = Python::acquire_gil();
-    // let py = gil_guard.python();
-    let distance_f = match distance_f {
-        "cosine" => "cosine",
-        "l2" | "euclidean" => "l2",
-        _ => return Err(PyErr::new::<ValueError, _>("distance function not correct")),
-    };
-
-    let vs = vs.as_array();
-    // (Vec<usize>, Vec<f32>)
-    let r = qs
-        .as_array()
-        .axis_iter(Axis(0))
+) -> (Vec<Vec<usize>>, Vec<Vec<N>>) {
+    qs.axis_iter(Axis(0))
         .into_par_iter()
         .zip(indexes)
         .map(|(q, idx)| {
@@ -42,9 +34,37 @@ pub fn sort_by_distances(
                 .collect::<Vec<_>>();
             sort_by_distance(q, &vs, distance_f, top_k)
         })
-        .unzip();
+        .unzip()
+}
+
+#[pyfunction]
+#[text_signature = "(qs, vs, distance_f, indexes, top_k, /)"]
+pub fn sort_by_distances(
+    py: Python,
+    qs: &PyAny,
+    vs: &PyAny,
+    distance_f: &str,
+    indexes: Vec<Vec<usize>>,
+    top_k: usize,
+) -> PyResult<PyObject> {
+    let distance_f = match distance_f {
+        "cosine" => "cosine",
+        "l2" | "euclidean" => "l2",
+        "inner_product" => "inner_product",
+        _ => return Err(PyErr::new::<ValueError, _>("distance function not correct")),
+    };
 
-    Ok(r)
+    if let (Ok(qs), Ok(vs)) = (
+        qs.extract::<&PyArray2<f32>>(),
+        vs.extract::<&PyArray2<f32>>(),
+    ) {
+        let r = sort_by_distances_arr(qs.as_array(), vs.as_array(), distance_f, indexes, top_k);
+        return Ok(r.to_object(py));
+    }
+    let qs: &PyArray2<f64> = qs.extract()?;
+    let vs: &PyArray2<f64> = vs.extract()?;
+    let r = sort_by_distances_arr(qs.as_array(), vs.as_array(), distance_f, indexes, top_k);
+    Ok(r.to_object(py))
 }
 
 // https://github.com/PyO3/pyo3/issues/696
@@ -58,6 +78,12 @@ enum PyLshErr {
     Err(#[from] LshError),
     #[error("array memory order is not contiguous")]
     NonContiguous,
+    #[error("{0} is only supported by the SQLite backends")]
+    NotSqlite(&'static str),
+    #[error("{0} is only supported by the SQLite in memory backend")]
+    NotSqliteMem(&'static str),
+    #[error("{0} is only supported by the in memory backend")]
+    NotMem(&'static str),
 }
 
 impl std::convert::From<PyLshErr> for PyErr {
@@ -72,177 +98,383 @@ fn floky(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<LshMips>()?;
     m.add_class::<LshSrp>()?;
     m.add_class::<LshL2Mem>()?;
+    m.add_class::<LshMipsMem>()?;
     m.add_class::<LshSrpMem>()?;
+    m.add_class::<LshL2SqlMem>()?;
+    m.add_class::<LshMipsSqlMem>()?;
+    m.add_class::<LshSrpSqlMem>()?;
     m.add_wrapped(wrap_pyfunction!(sort_by_distances)).unwrap();
     Ok(())
 }
 
-enum LshTypes {
-    L2(LshSql<f32, L2<f32>>),
-    L2Mem(LshMem<f32, L2<f32>>),
-    Mips(LshSql<f32, MIPS<f32>>),
-    Srp(LshSql<f32, SignRandomProjections<f32>>),
-    SrpMem(LshMem<f32, SignRandomProjections<f32>>),
+/// Copy a numpy array or another sequence. The data is copied while the GIL is held, as other
+/// Python threads can mutate a numpy array once the GIL is released.
+fn extract_vec<'a, N>(v: &'a PyAny) -> PyResult<Vec<N>>
+where
+    N: TypeNum + FromPyObject<'a>,
+{
+    if let Ok(arr) = v.extract::<&PyArray1<N>>() {
+        if let Ok(slice) = arr.as_slice() {
+            return Ok(slice.to_vec());
+        }
+    }
+    v.extract()
+}
+
+/// Copy a 2D numpy array, see `extract_vec`. The array should be in C order.
+fn extract_arr<N: TypeNum>(vs: &PyAny) -> PyResult<Array2<N>> {
+    let vs = vs.extract::<&PyArray2<N>>()?.as_array();
+    if !vs.is_standard_layout() {
+        return Err(PyLshErr::NonContiguous.into());
+    }
+    Ok(vs.to_owned())
+}
+
+/// Lock around an LSH. The Python objects can be used from multiple threads while the GIL is
+/// released. SQLite connections can not be shared between threads, so the SQLite backends are
+/// behind a `Mutex` and only the in memory backend is queried concurrently.
+trait Shared<S>: Send + Sync {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R;
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R;
+}
+
+impl<S: Send + Sync> Shared<S> for RwLock<S> {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.write().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl<S: Send> Shared<S> for Mutex<S> {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+enum LshTypes<N: Numeric + Float> {
+    L2(Mutex<LshSql<N, L2<N>>>),
+    L2Mem(RwLock<LshMem<N, L2<N>>>),
+    L2SqlMem(Mutex<LshSqlMem<N, L2<N>>>),
+    Mips(Mutex<LshSql<N, MIPS<N>>>),
+    MipsMem(RwLock<LshMem<N, MIPS<N>>>),
+    MipsSqlMem(Mutex<LshSqlMem<N, MIPS<N>>>),
+    Srp(Mutex<LshSql<N, SignRandomProjections<N>>>),
+    SrpMem(RwLock<LshMem<N, SignRandomProjections<N>>>),
+    SrpSqlMem(Mutex<LshSqlMem<N, SignRandomProjections<N>>>),
     Empty,
 }
 
+/// Lock the LSH of any variant with `$lock` (`read` or `write`) and call `$body` on it.
+///
+/// Locks must only be taken while the GIL is released. Otherwise a thread waiting for the lock
+/// holds the GIL, and blocks all other Python threads.
 macro_rules! call_lsh_types {
-    ($lsh:expr, $method_call:ident, $value:expr, $($optional:tt),*) => {
+    ($lsh:expr, $lock:ident, $inner:ident => $body:expr) => {
         match $lsh {
-            LshTypes::L2(lsh) => {lsh.$method_call($value) $($optional),*},
-            LshTypes::L2Mem(lsh) => {lsh.$method_call($value)$($optional),*},
-            LshTypes::Mips(lsh) => {lsh.$method_call($value)$($optional),*},
-            LshTypes::Srp(lsh) => {lsh.$method_call($value)$($optional),*},
-            LshTypes::SrpMem(lsh) => {lsh.$method_call($value)$($optional),*},
+            LshTypes::L2(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::L2Mem(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::L2SqlMem(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::Mips(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::MipsMem(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::MipsSqlMem(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::Srp(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::SrpMem(l) => Shared::$lock(l, |$inner| $body),
+            LshTypes::SrpSqlMem(l) => Shared::$lock(l, |$inner| $body),
             LshTypes::Empty => panic!("base not initialized"),
-        };
+        }
     };
+}
 
-    ($lsh:expr, $method_call:ident, $($optional:tt),*) => {
+/// Lock the LSH of the in memory backends and call `$body` on it. Other backends return a
+/// `PyLshErr::NotMem` error for `$method`.
+macro_rules! call_lsh_mem {
+    ($lsh:expr, $method:expr, $lock:ident, $inner:ident => $body:expr) => {
         match $lsh {
-            LshTypes::L2(lsh) => {lsh.$method_call() $($optional),*},
-            LshTypes::L2Mem(lsh) => {lsh.$method_call() $($optional),*},
-            LshTypes::Mips(lsh) => {lsh.$method_call() $($optional),*},
-            LshTypes::Srp(lsh) => {lsh.$method_call() $($optional),*},
-            LshTypes::SrpMem(lsh) => {lsh.$method_call() $($optional),*},
+            LshTypes::L2Mem(l) => Shared::$lock(l, |$inner| $body).map_err(PyLshErr::from),
+            LshTypes::MipsMem(l) => Shared::$lock(l, |$inner| $body).map_err(PyLshErr::from),
+            LshTypes::SrpMem(l) => Shared::$lock(l, |$inner| $body).map_err(PyLshErr::from),
             LshTypes::Empty => panic!("base not initialized"),
-        };
+            _ => Err(PyLshErr::NotMem($method)),
+        }
     };
 }
 
-#[pyclass]
-struct Base {
-    lsh: LshTypes,
-}
+impl<N> LshTypes<N>
+where
+    N: Numeric + Float + DeserializeOwned + TypeNum + ToPyObject + for<'a> FromPyObject<'a>,
+{
+    fn store_vec(&self, py: Python, v: &PyAny) -> PyResult<()> {
+        let v = extract_vec(v)?;
+        py.allow_threads(|| call_lsh_types!(self, write, lsh => lsh.store_vec(&v)))
+            .map_err(PyLshErr::from)?;
+        Ok(())
+    }
 
-impl Base {
-    fn _store_vec(&mut self, v: Vec<f32>) -> IntResult<()> {
-        call_lsh_types!(&mut self.lsh, store_vec, &v,)?;
+    fn store_vecs(&self, py: Python, vs: &PyAny) -> PyResult<()> {
+        let vs = extract_arr(vs)?;
+        py.allow_threads(|| call_lsh_types!(self, write, lsh => lsh.store_array(vs.view())))
+            .map_err(PyLshErr::from)?;
         Ok(())
     }
 
-    fn _store_vecs(&mut self, vs: &PyArray2<f32>) -> IntResult<()> {
-        let vs = vs.as_array();
-        call_lsh_types!(&mut self.lsh, store_array, vs,)?;
+    fn query_bucket_idx(&self, py: Python, v: &PyAny) -> PyResult<Vec<u32>> {
+        let v = extract_vec(v)?;
+        let q = py
+            .allow_threads(|| call_lsh_types!(self, read, lsh => lsh.query_bucket_ids(&v)))
+            .map_err(PyLshErr::from)?;
+        Ok(q)
+    }
+
+    fn increase_storage(&self, py: Python, upper_bound: usize) -> PyResult<()> {
+        py.allow_threads(
+            || call_lsh_types!(self, write, lsh => lsh.increase_storage(upper_bound).map(|_| ())),
+        )
+        .map_err(PyLshErr::from)?;
         Ok(())
     }
-    fn _query_bucket_idx(&self, v: Vec<f32>) -> IntResult<Vec<u32>> {
-        let q = call_lsh_types!(&self.lsh, query_bucket_ids, &v,)?;
+
+    fn query_batch(&self, py: Python, vs: &PyAny) -> PyResult<Vec<Vec<u32>>> {
+        let vs = extract_arr(vs)?;
+        // SQLite connections can not be shared between threads, so only the in memory backend
+        // queries in parallel.
+        let q = py
+            .allow_threads(|| match self {
+                LshTypes::L2Mem(l) => {
+                    Shared::read(l, |lsh| lsh.query_bucket_ids_batch_arr_par(vs.view()))
+                }
+                LshTypes::MipsMem(l) => {
+                    Shared::read(l, |lsh| lsh.query_bucket_ids_batch_arr_par(vs.view()))
+                }
+                LshTypes::SrpMem(l) => {
+                    Shared::read(l, |lsh| lsh.query_bucket_ids_batch_arr_par(vs.view()))
+                }
+                lsh => call_lsh_types!(lsh, read, lsh => lsh.query_bucket_ids_batch_arr(vs.view())),
+            })
+            .map_err(PyLshErr::from)?;
         Ok(q)
     }
 
-    fn _increase_storage(&mut self, upper_bound: usize) -> IntResult<()> {
-        call_lsh_types!(&mut self.lsh, increase_storage, upper_bound, ;);
+    fn query_bucket(&self, py: Python, v: &PyAny) -> PyResult<PyObject> {
+        let v = extract_vec(v)?;
+        let q = py
+            .allow_threads(|| {
+                call_lsh_types!(self, read, lsh => lsh
+                    .query_bucket(&v)
+                    .map(|bucket| bucket.into_iter().cloned().collect::<Vec<_>>()))
+            })
+            .map_err(PyLshErr::from)?;
+        Ok(q.to_object(py))
+    }
+
+    fn delete_vec(&self, py: Python, v: &PyAny) -> PyResult<()> {
+        let v = extract_vec(v)?;
+        py.allow_threads(|| call_lsh_types!(self, write, lsh => lsh.delete_vec(&v)))
+            .map_err(PyLshErr::from)?;
         Ok(())
     }
 
-    fn _query_batch(&self, vs: &PyArray2<f32>) -> IntResult<Vec<Vec<u32>>> {
-        let gil_guard = Python::acquire_gil();
-        let py = gil_guard.python();
-        // allow threads doesn't make a difference on the rust side. But allows other python
-        // code to run.
-        // https://github.com/PyO3/pyo3/issues/649#issuecomment-546656381
+    fn describe(&self, py: Python) -> PyResult<String> {
+        let s = py
+            .allow_threads(|| call_lsh_types!(self, read, lsh => lsh.describe()))
+            .map_err(PyLshErr::from)?;
+        Ok(s)
+    }
 
-        let vs = vs.as_array();
-        if !vs.is_standard_layout() {
-            return Err(PyLshErr::NonContiguous);
-        }
-        let q = match &self.lsh {
-            LshTypes::L2(lsh) => lsh.query_bucket_ids_batch_arr(vs),
-            LshTypes::L2Mem(lsh) => {
-                py.allow_threads(move || lsh.query_bucket_ids_batch_arr_par(vs))
+    /// Fit the data dependent hashers on the data points `vs`. Only MIPS needs the maximum norm
+    /// of the data, for the other hash families this is a no-op.
+    fn fit(&self, py: Python, vs: &PyAny) -> PyResult<()> {
+        let vs = extract_arr::<N>(vs)?;
+        // standard layout is checked by `extract_arr`
+        let flat = vs.as_slice().unwrap();
+        py.allow_threads(|| match self {
+            LshTypes::Mips(l) => {
+                Shared::write(l, |lsh| lsh.hashers.iter_mut().for_each(|h| h.fit(flat)))
             }
-            LshTypes::Mips(lsh) => lsh.query_bucket_ids_batch_arr(vs),
-            LshTypes::Srp(lsh) => lsh.query_bucket_ids_batch_arr(vs),
-            LshTypes::SrpMem(lsh) => {
-                py.allow_threads(move || lsh.query_bucket_ids_batch_arr_par(vs))
+            LshTypes::MipsMem(l) => {
+                Shared::write(l, |lsh| lsh.hashers.iter_mut().for_each(|h| h.fit(flat)))
             }
-            _ => panic!("base not initialized"),
-        }?;
-        Ok(q)
+            LshTypes::MipsSqlMem(l) => {
+                Shared::write(l, |lsh| lsh.hashers.iter_mut().for_each(|h| h.fit(flat)))
+            }
+            _ => {}
+        });
+        Ok(())
     }
 
-    fn _query_bucket(&self, v: Vec<f32>) -> IntResult<Vec<Vec<f32>>> {
-        let q = match &self.lsh {
-            LshTypes::L2(lsh) => lsh
-                .query_bucket(&v)?
-                .into_iter()
-                .map(|dp| dp.clone())
-                .collect(),
-            LshTypes::L2Mem(lsh) => lsh
-                .query_bucket(&v)?
-                .into_iter()
-                .map(|dp| dp.clone())
-                .collect(),
-            LshTypes::Mips(lsh) => lsh
-                .query_bucket(&v)?
-                .into_iter()
-                .map(|dp| dp.clone())
-                .collect(),
-            LshTypes::Srp(lsh) => lsh
-                .query_bucket(&v)?
-                .into_iter()
-                .map(|dp| dp.clone())
-                .collect(),
-            LshTypes::SrpMem(lsh) => lsh
-                .query_bucket(&v)?
-                .into_iter()
-                .map(|dp| dp.clone())
-                .collect(),
+    /// Call `f` on the hash tables of the SQLite backends. Other backends return a
+    /// `PyLshErr::NotSqlite` error for `method`.
+    fn with_sql_table<R>(
+        &self,
+        py: Python,
+        method: &'static str,
+        f: impl FnOnce(&mut SqlTable<N>) -> lsh_rs::Result<R> + Send,
+    ) -> IntResult<R>
+    where
+        R: Send,
+    {
+        let r = py.allow_threads(|| match self {
+            LshTypes::L2(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
+            LshTypes::Mips(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
+            LshTypes::Srp(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
+            LshTypes::L2SqlMem(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
+            LshTypes::MipsSqlMem(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
+            LshTypes::SrpSqlMem(l) => {
+                Shared::write(l, |lsh| f(lsh.hash_tables.as_mut().unwrap())).map_err(PyLshErr::from)
+            }
             LshTypes::Empty => panic!("base not initialized"),
-        };
-        Ok(q)
+            _ => Err(PyLshErr::NotSqlite(method)),
+        })?;
+        Ok(r)
     }
 
-    fn _delete_vec(&mut self, v: Vec<f32>) -> IntResult<()> {
-        call_lsh_types!(&mut self.lsh, delete_vec, &v,)?;
-        Ok(())
+    fn commit(&self, py: Python) -> IntResult<()> {
+        self.with_sql_table(py, "commit", |ht| ht.commit())
     }
 
-    fn _describe(&mut self) -> IntResult<String> {
-        let s = call_lsh_types!(&mut self.lsh, describe,)?;
-        Ok(s)
+    fn init_transaction(&self, py: Python) -> IntResult<()> {
+        self.with_sql_table(py, "init_transaction", |ht| ht.init_transaction())
+    }
+
+    fn index(&self, py: Python) -> IntResult<()> {
+        self.with_sql_table(py, "index", |ht| ht.index_hash())
+    }
+
+    fn to_mem(&self, py: Python) -> IntResult<()> {
+        self.with_sql_table(py, "to_mem", |ht| ht.to_mem())
     }
 
-    fn _commit(&mut self) -> IntResult<()> {
-        match &mut self.lsh {
-            LshTypes::L2(lsh) => lsh.commit()?,
-            LshTypes::Mips(lsh) => lsh.commit()?,
-            LshTypes::Srp(lsh) => lsh.commit()?,
-            _ => panic!("base not initialized"),
-        };
+    fn to_db(&self, py: Python, db_path: &str) -> IntResult<()> {
+        py.allow_threads(|| match self {
+            LshTypes::L2SqlMem(l) => {
+                Shared::write(l, |lsh| lsh.hash_tables.as_mut().unwrap().to_db(db_path))
+                    .map_err(PyLshErr::from)
+            }
+            LshTypes::MipsSqlMem(l) => {
+                Shared::write(l, |lsh| lsh.hash_tables.as_mut().unwrap().to_db(db_path))
+                    .map_err(PyLshErr::from)
+            }
+            LshTypes::SrpSqlMem(l) => {
+                Shared::write(l, |lsh| lsh.hash_tables.as_mut().unwrap().to_db(db_path))
+                    .map_err(PyLshErr::from)
+            }
+            LshTypes::Empty => panic!("base not initialized"),
+            _ => Err(PyLshErr::NotSqliteMem("to_db")),
+        })?;
         Ok(())
     }
 
-    fn _init_transaction(&mut self) -> IntResult<()> {
-        match &mut self.lsh {
-            LshTypes::L2(lsh) => lsh.init_transaction()?,
-            LshTypes::Mips(lsh) => lsh.init_transaction()?,
-            LshTypes::Srp(lsh) => lsh.init_transaction()?,
-            _ => panic!("base not initialized"),
-        };
+    fn dump(&self, py: Python, path: &str) -> IntResult<()> {
+        py.allow_threads(|| call_lsh_mem!(self, "dump", read, lsh => lsh.dump(path)))?;
         Ok(())
     }
 
-    fn _index(&self) -> IntResult<()> {
-        match &self.lsh {
-            LshTypes::L2(lsh) => lsh.hash_tables.as_ref().unwrap().index_hash()?,
-            LshTypes::Mips(lsh) => lsh.hash_tables.as_ref().unwrap().index_hash()?,
-            LshTypes::Srp(lsh) => lsh.hash_tables.as_ref().unwrap().index_hash()?,
-            _ => panic!("base not initialized"),
-        };
+    fn load(&self, py: Python, path: &str) -> IntResult<()> {
+        py.allow_threads(|| call_lsh_mem!(self, "load", write, lsh => lsh.load(path)))?;
         Ok(())
     }
 
-    fn _to_mem(&mut self) -> IntResult<()> {
-        match &mut self.lsh {
-            LshTypes::L2(lsh) => lsh.hash_tables.as_mut().unwrap().to_mem()?,
-            LshTypes::Mips(lsh) => lsh.hash_tables.as_mut().unwrap().to_mem()?,
-            LshTypes::Srp(lsh) => lsh.hash_tables.as_mut().unwrap().to_mem()?,
-            _ => panic!("base not initialized"),
-        };
+    fn dump_bytes(&self, py: Python) -> IntResult<Vec<u8>> {
+        let buf =
+            py.allow_threads(|| call_lsh_mem!(self, "dump_bytes", read, lsh => lsh.dump_bytes()))?;
+        Ok(buf)
+    }
+
+    fn load_bytes(&self, py: Python, buf: &[u8]) -> IntResult<()> {
+        py.allow_threads(|| call_lsh_mem!(self, "load_bytes", write, lsh => lsh.load_bytes(buf)))?;
         Ok(())
     }
+
+    fn multi_probe(&self, py: Python, budget: usize) {
+        py.allow_threads(|| call_lsh_types!(self, write, lsh => { lsh.multi_probe(budget); }))
+    }
+
+    fn base(&self, py: Python) {
+        py.allow_threads(|| call_lsh_types!(self, write, lsh => { lsh.base(); }))
+    }
+}
+
+/// The element types supported by the Python classes.
+enum DTypes {
+    F32(LshTypes<f32>),
+    F64(LshTypes<f64>),
+}
+
+macro_rules! call_dtypes {
+    ($lsh:expr, $inner:ident => $body:expr) => {
+        match $lsh {
+            DTypes::F32($inner) => $body,
+            DTypes::F64($inner) => $body,
+        }
+    };
+}
+
+/// Create `LshTypes` in the element type given by the numpy dtype name. `$N` is an alias of the
+/// element type in `$body`.
+macro_rules! with_dtype {
+    ($dtype:expr, $N:ident => $body:expr) => {
+        match $dtype {
+            "float32" => {
+                type $N = f32;
+                DTypes::F32($body)
+            }
+            "float64" => {
+                type $N = f64;
+                DTypes::F64($body)
+            }
+            dtype => {
+                return Err(ValueError::py_err(format!(
+                    "dtype {} is not supported, use float32 or float64",
+                    dtype
+                )))
+            }
+        }
+    };
+}
+
+/// Cast a parameter to the element type of the hash functions.
+fn cast<N: NumCast>(x: f64) -> N {
+    N::from(x).unwrap()
+}
+
+/// Create the builder of an LSH with the parameters that all Python classes share.
+fn builder<N, T, H>(
+    n_projections: usize,
+    n_hash_tables: usize,
+    dim: usize,
+    seed: u64,
+    db_path: &str,
+) -> lsh_rs::LSH<N, T, H>
+where
+    N: Numeric + DeserializeOwned,
+    T: lsh_rs::HashTables<N>,
+    H: lsh_rs::VecHash<N>,
+{
+    let mut lsh = lsh_rs::LSH::new(n_projections, n_hash_tables, dim);
+    lsh.seed(seed).only_index().set_database_file(db_path);
+    lsh
+}
+
+#[pyclass]
+struct Base {
+    lsh: DTypes,
 }
 
 #[pymethods]
@@ -250,79 +482,98 @@ impl Base {
     #[new]
     fn new() -> Self {
         Base {
-            lsh: LshTypes::Empty,
+            lsh: DTypes::F32(LshTypes::Empty),
         }
     }
 
-    fn store_vec(&mut self, v: Vec<f32>) -> PyResult<()> {
-        self._store_vec(v)?;
-        Ok(())
+    fn store_vec(&self, py: Python, v: &PyAny) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.store_vec(py, v))
     }
 
-    fn store_vecs(&mut self, vs: &PyArray2<f32>) -> PyResult<()> {
-        let gil_guard = Python::acquire_gil();
-        let py = gil_guard.python();
-        py.allow_threads(move || self._store_vecs(vs))?;
-        Ok(())
+    fn store_vecs(&self, py: Python, vs: &PyAny) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.store_vecs(py, vs))
     }
 
-    fn query_bucket(&self, v: Vec<f32>) -> PyResult<Vec<Vec<f32>>> {
-        let q = self._query_bucket(v)?;
-        Ok(q)
+    fn query_bucket(&self, py: Python, v: &PyAny) -> PyResult<PyObject> {
+        call_dtypes!(&self.lsh, lsh => lsh.query_bucket(py, v))
     }
 
-    fn query_bucket_idx(&self, v: Vec<f32>) -> PyResult<Vec<u32>> {
-        let q = self._query_bucket_idx(v)?;
-        Ok(q)
+    fn query_bucket_idx(&self, py: Python, v: &PyAny) -> PyResult<Vec<u32>> {
+        call_dtypes!(&self.lsh, lsh => lsh.query_bucket_idx(py, v))
     }
 
-    fn query_bucket_idx_batch(&self, vs: &PyArray2<f32>) -> PyResult<Vec<Vec<u32>>> {
-        let q = self._query_batch(vs)?;
-        Ok(q)
+    fn query_bucket_idx_batch(&self, py: Python, vs: &PyAny) -> PyResult<Vec<Vec<u32>>> {
+        call_dtypes!(&self.lsh, lsh => lsh.query_batch(py, vs))
+    }
+
+    fn delete_vec(&self, py: Python, v: &PyAny) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.delete_vec(py, v))
+    }
+
+    fn describe(&self, py: Python) -> PyResult<String> {
+        call_dtypes!(&self.lsh, lsh => lsh.describe(py))
     }
 
-    fn delete_vec(&mut self, v: Vec<f32>) -> PyResult<()> {
-        self._delete_vec(v)?;
+    fn commit(&self, py: Python) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.commit(py))?;
         Ok(())
     }
 
-    fn describe(&mut self) -> PyResult<String> {
-        let s = self._describe()?;
-        Ok(s)
+    fn init_transaction(&self, py: Python) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.init_transaction(py))?;
+        Ok(())
+    }
+
+    fn index(&self, py: Python) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.index(py))?;
+        Ok(())
     }
 
-    fn commit(&mut self) -> PyResult<()> {
-        self._commit()?;
+    fn to_mem(&self, py: Python) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.to_mem(py))?;
         Ok(())
     }
 
-    fn init_transaction(&mut self) -> PyResult<()> {
-        self._init_transaction()?;
+    fn to_db(&self, py: Python, db_path: &str) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.to_db(py, db_path))?;
         Ok(())
     }
 
-    fn index(&self) -> PyResult<()> {
-        self._index()?;
+    fn fit(&self, py: Python, vs: &PyAny) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.fit(py, vs))
+    }
+
+    fn dump(&self, py: Python, path: &str) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.dump(py, path))?;
         Ok(())
     }
 
-    fn to_mem(&mut self) -> PyResult<()> {
-        self._to_mem()?;
+    fn load(&self, py: Python, path: &str) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.load(py, path))?;
         Ok(())
     }
 
-    fn increase_storage(&mut self, upper_bound: usize) -> PyResult<()> {
-        self._increase_storage(upper_bound)?;
+    fn dump_bytes(&self, py: Python) -> PyResult<PyObject> {
+        let buf = call_dtypes!(&self.lsh, lsh => lsh.dump_bytes(py))?;
+        Ok(PyBytes::new(py, &buf).into())
+    }
+
+    fn load_bytes(&self, py: Python, buf: &PyBytes) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.load_bytes(py, buf.as_bytes()))?;
         Ok(())
     }
 
-    fn multi_probe(&mut self, budget: usize) -> PyResult<()> {
-        call_lsh_types!(&mut self.lsh, multi_probe, budget, ;);
+    fn increase_storage(&self, py: Python, upper_bound: usize) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.increase_storage(py, upper_bound))
+    }
+
+    fn multi_probe(&self, py: Python, budget: usize) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.multi_probe(py, budget));
         Ok(())
     }
 
-    fn base(&mut self) -> PyResult<()> {
-        call_lsh_types!(&mut self.lsh, base, ;);
+    fn base(&self, py: Python) -> PyResult<()> {
+        call_dtypes!(&self.lsh, lsh => lsh.base(py));
         Ok(())
     }
 }
@@ -337,26 +588,17 @@ impl LshL2 {
         n_projections: usize,
         n_hash_tables: usize,
         dim: usize,
-        r: f32,
+        r: f64,
         seed: u64,
         db_path: String,
+        dtype: &str,
     ) -> PyResult<(Self, Base)> {
-        let r = LshSql::<f32, _>::new(n_projections, n_hash_tables, dim)
-            .seed(seed)
-            .only_index()
-            .set_database_file(&db_path)
-            .l2(r);
-
-        let lsh = match r {
-            Ok(lsh) => lsh,
-            Err(e) => return Err(RuntimeError::py_err(format!("{}", e))),
-        };
-        Ok((
-            LshL2 {},
-            Base {
-                lsh: LshTypes::L2(lsh),
-            },
-        ))
+        let lsh = with_dtype!(dtype, N => LshTypes::L2(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .l2(cast(r))
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshL2 {}, Base { lsh }))
     }
 }
 
@@ -370,26 +612,41 @@ impl LshL2Mem {
         n_projections: usize,
         n_hash_tables: usize,
         dim: usize,
-        r: f32,
+        r: f64,
+        seed: u64,
+        db_path: String,
+        dtype: &str,
+    ) -> PyResult<(Self, Base)> {
+        let lsh = with_dtype!(dtype, N => LshTypes::L2Mem(RwLock::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .l2(cast(r))
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshL2Mem {}, Base { lsh }))
+    }
+}
+
+#[pyclass(extends=Base)]
+struct LshL2SqlMem {}
+
+#[pymethods]
+impl LshL2SqlMem {
+    #[new]
+    fn new(
+        n_projections: usize,
+        n_hash_tables: usize,
+        dim: usize,
+        r: f64,
         seed: u64,
         db_path: String,
+        dtype: &str,
     ) -> PyResult<(Self, Base)> {
-        let r = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim)
-            .seed(seed)
-            .only_index()
-            .set_database_file(&db_path)
-            .l2(r);
-
-        let lsh = match r {
-            Ok(lsh) => lsh,
-            Err(e) => return Err(RuntimeError::py_err(format!("{}", e))),
-        };
-        Ok((
-            LshL2Mem {},
-            Base {
-                lsh: LshTypes::L2Mem(lsh),
-            },
-        ))
+        let lsh = with_dtype!(dtype, N => LshTypes::L2SqlMem(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .l2(cast(r))
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshL2SqlMem {}, Base { lsh }))
     }
 }
 
@@ -403,30 +660,74 @@ impl LshMips {
         n_projections: usize,
         n_hash_tables: usize,
         dim: usize,
-        r: f32,
-        U: f32,
+        r: f64,
+        U: f64,
+        m: usize,
+        seed: u64,
+        db_path: String,
+        dtype: &str,
+    ) -> PyResult<(Self, Base)> {
+        let lsh = with_dtype!(dtype, N => LshTypes::Mips(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .mips(cast(r), cast(U), m)
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshMips {}, Base { lsh }))
+    }
+}
+
+#[pyclass(extends=Base)]
+struct LshMipsMem {}
+
+#[pymethods]
+impl LshMipsMem {
+    #[new]
+    fn new(
+        n_projections: usize,
+        n_hash_tables: usize,
+        dim: usize,
+        r: f64,
+        U: f64,
         m: usize,
         seed: u64,
         db_path: String,
+        dtype: &str,
     ) -> PyResult<(Self, Base)> {
-        let r = LshSql::<f32, _>::new(n_projections, n_hash_tables, dim)
-            .seed(seed)
-            .only_index()
-            .set_database_file(&db_path)
-            .mips(r, U, m);
-        let lsh = match r {
-            Ok(lsh) => lsh,
-            Err(e) => return Err(RuntimeError::py_err(format!("{}", e))),
-        };
-
-        Ok((
-            LshMips {},
-            Base {
-                lsh: LshTypes::Mips(lsh),
-            },
-        ))
+        let lsh = with_dtype!(dtype, N => LshTypes::MipsMem(RwLock::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .mips(cast(r), cast(U), m)
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshMipsMem {}, Base { lsh }))
     }
 }
+
+#[pyclass(extends=Base)]
+struct LshMipsSqlMem {}
+
+#[pymethods]
+impl LshMipsSqlMem {
+    #[new]
+    fn new(
+        n_projections: usize,
+        n_hash_tables: usize,
+        dim: usize,
+        r: f64,
+        U: f64,
+        m: usize,
+        seed: u64,
+        db_path: String,
+        dtype: &str,
+    ) -> PyResult<(Self, Base)> {
+        let lsh = with_dtype!(dtype, N => LshTypes::MipsSqlMem(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .mips(cast(r), cast(U), m)
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshMipsSqlMem {}, Base { lsh }))
+    }
+}
+
 #[pyclass(extends=Base)]
 struct LshSrp {}
 
@@ -439,22 +740,14 @@ impl LshSrp {
         dim: usize,
         seed: u64,
         db_path: String,
+        dtype: &str,
     ) -> PyResult<(Self, Base)> {
-        let r = LshSql::<f32, _>::new(n_projections, n_hash_tables, dim)
-            .seed(seed)
-            .only_index()
-            .set_database_file(&db_path)
-            .srp();
-        let lsh = match r {
-            Ok(lsh) => lsh,
-            Err(e) => return Err(RuntimeError::py_err(format!("{}", e))),
-        };
-        Ok((
-            LshSrp {},
-            Base {
-                lsh: LshTypes::Srp(lsh),
-            },
-        ))
+        let lsh = with_dtype!(dtype, N => LshTypes::Srp(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .srp()
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshSrp {}, Base { lsh }))
     }
 }
 
@@ -470,21 +763,36 @@ impl LshSrpMem {
         dim: usize,
         seed: u64,
         db_path: String,
+        dtype: &str,
+    ) -> PyResult<(Self, Base)> {
+        let lsh = with_dtype!(dtype, N => LshTypes::SrpMem(RwLock::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .srp()
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshSrpMem {}, Base { lsh }))
+    }
+}
+
+#[pyclass(extends=Base)]
+struct LshSrpSqlMem {}
+
+#[pymethods]
+impl LshSrpSqlMem {
+    #[new]
+    fn new(
+        n_projections: usize,
+        n_hash_tables: usize,
+        dim: usize,
+        seed: u64,
+        db_path: String,
+        dtype: &str,
     ) -> PyResult<(Self, Base)> {
-        let r = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim)
-            .seed(seed)
-            .only_index()
-            .set_database_file(&db_path)
-            .srp();
-        let lsh = match r {
-            Ok(lsh) => lsh,
-            Err(e) => return Err(RuntimeError::py_err(format!("{}", e))),
-        };
-        Ok((
-            LshSrpMem {},
-            Base {
-                lsh: LshTypes::SrpMem(lsh),
-            },
-        ))
+        let lsh = with_dtype!(dtype, N => LshTypes::SrpSqlMem(Mutex::new(
+            builder::<N, _, _>(n_projections, n_hash_tables, dim, seed, &db_path)
+                .srp()
+                .map_err(PyLshErr::from)?
+        )));
+        Ok((LshSrpSqlMem {}, Base { lsh }))
     }
 }
@@ -1,4 +1,7 @@
-from floky import L2, SRP, QueryResult
+from floky import L2, SRP, MIPS, QueryResult
+import os
+import pickle
+from concurrent.futures import ThreadPoolExecutor
 import numpy as np
 from scipy.spatial.distance import cdist
 from typing import List
@@ -52,3 +55,107 @@ def test_srp():
     query = np.random.randn(n, dim)
     results = lsh.predict(query)
     assert get_mean_collisions(results) == 36.21
+
+
+def test_dtype():
+    np.random.seed(1)
+    arr = np.random.randn(1000, 10)
+    for dtype in ["float32", "float64"]:
+        lsh = L2(n_projections=10, n_hash_tables=3, log=False, seed=1, dtype=dtype)
+        lsh.fit(arr)
+        assert lsh.data.dtype == dtype
+        results = lsh.predict(arr[:5], top_k=1)
+        assert [r.index[0] for r in results] == list(range(5))
+
+
+def test_mips(tmp_path):
+    np.random.seed(1)
+    arr = np.random.randn(1000, 10)
+    for backend in ["mem", "sqlite", "sqlite-mem"]:
+        lsh = MIPS(
+            n_projections=5,
+            n_hash_tables=5,
+            log=False,
+            seed=1,
+            backend=backend,
+            db_path=str(tmp_path / "mips.db3"),
+        )
+        lsh.fit(arr)
+        results = lsh.predict(arr[:5], top_k=3)
+        for r, q in zip(results, arr[:5]):
+            ip = arr[r.index] @ q
+            assert np.allclose(r.distances, -ip, atol=1e-4)
+            assert np.all(np.diff(ip) <= 0)
+
+
+def test_backends(tmp_path):
+    np.random.seed(1)
+    arr = np.random.randn(1000, 10)
+    query = np.random.randn(20, 10)
+    results = {}
+    for backend in ["mem", "sqlite", "sqlite-mem"]:
+        lsh = L2(
+            n_projections=10,
+            n_hash_tables=3,
+            log=False,
+            seed=1,
+            backend=backend,
+            db_path=str(tmp_path / "lsh.db3"),
+            multi_probe=8,
+        )
+        lsh.fit(arr)
+        results[backend] = [list(r.index) for r in lsh.predict(query)]
+    assert results["mem"] == results["sqlite"] == results["sqlite-mem"]
+
+    db_path = tmp_path / "copy.db3"
+    lsh.to_db(str(db_path))
+    assert os.path.exists(db_path)
+
+
+def test_pickle(tmp_path):
+    np.random.seed(1)
+    arr = np.random.randn(1000, 10)
+    query = np.random.randn(20, 10)
+    for backend in ["mem", "sqlite", "sqlite-mem"]:
+        lsh = MIPS(
+            n_projections=5,
+            n_hash_tables=5,
+            log=False,
+            seed=1,
+            backend=backend,
+            db_path=str(tmp_path / "lsh.db3"),
+        )
+        lsh.fit(arr)
+        expected = [list(r.index) for r in lsh.predict(query)]
+
+        loaded = pickle.loads(pickle.dumps(lsh))
+        assert [list(r.index) for r in loaded.predict(query)] == expected
+
+        path = str(tmp_path / "lsh.pickle")
+        lsh.dump(path)
+        loaded = MIPS.load(path)
+        assert [list(r.index) for r in loaded.predict(query)] == expected
+
+
+def test_threads(tmp_path):
+    np.random.seed(1)
+    arr = np.random.randn(1000, 10)
+    query = np.random.randn(100, 10)
+    for backend in ["mem", "sqlite"]:
+        lsh = L2(
+            n_projections=10,
+            n_hash_tables=3,
+            log=False,
+            seed=1,
+            backend=backend,
+            db_path=str(tmp_path / "lsh.db3"),
+        )
+        lsh.fit(arr)
+        expected = [lsh.query_bucket_idx(q) for q in query]
+        with ThreadPoolExecutor(4) as pool:
+            stored = pool.map(lsh.store_vec, np.random.randn(100, 10))
+            results = list(pool.map(lsh.query_bucket_idx, query))
+            list(stored)
+        # vectors stored concurrently get new ids, the original ids are still found.
+        for r, e in zip(results, expected):
+            assert set(e) <= set(r)
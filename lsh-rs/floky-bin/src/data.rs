@@ -0,0 +1,349 @@
+use anyhow::{anyhow, bail, Context, Result};
+use lsh_rs::{
+    io::read_vecs,
+    npy::{read_npy, read_npz},
+};
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Text formats that are read line by line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Text format of a data file. `None` for the binary formats. Stdin (`-`) and unknown
+    /// extensions are read as csv.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("fvecs") | Some("bvecs") | Some("npy") | Some("npz") => None,
+            Some(ext) => Some(ext.parse().unwrap_or(Format::Csv)),
+            None => Some(Format::Csv),
+        }
+    }
+
+    fn separator(self) -> char {
+        match self {
+            Format::Tsv => '\t',
+            _ => ',',
+        }
+    }
+}
+
+/// Id and metadata of a data point. Written to the records file of an index.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordInfo {
+    pub id: Option<String>,
+    pub metadata: Option<Value>,
+}
+
+/// A vector with its optional id and metadata.
+#[derive(Debug)]
+pub struct Record {
+    pub vector: Vec<f32>,
+    pub info: RecordInfo,
+}
+
+/// A json line is either an array of values or an object with a `vector` field.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRecord {
+    Vector(Vec<f32>),
+    Record {
+        vector: Vec<f32>,
+        #[serde(default)]
+        id: Option<Value>,
+        #[serde(default)]
+        metadata: Option<Value>,
+    },
+}
+
+/// Streaming reader of csv, tsv and json lines files. Vectors are read in chunks, so that the
+/// input does not need to fit in memory.
+pub struct RecordReader<R> {
+    lines: Lines<R>,
+    name: String,
+    format: Format,
+    id_column: Option<usize>,
+    strict: bool,
+    dim: Option<usize>,
+    line: usize,
+    /// Number of malformed lines that were skipped.
+    pub n_skipped: usize,
+}
+
+impl RecordReader<Box<dyn BufRead>> {
+    /// Open a data file, or stdin if `path` is `-`.
+    pub fn open(
+        path: &Path,
+        format: Format,
+        id_column: Option<usize>,
+        strict: bool,
+    ) -> Result<Self> {
+        let reader: Box<dyn BufRead> = if path == Path::new("-") {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            let f =
+                File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+            Box::new(BufReader::new(f))
+        };
+        let name = if path == Path::new("-") {
+            "<stdin>".to_string()
+        } else {
+            path.display().to_string()
+        };
+        Ok(RecordReader::new(reader, name, format, id_column, strict))
+    }
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// # Arguments
+    /// * `name` - Name of the input used in error messages.
+    /// * `id_column` - Column of a csv or tsv line that holds the id instead of a value.
+    /// * `strict` - Fail on the first malformed line instead of skipping it.
+    pub fn new(
+        reader: R,
+        name: String,
+        format: Format,
+        id_column: Option<usize>,
+        strict: bool,
+    ) -> Self {
+        RecordReader {
+            lines: reader.lines(),
+            name,
+            format,
+            id_column,
+            strict,
+            dim: None,
+            line: 0,
+            n_skipped: 0,
+        }
+    }
+
+    fn parse_line(&self, line: &str) -> Result<Record> {
+        let record = match self.format {
+            Format::Jsonl => match serde_json::from_str(line)? {
+                JsonRecord::Vector(vector) => Record {
+                    vector,
+                    info: RecordInfo::default(),
+                },
+                JsonRecord::Record {
+                    vector,
+                    id,
+                    metadata,
+                } => {
+                    let id = id.map(|id| match id {
+                        Value::String(s) => s,
+                        id => id.to_string(),
+                    });
+                    Record {
+                        vector,
+                        info: RecordInfo { id, metadata },
+                    }
+                }
+            },
+            Format::Csv | Format::Tsv => {
+                let mut id = None;
+                let mut vector = vec![];
+                for (i, s) in line.split(self.format.separator()).enumerate() {
+                    let s = s.trim();
+                    if Some(i) == self.id_column {
+                        id = Some(s.to_string())
+                    } else {
+                        vector.push(
+                            s.parse()
+                                .map_err(|_| anyhow!("could not parse value {:?}", s))?,
+                        )
+                    }
+                }
+                if self.id_column.is_some() && id.is_none() {
+                    bail!("missing id column")
+                }
+                Record {
+                    vector,
+                    info: RecordInfo { id, metadata: None },
+                }
+            }
+        };
+        match self.dim {
+            Some(dim) if dim != record.vector.len() => {
+                bail!("expected {} values, got {}", dim, record.vector.len())
+            }
+            _ if record.vector.is_empty() => bail!("empty vector"),
+            _ => Ok(record),
+        }
+    }
+
+    /// Read the next record. Malformed lines are reported on stderr and skipped, unless the
+    /// reader is strict. Returns `None` at the end of the input.
+    pub fn read_record(&mut self) -> Result<Option<Record>> {
+        while let Some(line) = self.lines.next() {
+            let line = line?;
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match self.parse_line(&line) {
+                Ok(record) => {
+                    self.dim = Some(record.vector.len());
+                    return Ok(Some(record));
+                }
+                Err(e) if self.strict => bail!("{}:{}: {}", self.name, self.line, e),
+                Err(e) => {
+                    eprintln!("{}:{}: skipped: {}", self.name, self.line, e);
+                    self.n_skipped += 1;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read up to `n` records. Returns an empty chunk at the end of the input.
+    pub fn read_chunk(&mut self, n: usize) -> Result<Vec<Record>> {
+        let mut chunk = Vec::with_capacity(n.min(1 << 16));
+        while chunk.len() < n {
+            match self.read_record()? {
+                Some(record) => chunk.push(record),
+                None => break,
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+/// Read a text file completely. Malformed lines are an error.
+pub fn read_text<P>(path: P, format: Format) -> Result<Vec<Vec<f32>>>
+where
+    P: AsRef<Path>,
+{
+    let mut reader = RecordReader::open(path.as_ref(), format, None, true)?;
+    let mut vs = vec![];
+    while let Some(record) = reader.read_record()? {
+        vs.push(record.vector)
+    }
+    Ok(vs)
+}
+
+fn rows(arr: Array2<f32>) -> Vec<Vec<f32>> {
+    arr.outer_iter().map(|v| v.to_vec()).collect()
+}
+
+/// Read a data file. `.fvecs` and `.bvecs` files are read as benchmark vecs files, `.npy` and
+/// `.npz` files as NumPy arrays, `.tsv` and `.jsonl` files as text and all other files as csv.
+pub fn read_vectors<P>(path: P) -> Result<Vec<Vec<f32>>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let vs = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("fvecs") => read_vecs::<f32, _>(path)?,
+        Some("bvecs") => read_vecs::<u8, _>(path)?
+            .into_iter()
+            .map(|v| v.into_iter().map(f32::from).collect())
+            .collect(),
+        Some("npy") => rows(read_npy(path)?),
+        Some("npz") => {
+            let mut arrays = read_npz(path)?;
+            if arrays.len() != 1 {
+                bail!("{} should contain a single array", path.display())
+            }
+            rows(arrays.pop().unwrap().1)
+        }
+        _ => read_text(path, Format::from_path(path).unwrap_or(Format::Csv))?,
+    };
+    if vs.is_empty() {
+        bail!("{} contains no vectors", path.display())
+    }
+    Ok(vs)
+}
+
+/// Read the records file of an index.
+pub fn read_records<P>(path: P) -> Result<Vec<RecordInfo>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let f = File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+    BufReader::new(f)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn reader(input: &str, format: Format, id_column: Option<usize>) -> RecordReader<Cursor<&str>> {
+        RecordReader::new(
+            Cursor::new(input),
+            "test".to_string(),
+            format,
+            id_column,
+            false,
+        )
+    }
+
+    #[test]
+    fn test_read_chunks_skips_malformed_lines() {
+        let input = "1,2,3\n4,x,6\n\n7,8\n9,10,11\n12,13,14\n";
+        let mut r = reader(input, Format::Csv, None);
+        let chunk = r.read_chunk(2).unwrap();
+        assert_eq!(chunk.len(), 2);
+        assert_eq!(chunk[1].vector, vec![9., 10., 11.]);
+        assert_eq!(r.n_skipped, 2);
+        assert_eq!(r.read_chunk(2).unwrap().len(), 1);
+        assert!(r.read_chunk(2).unwrap().is_empty());
+
+        let mut r = RecordReader::new(Cursor::new(input), "test".into(), Format::Csv, None, true);
+        let err = r.read_chunk(10).unwrap_err();
+        assert!(err.to_string().starts_with("test:2:"));
+    }
+
+    #[test]
+    fn test_read_tsv_with_id_column() {
+        let mut r = reader("a\t1\t2\nb\t3\t4\n", Format::Tsv, Some(0));
+        let chunk = r.read_chunk(10).unwrap();
+        assert_eq!(chunk[1].vector, vec![3., 4.]);
+        assert_eq!(chunk[1].info.id.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_read_json_lines() {
+        let input = r#"[1, 2]
+{"vector": [3, 4], "id": 7, "metadata": {"label": "cat"}}
+{"vector": [5, 6], "id": "x"}
+{"values": [5, 6]}
+"#;
+        let mut r = reader(input, Format::Jsonl, None);
+        let chunk = r.read_chunk(10).unwrap();
+        assert_eq!(chunk.len(), 3);
+        assert!(chunk[0].info.id.is_none());
+        assert_eq!(chunk[1].info.id.as_deref(), Some("7"));
+        assert_eq!(chunk[1].info.metadata.as_ref().unwrap()["label"], "cat");
+        assert_eq!(chunk[2].info.id.as_deref(), Some("x"));
+        assert_eq!(r.n_skipped, 1);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::data::read_vectors;
+use anyhow::{anyhow, bail, Context, Result};
+use lsh_rs::{
+    eval::{self, Metric},
+    io::read_fvecs,
+    HashTables, LshMem, LshSql, LshSqlMem, SignRandomProjections, VecHash, L2, LSH, MIPS,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Hash family of an index.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Family {
+    Srp,
+    L2,
+    Mips,
+}
+
+impl FromStr for Family {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "srp" => Ok(Family::Srp),
+            "l2" => Ok(Family::L2),
+            "mips" => Ok(Family::Mips),
+            _ => Err(format!("unknown hash family: {}", s)),
+        }
+    }
+}
+
+impl Family {
+    /// Distance used to re-rank the candidates.
+    pub fn metric(self) -> Metric {
+        match self {
+            Family::Srp => Metric::Cosine,
+            Family::L2 => Metric::L2,
+            Family::Mips => Metric::InnerProduct,
+        }
+    }
+}
+
+/// Storage backend of an index.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// In memory hash tables, serialized with bincode.
+    Mem,
+    /// SQLite database on disk.
+    Sqlite,
+    /// SQLite database that is built in memory and written to disk when done.
+    SqliteMem,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "mem" => Ok(Backend::Mem),
+            "sqlite" => Ok(Backend::Sqlite),
+            "sqlite-mem" => Ok(Backend::SqliteMem),
+            _ => Err(format!("unknown backend: {}", s)),
+        }
+    }
+}
+
+/// Parameters needed to reopen an index. Stored next to the index as `<index>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexMeta {
+    pub family: Family,
+    pub backend: Backend,
+    pub n_projections: usize,
+    pub n_hash_tables: usize,
+    pub dim: usize,
+    pub seed: u64,
+    pub r: f32,
+    pub U: f32,
+    pub m: usize,
+    pub n_points: usize,
+    /// Data file the index is built from. Used to compute exact distances.
+    pub data: PathBuf,
+    /// File with the id and metadata of every data point, one json line per data point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records: Option<PathBuf>,
+}
+
+/// An opened index of any family and backend.
+pub enum Index {
+    SrpMem(LshMem<f32, SignRandomProjections<f32>>),
+    SrpSql(LshSql<f32, SignRandomProjections<f32>>),
+    SrpSqlMem(LshSqlMem<f32, SignRandomProjections<f32>>),
+    L2Mem(LshMem<f32, L2<f32>>),
+    L2Sql(LshSql<f32, L2<f32>>),
+    L2SqlMem(LshSqlMem<f32, L2<f32>>),
+    MipsMem(LshMem<f32, MIPS<f32>>),
+}
+
+/// Call the same expression on the LSH of any `Index` variant.
+macro_rules! call_index {
+    ($index:expr, $lsh:ident => $body:expr) => {
+        match $index {
+            Index::SrpMem($lsh) => $body,
+            Index::SrpSql($lsh) => $body,
+            Index::SrpSqlMem($lsh) => $body,
+            Index::L2Mem($lsh) => $body,
+            Index::L2Sql($lsh) => $body,
+            Index::L2SqlMem($lsh) => $body,
+            Index::MipsMem($lsh) => $body,
+        }
+    };
+}
+
+/// Data point found by a query.
+#[derive(Debug, Serialize)]
+pub struct Neighbor {
+    pub id: u32,
+    pub distance: f32,
+    /// Id of the data point in the input records.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Sort the candidate `ids` by their exact distance to `q` and keep the `top_k` closest.
+pub fn rerank(
+    q: &[f32],
+    ids: &[u32],
+    vs: &[Vec<f32>],
+    top_k: usize,
+    metric: Metric,
+) -> Result<Vec<Neighbor>> {
+    if let Some(id) = ids.iter().find(|&&id| id as usize >= vs.len()) {
+        return Err(anyhow!("id {} is not in the data file", id));
+    }
+    let get = |id| vs.get(id as usize).map(Vec::as_slice);
+    let neighbors = eval::rerank_with(q, ids, get, top_k, metric)
+        .into_iter()
+        .map(|(id, distance)| Neighbor {
+            id,
+            distance,
+            key: None,
+            metadata: None,
+        })
+        .collect();
+    Ok(neighbors)
+}
+
+fn builder<T, H>(meta: &IndexMeta, db_path: &str, multi_probe: Option<usize>) -> LSH<f32, T, H>
+where
+    T: HashTables<f32>,
+    H: VecHash<f32>,
+{
+    let mut lsh = LSH::new(meta.n_projections, meta.n_hash_tables, meta.dim);
+    lsh.seed(meta.seed).only_index().set_database_file(db_path);
+    if let Some(budget) = multi_probe {
+        lsh.multi_probe(budget);
+    }
+    lsh
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("path is not valid utf-8: {}", path.display()))
+}
+
+/// Path of a file stored next to the index, i.e. `<index>.json`.
+pub fn sibling(index: &Path, suffix: &str) -> PathBuf {
+    let mut p = index.as_os_str().to_owned();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+impl IndexMeta {
+    fn path(index: &Path) -> PathBuf {
+        sibling(index, ".json")
+    }
+
+    /// Read the parameters of the index at `index`.
+    pub fn read(index: &Path) -> Result<Self> {
+        let path = Self::path(index);
+        let f = File::open(&path)
+            .with_context(|| format!("could not open index metadata {}", path.display()))?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Vectors inserted by the server, stored next to the index as `<index>.inserted.fvecs`.
+    pub fn inserted_path(index: &Path) -> PathBuf {
+        sibling(index, ".inserted.fvecs")
+    }
+
+    /// Ids deleted by the server, one per line, stored next to the index as `<index>.deleted`.
+    pub fn deleted_path(index: &Path) -> PathBuf {
+        sibling(index, ".deleted")
+    }
+
+    /// Read the data points of the index at `index`. The vectors inserted by the server follow
+    /// the vectors of the data file, so the position of a vector is its id.
+    pub fn read_vectors(&self, index: &Path) -> Result<Vec<Vec<f32>>> {
+        let mut vs = read_vectors(&self.data)?;
+        let inserted = Self::inserted_path(index);
+        if inserted.exists() {
+            vs.extend(read_fvecs(&inserted)?);
+        }
+        Ok(vs)
+    }
+
+    /// Read the ids deleted from the index at `index`.
+    pub fn read_deleted(index: &Path) -> Result<HashSet<u32>> {
+        let path = Self::deleted_path(index);
+        if !path.exists() {
+            return Ok(HashSet::new());
+        }
+        BufReader::new(File::open(&path)?)
+            .lines()
+            .map(|line| {
+                let line = line?;
+                line.trim()
+                    .parse()
+                    .with_context(|| format!("invalid id {} in {}", line, path.display()))
+            })
+            .collect()
+    }
+
+    pub fn write(&self, index: &Path) -> Result<()> {
+        let f = File::create(Self::path(index))?;
+        serde_json::to_writer_pretty(f, self)?;
+        Ok(())
+    }
+
+    /// Create a new empty index at `index`.
+    pub fn create(&self, index: &Path) -> Result<Index> {
+        let db_path = path_str(index)?;
+        let idx = match (self.family, self.backend) {
+            (Family::Srp, Backend::Mem) => Index::SrpMem(builder(self, db_path, None).srp()?),
+            (Family::Srp, Backend::Sqlite) => Index::SrpSql(builder(self, db_path, None).srp()?),
+            (Family::Srp, Backend::SqliteMem) => {
+                Index::SrpSqlMem(builder(self, db_path, None).srp()?)
+            }
+            (Family::L2, Backend::Mem) => Index::L2Mem(builder(self, db_path, None).l2(self.r)?),
+            (Family::L2, Backend::Sqlite) => Index::L2Sql(builder(self, db_path, None).l2(self.r)?),
+            (Family::L2, Backend::SqliteMem) => {
+                Index::L2SqlMem(builder(self, db_path, None).l2(self.r)?)
+            }
+            (Family::Mips, Backend::Mem) => {
+                Index::MipsMem(builder(self, db_path, None).mips(self.r, self.U, self.m)?)
+            }
+            // the SQLite backends store the hashers before they are fitted.
+            (Family::Mips, _) => bail!("the mips family is only supported by the mem backend"),
+        };
+        Ok(idx)
+    }
+
+    /// Open an existing index at `index`. A `sqlite-mem` index is loaded in memory.
+    pub fn open(&self, index: &Path, multi_probe: Option<usize>) -> Result<Index> {
+        if !index.exists() {
+            bail!("index {} does not exist", index.display())
+        }
+        let db_path = path_str(index)?;
+        let mut idx = match (self.family, self.backend) {
+            (Family::Srp, Backend::Mem) => {
+                let mut lsh = builder(self, db_path, multi_probe).srp()?;
+                lsh.load(index)?;
+                Index::SrpMem(lsh)
+            }
+            (Family::Srp, _) => Index::SrpSql(builder(self, db_path, multi_probe).srp()?),
+            (Family::L2, Backend::Mem) => {
+                let mut lsh = builder(self, db_path, multi_probe).l2(self.r)?;
+                lsh.load(index)?;
+                Index::L2Mem(lsh)
+            }
+            (Family::L2, _) => Index::L2Sql(builder(self, db_path, multi_probe).l2(self.r)?),
+            (Family::Mips, Backend::Mem) => {
+                let mut lsh = builder(self, db_path, multi_probe).mips(self.r, self.U, self.m)?;
+                lsh.load(index)?;
+                Index::MipsMem(lsh)
+            }
+            (Family::Mips, _) => bail!("the mips family is only supported by the mem backend"),
+        };
+        if self.backend == Backend::SqliteMem {
+            match &mut idx {
+                Index::SrpSql(lsh) => lsh.hash_tables.as_mut().unwrap().to_mem()?,
+                Index::L2Sql(lsh) => lsh.hash_tables.as_mut().unwrap().to_mem()?,
+                _ => {}
+            }
+        }
+        Ok(idx)
+    }
+}
+
+impl Index {
+    /// Fit data dependent hashers. Only MIPS needs the maximum norm of the data.
+    pub fn fit(&mut self, vs: &[Vec<f32>]) {
+        if let Index::MipsMem(lsh) = self {
+            let flat = vs.concat();
+            lsh.hashers.iter_mut().for_each(|h| h.fit(&flat));
+        }
+    }
+
+    /// Commit the stored vectors of a SQLite backend and start a new transaction.
+    pub fn commit(&mut self) -> Result<()> {
+        match self {
+            Index::SrpSql(lsh) => {
+                lsh.commit()?;
+                lsh.init_transaction()?;
+            }
+            Index::L2Sql(lsh) => {
+                lsh.commit()?;
+                lsh.init_transaction()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Write the index to `path`.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        match self {
+            Index::SrpMem(lsh) => lsh.dump(path)?,
+            Index::L2Mem(lsh) => lsh.dump(path)?,
+            Index::MipsMem(lsh) => lsh.dump(path)?,
+            Index::SrpSql(lsh) => lsh.hash_tables.as_ref().unwrap().index_hash()?,
+            Index::L2Sql(lsh) => lsh.hash_tables.as_ref().unwrap().index_hash()?,
+            Index::SrpSqlMem(lsh) => {
+                let ht = lsh.hash_tables.as_mut().unwrap();
+                ht.index_hash()?;
+                ht.to_db(path)?;
+            }
+            Index::L2SqlMem(lsh) => {
+                let ht = lsh.hash_tables.as_mut().unwrap();
+                ht.index_hash()?;
+                ht.to_db(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rerank_zero_vector() {
+        let vs = vec![vec![0., 0.], vec![1., 0.], vec![0., 1.]];
+        let neighbors = rerank(&[1., 0.1], &[0, 1, 2], &vs, 3, Metric::Cosine).unwrap();
+        let ids: Vec<u32> = neighbors.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1, 2, 0]);
+        assert!(rerank(&[1., 0.], &[3], &vs, 1, Metric::Cosine).is_err());
+    }
+}
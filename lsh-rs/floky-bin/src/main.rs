@@ -1,92 +1,562 @@
-use lsh_rs::LshSql;
-use std::env;
+#![allow(non_snake_case)]
+#[macro_use]
+mod index;
+mod data;
+mod server;
+
+use crate::data::{read_records, read_text, read_vectors, Format, RecordReader};
+use crate::index::{rerank, sibling, Backend, Family, Index, IndexMeta, Neighbor};
+use anyhow::{bail, Result};
+use lsh_rs::{
+    eval::{brute_force_knn, evaluate, EvalRes, Metric},
+    io::{read_ivecs, write_ivecs, VecsWriter},
+    tune::{self, TuneConfig, TuneResult},
+};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io;
-use std::io::{BufRead, Write};
-use std::path::Path;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
 
-fn usage() {
-    println!(
-        "
-floky-bin <n-projections> <n-hash-tables> file.csv
-    "
-    )
-}
-
-fn read_csv<P>(path: P) -> Vec<Vec<f32>>
-where
-    P: AsRef<Path>,
-{
-    let mut vs = vec![];
-    if let Ok(lines) = read_lines(path) {
-        for line in lines {
-            if let Ok(line) = line {
-                let mut split = line.split(',');
-
-                let mut v = vec![];
-                for s in split {
-                    let val: f32 = s.parse().expect("could not parse values");
-                    v.push(val)
-                }
-                vs.push(v)
-            }
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "floky-bin",
+    about = "Approximate nearest neighbor search with Locality Sensitive Hashing."
+)]
+enum Cmd {
+    /// Build an index from a csv, tsv or json lines file, stdin or an .fvecs/.bvecs/.npy/.npz file.
+    Build(BuildOpt),
+    /// Query the nearest neighbors of the vectors in a data file.
+    Query(QueryOpt),
+    /// Show statistics of the hash tables of an index.
+    Describe(DescribeOpt),
+    /// Search parameters that reach a target recall on a data sample.
+    Tune(TuneOpt),
+    /// Measure recall and latency of an index against exact nearest neighbors.
+    Eval(EvalOpt),
+    /// Export the parameters, hashers and statistics of an index as json.
+    Export(ExportOpt),
+    /// Serve an index with json endpoints over http.
+    Serve(ServeOpt),
+}
+
+#[derive(Debug, StructOpt)]
+struct BuildOpt {
+    /// Hash family: srp, l2 or mips.
+    #[structopt(long, default_value = "l2")]
+    family: Family,
+    /// Storage backend: mem, sqlite or sqlite-mem.
+    #[structopt(long, default_value = "sqlite")]
+    backend: Backend,
+    /// Number of projections (K).
+    #[structopt(short = "k", long, default_value = "18")]
+    n_projections: usize,
+    /// Number of hash tables (L).
+    #[structopt(short = "l", long, default_value = "20")]
+    n_hash_tables: usize,
+    /// Parameter r of the l2 and mips hash functions.
+    #[structopt(short = "r", long, default_value = "4.0")]
+    r: f32,
+    /// Parameter U of the mips hash function.
+    #[structopt(long = "U", default_value = "0.83")]
+    U: f32,
+    /// Parameter m of the mips hash function.
+    #[structopt(short = "m", long, default_value = "3")]
+    m: usize,
+    /// Seed of the hash functions. If 0, the hash functions are seeded randomly.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+    /// Number of vectors stored per transaction. Must be larger than 0.
+    #[structopt(long, default_value = "1000")]
+    chunk_size: usize,
+    /// Format of a text data file: csv, tsv or jsonl. Defaults to the file extension.
+    #[structopt(long)]
+    format: Option<Format>,
+    /// Column of a csv or tsv file that holds the id of a vector.
+    #[structopt(long)]
+    id_column: Option<usize>,
+    /// Fail on malformed lines instead of skipping them.
+    #[structopt(long)]
+    strict: bool,
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Data file. Use `-` to read from stdin.
+    #[structopt(parse(from_os_str))]
+    data: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct QueryOpt {
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Number of buckets probed per hash table.
+    #[structopt(long)]
+    multi_probe: Option<usize>,
+    /// Number of neighbors returned per query.
+    #[structopt(long, default_value = "10")]
+    top_k: usize,
+    /// Output json instead of `query id distance` lines.
+    #[structopt(long)]
+    json: bool,
+    /// Query file.
+    #[structopt(parse(from_os_str))]
+    queries: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct DescribeOpt {
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Output json.
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct TuneOpt {
+    /// Hash family: srp or l2.
+    #[structopt(long, default_value = "l2")]
+    family: Family,
+    /// Minimal recall@k.
+    #[structopt(long, default_value = "0.9")]
+    target_recall: f64,
+    /// k of recall@k.
+    #[structopt(long, default_value = "10")]
+    top_k: usize,
+    /// Maximum memory usage of the index in bytes.
+    #[structopt(long)]
+    memory_limit: Option<usize>,
+    /// Number of data points the final index will hold. Defaults to the sample size.
+    #[structopt(long, default_value = "0")]
+    n_total: usize,
+    /// Number of vectors at the end of the data file held out as queries.
+    #[structopt(long, default_value = "100")]
+    n_queries: usize,
+    /// Candidate values for K.
+    #[structopt(short = "k", long, use_delimiter = true)]
+    n_projections: Vec<usize>,
+    /// Candidate values for L.
+    #[structopt(short = "l", long, use_delimiter = true)]
+    n_hash_tables: Vec<usize>,
+    /// Candidate values for r.
+    #[structopt(short = "r", long, use_delimiter = true)]
+    r: Vec<f32>,
+    /// Candidate multi-probe budgets.
+    #[structopt(long, use_delimiter = true)]
+    multi_probe: Vec<usize>,
+    #[structopt(long, default_value = "1")]
+    seed: u64,
+    /// Output json.
+    #[structopt(long)]
+    json: bool,
+    /// Data file.
+    #[structopt(parse(from_os_str))]
+    data: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct EvalOpt {
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Number of buckets probed per hash table.
+    #[structopt(long)]
+    multi_probe: Option<usize>,
+    /// k of recall@k.
+    #[structopt(long, default_value = "10")]
+    top_k: usize,
+    /// Ground truth .ivecs file. If not given, the ground truth is computed by brute force.
+    #[structopt(long, parse(from_os_str))]
+    ground_truth: Option<PathBuf>,
+    /// Write the brute force ground truth to this .ivecs file.
+    #[structopt(long, parse(from_os_str))]
+    save_ground_truth: Option<PathBuf>,
+    /// Output json.
+    #[structopt(long)]
+    json: bool,
+    /// Query file.
+    #[structopt(parse(from_os_str))]
+    queries: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportOpt {
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Output file. Defaults to stdout.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ServeOpt {
+    /// Location of the index.
+    #[structopt(short, long, default_value = "lsh.db3", parse(from_os_str))]
+    index: PathBuf,
+    /// Number of buckets probed per hash table.
+    #[structopt(long)]
+    multi_probe: Option<usize>,
+    /// Address to listen on.
+    #[structopt(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+    /// Number of worker threads.
+    #[structopt(long, default_value = "4")]
+    threads: usize,
+}
+
+#[derive(Serialize)]
+struct QueryResult {
+    query: usize,
+    neighbors: Vec<Neighbor>,
+}
+
+#[derive(Serialize)]
+struct Export {
+    meta: IndexMeta,
+    stats: lsh_rs::Stats,
+    hashers: serde_json::Value,
+}
+
+impl BuildOpt {
+    fn meta(&self, dim: usize, n_points: usize, data: PathBuf) -> IndexMeta {
+        IndexMeta {
+            family: self.family,
+            backend: self.backend,
+            n_projections: self.n_projections,
+            n_hash_tables: self.n_hash_tables,
+            dim,
+            seed: self.seed,
+            r: self.r,
+            U: self.U,
+            m: self.m,
+            n_points,
+            data,
+            records: None,
         }
-    };
-    vs
+    }
 }
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+fn build(opt: BuildOpt) -> Result<()> {
+    if opt.index.exists() {
+        bail!("index {} already exists", opt.index.display())
+    }
+    if opt.chunk_size == 0 {
+        bail!("chunk-size should be larger than 0")
+    }
+    let format = opt.format.or_else(|| Format::from_path(&opt.data));
+    match format {
+        // MIPS hashers are fitted on the complete data set.
+        Some(format) if opt.family != Family::Mips => build_streaming(&opt, format),
+        _ if opt.data == Path::new("-") => bail!("the mips family cannot be built from stdin"),
+        _ => build_in_memory(&opt, format),
+    }
 }
 
-fn run_lsh(n_projections: usize, n_hash_tables: usize, vs: &Vec<Vec<f32>>) {
-    let dim = vs[0].len();
-    let mut lsh = LshSql::new(n_projections, n_hash_tables, dim)
-        .only_index()
-        .l2(4.)
-        .expect("could not make lsh");
+fn build_in_memory(opt: &BuildOpt, format: Option<Format>) -> Result<()> {
+    let vs = match format {
+        Some(format) => read_text(&opt.data, format)?,
+        None => read_vectors(&opt.data)?,
+    };
+    if vs.is_empty() {
+        bail!("{} contains no vectors", opt.data.display())
+    }
+    let meta = opt.meta(vs[0].len(), vs.len(), opt.data.canonicalize()?);
+    let mut index = meta.create(&opt.index)?;
+    index.fit(&vs);
 
     let total = vs.len();
     let mut c = 0;
-    for chunk in vs.chunks(100) {
-        print!("{}/{}\r", c, total);
-        std::io::stdout().flush();
-        lsh.store_vecs(chunk);
-        c += 100;
-        lsh.commit();
-        lsh.init_transaction();
-    }
-    lsh.commit();
-}
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    match args.len() {
-        4 => {
-            let default = String::from("18");
-            let n_projections: usize = args
-                .get(1)
-                .unwrap_or(&default)
-                .parse()
-                .expect("n-projections not properly defined");
-            let default = String::from("20");
-            let n_hash_tables: usize = args
-                .get(2)
-                .unwrap_or(&default)
-                .parse()
-                .expect("n-hash-tables not properly defined");
-
-            let csv = args.get(3).expect("file not given");
-            let vs = read_csv(csv);
-            run_lsh(n_projections, n_hash_tables, &vs);
+    for chunk in vs.chunks(opt.chunk_size) {
+        call_index!(&mut index, lsh => lsh.store_vecs(chunk))?;
+        index.commit()?;
+        c += chunk.len();
+        eprint!("{}/{}\r", c, total);
+    }
+    eprintln!();
+    index.save(&opt.index)?;
+    meta.write(&opt.index)
+}
+
+/// Build an index from a text file or stdin without loading it in memory. The stored vectors
+/// are copied to `<index>.fvecs`, which becomes the data file of the index, so that the ids
+/// match the data file when malformed lines are skipped.
+fn build_streaming(opt: &BuildOpt, format: Format) -> Result<()> {
+    let mut reader = RecordReader::open(&opt.data, format, opt.id_column, opt.strict)?;
+    let mut chunk = reader.read_chunk(opt.chunk_size)?;
+    if chunk.is_empty() {
+        bail!("{} contains no vectors", opt.data.display())
+    }
+    let data = sibling(&opt.index, ".fvecs");
+    let mut meta = opt.meta(chunk[0].vector.len(), 0, data.clone());
+    if format == Format::Jsonl || opt.id_column.is_some() {
+        meta.records = Some(sibling(&opt.index, ".records.jsonl"));
+    }
+    let mut index = meta.create(&opt.index)?;
+    let mut data_writer = VecsWriter::<f32, _>::create(&data)?;
+    meta.data = data.canonicalize()?;
+    let mut records_writer = match &meta.records {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+
+    while !chunk.is_empty() {
+        let (vs, infos): (Vec<_>, Vec<_>) = chunk.into_iter().map(|r| (r.vector, r.info)).unzip();
+        call_index!(&mut index, lsh => lsh.store_vecs(&vs))?;
+        index.commit()?;
+        for v in &vs {
+            data_writer.write_vec(v)?;
+        }
+        if let Some(w) = &mut records_writer {
+            for info in &infos {
+                serde_json::to_writer(&mut *w, info)?;
+                writeln!(w)?;
+            }
+        }
+        meta.n_points += vs.len();
+        eprint!("stored {} vectors\r", meta.n_points);
+        chunk = reader.read_chunk(opt.chunk_size)?;
+    }
+    eprintln!();
+    if reader.n_skipped > 0 {
+        eprintln!("skipped {} malformed lines", reader.n_skipped);
+    }
+    data_writer.flush()?;
+    if let Some(w) = &mut records_writer {
+        w.flush()?;
+    }
+    index.save(&opt.index)?;
+    meta.write(&opt.index)
+}
+
+/// Query the index and re-rank the candidates by their exact distance. The `deleted` ids are
+/// skipped.
+fn query_knn(
+    index: &Index,
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    deleted: &HashSet<u32>,
+    top_k: usize,
+    metric: Metric,
+) -> Result<Vec<QueryResult>> {
+    qs.iter()
+        .enumerate()
+        .map(|(i, q)| {
+            let mut ids = call_index!(index, lsh => lsh.query_bucket_ids(q))?;
+            ids.retain(|id| !deleted.contains(id));
+            let neighbors = rerank(q, &ids, vs, top_k, metric)?;
+            Ok(QueryResult {
+                query: i,
+                neighbors,
+            })
+        })
+        .collect()
+}
+
+fn query(opt: QueryOpt) -> Result<()> {
+    let meta = IndexMeta::read(&opt.index)?;
+    let index = meta.open(&opt.index, opt.multi_probe)?;
+    let vs = meta.read_vectors(&opt.index)?;
+    let deleted = IndexMeta::read_deleted(&opt.index)?;
+    let qs = read_vectors(&opt.queries)?;
+    let mut results = query_knn(&index, &vs, &qs, &deleted, opt.top_k, meta.family.metric())?;
+    if let Some(path) = &meta.records {
+        let records = read_records(path)?;
+        for n in results.iter_mut().flat_map(|res| res.neighbors.iter_mut()) {
+            if let Some(info) = records.get(n.id as usize) {
+                n.key = info.id.clone();
+                n.metadata = info.metadata.clone();
+            }
         }
-        _ => {
-            usage();
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if opt.json {
+        serde_json::to_writer(&mut out, &results)?;
+        writeln!(out)?;
+    } else {
+        for res in results {
+            for n in res.neighbors {
+                match n.key {
+                    Some(key) => writeln!(out, "{}\t{}\t{}\t{}", res.query, n.id, n.distance, key)?,
+                    None => writeln!(out, "{}\t{}\t{}", res.query, n.id, n.distance)?,
+                }
+            }
         }
     }
+    Ok(())
+}
+
+fn describe(opt: DescribeOpt) -> Result<()> {
+    let meta = IndexMeta::read(&opt.index)?;
+    let index = meta.open(&opt.index, None)?;
+    if opt.json {
+        println!("{}", call_index!(&index, lsh => lsh.stats())?.to_json()?);
+    } else {
+        println!("{:?} index, {:?} backend", meta.family, meta.backend);
+        println!(
+            "K: {}, L: {}, dim: {}",
+            meta.n_projections, meta.n_hash_tables, meta.dim
+        );
+        print!("{}", call_index!(&index, lsh => lsh.describe())?);
+    }
+    Ok(())
+}
+
+fn tune(opt: TuneOpt) -> Result<()> {
+    let family = match opt.family {
+        Family::Srp => tune::Family::Srp,
+        Family::L2 => tune::Family::L2,
+        Family::Mips => bail!("the mips family cannot be tuned"),
+    };
+    let data = read_vectors(&opt.data)?;
+    if opt.n_queries == 0 || opt.n_queries >= data.len() {
+        bail!("n-queries should be between 0 and the number of vectors")
+    }
+    let (vs, qs) = data.split_at(data.len() - opt.n_queries);
+
+    let mut cfg = TuneConfig::new(family, opt.target_recall, opt.top_k);
+    cfg.n_total = opt.n_total;
+    cfg.seed = opt.seed;
+    if let Some(limit) = opt.memory_limit {
+        cfg.memory_limit = limit
+    }
+    if !opt.n_projections.is_empty() {
+        cfg.k = opt.n_projections
+    }
+    if !opt.n_hash_tables.is_empty() {
+        cfg.l = opt.n_hash_tables
+    }
+    if !opt.r.is_empty() {
+        cfg.r = opt.r
+    }
+    if !opt.multi_probe.is_empty() {
+        cfg.multi_probe_budget = opt.multi_probe
+    }
+    let res = tune::tune(vs, qs, &cfg)?;
+
+    if opt.json {
+        println!("{}", serde_json::to_string(&res)?);
+    } else {
+        print_tune_result(&res, &opt.data);
+    }
+    Ok(())
+}
+
+fn print_tune_result(res: &TuneResult, data: &Path) {
+    println!(
+        "K: {}, L: {}, r: {:?}, multi-probe: {:?}",
+        res.k, res.l, res.r, res.multi_probe_budget
+    );
+    print_eval_result(&res.eval);
+    println!("predicted memory: {} bytes", res.memory_bytes);
+    println!("predicted candidates: {:.1}", res.predicted_candidates);
+    println!("predicted latency: {:.6}s", res.predicted_latency);
+
+    let family = match res.r {
+        Some(r) => format!("--family l2 -r {}", r),
+        None => "--family srp".to_string(),
+    };
+    println!(
+        "\nfloky-bin build {} -k {} -l {} --seed {} {}",
+        family,
+        res.k,
+        res.l,
+        res.seed,
+        data.display()
+    );
+    if let Some(budget) = res.multi_probe_budget {
+        println!("query with: --multi-probe {}", budget);
+    }
+}
+
+fn eval(opt: EvalOpt) -> Result<()> {
+    let meta = IndexMeta::read(&opt.index)?;
+    let index = meta.open(&opt.index, opt.multi_probe)?;
+    let vs = meta.read_vectors(&opt.index)?;
+    let qs = read_vectors(&opt.queries)?;
+    let metric = meta.family.metric();
+
+    let ground_truth = match &opt.ground_truth {
+        Some(path) => read_ivecs(path)?,
+        None => brute_force_knn(&vs, &qs, opt.top_k, metric),
+    };
+    if ground_truth.len() != qs.len() {
+        bail!("ground truth does not match the number of queries")
+    }
+    if let Some(path) = &opt.save_ground_truth {
+        write_ivecs(path, &ground_truth)?;
+    }
+    let res =
+        call_index!(&index, lsh => evaluate(lsh, &vs, &qs, &ground_truth, opt.top_k, metric))?;
+    if opt.json {
+        println!("{}", serde_json::to_string(&res)?);
+    } else {
+        print_eval_result(&res);
+    }
+    Ok(())
+}
+
+fn print_eval_result(res: &EvalRes) {
+    println!("recall@{}: {:.4}", res.k, res.recall);
+    println!(
+        "candidates: avg {:.1}, min {}, max {}",
+        res.avg_candidates, res.min_candidates, res.max_candidates
+    );
+    println!(
+        "latency: p50 {:.6}s, p90 {:.6}s, p99 {:.6}s",
+        res.latency_p50, res.latency_p90, res.latency_p99
+    );
+}
+
+fn export(opt: ExportOpt) -> Result<()> {
+    let meta = IndexMeta::read(&opt.index)?;
+    let index = meta.open(&opt.index, None)?;
+    let (stats, hashers) =
+        call_index!(&index, lsh => (lsh.stats()?, serde_json::to_value(&lsh.hashers)?));
+    let export = Export {
+        meta,
+        stats,
+        hashers,
+    };
+    match opt.output {
+        Some(path) => serde_json::to_writer(File::create(path)?, &export)?,
+        None => {
+            serde_json::to_writer(io::stdout(), &export)?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn serve(opt: ServeOpt) -> Result<()> {
+    let meta = IndexMeta::read(&opt.index)?;
+    // a sqlite-mem index is served from disk, so that inserts are committed to the database.
+    let backend = match meta.backend {
+        Backend::SqliteMem => Backend::Sqlite,
+        backend => backend,
+    };
+    let index = IndexMeta {
+        backend,
+        ..meta.clone()
+    }
+    .open(&opt.index, opt.multi_probe)?;
+    server::serve(index, &meta, &opt.index, &opt.addr, opt.threads)
+}
+
+fn main() -> Result<()> {
+    match Cmd::from_args() {
+        Cmd::Build(opt) => build(opt),
+        Cmd::Query(opt) => query(opt),
+        Cmd::Describe(opt) => describe(opt),
+        Cmd::Tune(opt) => tune(opt),
+        Cmd::Eval(opt) => eval(opt),
+        Cmd::Export(opt) => export(opt),
+        Cmd::Serve(opt) => serve(opt),
+    }
 }
@@ -0,0 +1,451 @@
+//! JSON over HTTP server for an index.
+//!
+//! Endpoints:
+//! * `POST /insert` `{"vectors": [[..], ..]}` -> `{"ids": [..]}`
+//! * `POST /delete` `{"ids": [..]}` -> `{"deleted": n}`
+//! * `POST /query` `{"vector": [..], "k": 10}` -> `{"neighbors": [{"id": .., "distance": ..}, ..]}`
+//! * `POST /query/batch` `{"vectors": [[..], ..], "k": 10}` -> `{"results": [[..], ..]}`
+//! * `GET /stats` -> statistics of the hash tables.
+//!
+//! Queries run concurrently on the in memory backend. SQLite connections can not be shared
+//! between threads, so on the SQLite backends all requests are serialized.
+//!
+//! Inserted vectors are appended to `<index>.inserted.fvecs`, which is read after the data file
+//! of the index, and the hash tables are written to disk after every insert. A mem index is
+//! dumped completely, so inserts are slow on large mem indexes. The backends cannot delete by id,
+//! so deleted ids are appended to `<index>.deleted` and filtered from the results.
+use crate::index::{rerank, Index, IndexMeta, Neighbor};
+use anyhow::{anyhow, bail, Result};
+use lsh_rs::{eval::Metric, io::VecsWriter, HashTables, Persist, Stats, VecHash, LSH};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use tiny_http::{Header, Method, Response, Server};
+
+/// Lock around the state that is shared by the worker threads.
+trait Shared<S>: Send + Sync {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R;
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R;
+}
+
+impl<S: Send + Sync> Shared<S> for RwLock<S> {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.write().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl<S: Send> Shared<S> for Mutex<S> {
+    fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+struct State<T: HashTables<f32>, H: VecHash<f32>> {
+    lsh: LSH<f32, T, H>,
+    /// Location of the index.
+    index: PathBuf,
+    /// Data points used for exact re-ranking. Vectors inserted by the server are appended.
+    vs: Vec<Vec<f32>>,
+    /// The backends cannot delete by id, so deleted ids are filtered from the results.
+    deleted: HashSet<u32>,
+    metric: Metric,
+    dim: usize,
+}
+
+impl<T: HashTables<f32>, H: VecHash<f32>> State<T, H> {
+    /// State of the opened index at `index`, with the inserted vectors and deleted ids of
+    /// previous runs of the server.
+    fn open(lsh: LSH<f32, T, H>, meta: &IndexMeta, index: &Path) -> Result<Self> {
+        Ok(State {
+            lsh,
+            index: index.to_path_buf(),
+            vs: meta.read_vectors(index)?,
+            deleted: IndexMeta::read_deleted(index)?,
+            metric: meta.family.metric(),
+            dim: meta.dim,
+        })
+    }
+
+    fn check_dim(&self, v: &[f32]) -> Result<()> {
+        if v.len() != self.dim {
+            bail!("expected vectors of {} values, got {}", self.dim, v.len())
+        }
+        Ok(())
+    }
+
+    fn knn(&self, q: &[f32], k: usize) -> Result<Vec<Neighbor>> {
+        self.check_dim(q)?;
+        let mut ids = self.lsh.query_bucket_ids(q)?;
+        ids.retain(|id| !self.deleted.contains(id));
+        rerank(q, &ids, &self.vs, k, self.metric)
+    }
+
+    fn insert(&mut self, vs: Vec<Vec<f32>>) -> Result<Vec<u32>>
+    where
+        H: Sync,
+        LSH<f32, T, H>: Persist,
+    {
+        for v in &vs {
+            self.check_dim(v)?;
+        }
+        let ids = self.lsh.store_vecs(&vs)?;
+        let mut writer = VecsWriter::<f32, _>::append(IndexMeta::inserted_path(&self.index))?;
+        for v in &vs {
+            writer.write_vec(v)?;
+        }
+        writer.flush()?;
+        self.vs.extend(vs);
+        self.lsh.persist(Some(&self.index))?;
+        Ok(ids)
+    }
+
+    /// Delete the `ids`. Returns the number of ids that were not deleted yet.
+    fn delete(&mut self, ids: Vec<u32>) -> Result<usize> {
+        let n_points = self.vs.len() as u32;
+        let deleted = &mut self.deleted;
+        let ids: Vec<u32> = ids
+            .into_iter()
+            .filter(|&id| id < n_points && deleted.insert(id))
+            .collect();
+        if !ids.is_empty() {
+            let mut f = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(IndexMeta::deleted_path(&self.index))?;
+            for id in &ids {
+                writeln!(f, "{}", id)?;
+            }
+        }
+        Ok(ids.len())
+    }
+}
+
+fn default_k() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+struct InsertRequest {
+    vectors: Vec<Vec<f32>>,
+}
+
+#[derive(Serialize)]
+struct InsertResponse {
+    ids: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+struct DeleteRequest {
+    ids: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct DeleteResponse {
+    deleted: usize,
+}
+
+#[derive(Deserialize)]
+struct QueryRequest {
+    vector: Vec<f32>,
+    #[serde(default = "default_k")]
+    k: usize,
+}
+
+#[derive(Serialize)]
+struct QueryResponse {
+    neighbors: Vec<Neighbor>,
+}
+
+#[derive(Deserialize)]
+struct BatchQueryRequest {
+    vectors: Vec<Vec<f32>>,
+    #[serde(default = "default_k")]
+    k: usize,
+}
+
+#[derive(Serialize)]
+struct BatchQueryResponse {
+    results: Vec<Vec<Neighbor>>,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    n_deleted: usize,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn parse_body<B: DeserializeOwned>(body: &str) -> Result<B> {
+    serde_json::from_str(body).map_err(|e| anyhow!("invalid request body: {}", e))
+}
+
+/// Handle a request with a json `body`. Returns the status code and the json response.
+fn handle<S, T, H>(shared: &S, method: &Method, url: &str, body: &str) -> Result<(u16, String)>
+where
+    S: Shared<State<T, H>>,
+    T: HashTables<f32>,
+    H: VecHash<f32> + Sync,
+    LSH<f32, T, H>: Persist,
+{
+    let body = match (method, url) {
+        (Method::Post, "/insert") => {
+            let body: InsertRequest = parse_body(body)?;
+            if body.vectors.is_empty() {
+                bail!("no vectors given")
+            }
+            let ids = shared.write(|state| state.insert(body.vectors))?;
+            serde_json::to_string(&InsertResponse { ids })?
+        }
+        (Method::Post, "/delete") => {
+            let body: DeleteRequest = parse_body(body)?;
+            let deleted = shared.write(|state| state.delete(body.ids))?;
+            serde_json::to_string(&DeleteResponse { deleted })?
+        }
+        (Method::Post, "/query") => {
+            let body: QueryRequest = parse_body(body)?;
+            let neighbors = shared.read(|state| state.knn(&body.vector, body.k))?;
+            serde_json::to_string(&QueryResponse { neighbors })?
+        }
+        (Method::Post, "/query/batch") => {
+            let body: BatchQueryRequest = parse_body(body)?;
+            let results = shared.read(|state| {
+                body.vectors
+                    .iter()
+                    .map(|q| state.knn(q, body.k))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            serde_json::to_string(&BatchQueryResponse { results })?
+        }
+        (Method::Get, "/stats") => {
+            let res = shared.read(|state| -> Result<_> {
+                Ok(StatsResponse {
+                    n_deleted: state.deleted.len(),
+                    stats: state.lsh.stats()?,
+                })
+            })?;
+            serde_json::to_string(&res)?
+        }
+        (method, url) => {
+            let error = format!("no endpoint {} {}", method, url);
+            return Ok((404, serde_json::to_string(&ErrorResponse { error })?));
+        }
+    };
+    Ok((200, body))
+}
+
+fn run<S, T, H>(shared: S, addr: &str, n_threads: usize) -> Result<()>
+where
+    S: Shared<State<T, H>> + 'static,
+    T: HashTables<f32> + 'static,
+    H: VecHash<f32> + Sync + 'static,
+    LSH<f32, T, H>: Persist,
+{
+    let server =
+        Arc::new(Server::http(addr).map_err(|e| anyhow!("could not bind {}: {}", addr, e))?);
+    let shared = Arc::new(shared);
+    eprintln!("listening on http://{}", addr);
+
+    let workers: Vec<_> = (0..n_threads.max(1))
+        .map(|_| {
+            let server = server.clone();
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for mut req in server.incoming_requests() {
+                    let mut body = String::new();
+                    let res = match req.as_reader().read_to_string(&mut body) {
+                        Ok(_) => handle(&*shared, req.method(), req.url(), &body),
+                        Err(e) => Err(e.into()),
+                    };
+                    let (status, body) = res.unwrap_or_else(|e| {
+                        let error = ErrorResponse {
+                            error: e.to_string(),
+                        };
+                        (400, serde_json::to_string(&error).unwrap())
+                    });
+                    let header =
+                        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                    let res = Response::from_string(body)
+                        .with_status_code(status)
+                        .with_header(header);
+                    if let Err(e) = req.respond(res) {
+                        eprintln!("could not respond: {}", e)
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| anyhow!("worker thread panicked"))?;
+    }
+    Ok(())
+}
+
+/// Serve an opened index on `addr` with `n_threads` worker threads.
+///
+/// # Arguments
+/// * `index` - Index opened with [IndexMeta::open](../index/struct.IndexMeta.html#method.open).
+/// * `meta` - Parameters of the index.
+/// * `path` - Location of the index. Inserts and deletes are written next to the index.
+/// * `addr` - Address to listen on, i.e. `127.0.0.1:8080`.
+/// * `n_threads` - Number of worker threads.
+pub fn serve(
+    index: Index,
+    meta: &IndexMeta,
+    path: &Path,
+    addr: &str,
+    n_threads: usize,
+) -> Result<()> {
+    macro_rules! state {
+        ($lsh:expr) => {
+            State::open($lsh, meta, path)?
+        };
+    }
+    match index {
+        Index::SrpMem(lsh) => run(RwLock::new(state!(lsh)), addr, n_threads),
+        Index::L2Mem(lsh) => run(RwLock::new(state!(lsh)), addr, n_threads),
+        Index::MipsMem(lsh) => run(RwLock::new(state!(lsh)), addr, n_threads),
+        Index::SrpSql(lsh) => run(Mutex::new(state!(lsh)), addr, n_threads),
+        Index::L2Sql(lsh) => run(Mutex::new(state!(lsh)), addr, n_threads),
+        Index::SrpSqlMem(_) | Index::L2SqlMem(_) => bail!("opened indexes are never sqlite-mem"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::{Backend, Family};
+    use lsh_rs::io::write_vecs;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    /// Open the index, handle the requests and return the json responses.
+    fn session(meta: &IndexMeta, path: &Path, requests: &[(&str, Value)]) -> Vec<Value> {
+        fn run<T, H>(
+            lsh: LSH<f32, T, H>,
+            meta: &IndexMeta,
+            path: &Path,
+            requests: &[(&str, Value)],
+        ) -> Vec<Value>
+        where
+            T: HashTables<f32> + Send,
+            H: VecHash<f32> + Send + Sync,
+            LSH<f32, T, H>: Persist,
+        {
+            let shared = Mutex::new(State::open(lsh, meta, path).unwrap());
+            requests
+                .iter()
+                .map(|(url, body)| {
+                    let (status, res) =
+                        handle(&shared, &Method::Post, url, &body.to_string()).unwrap();
+                    assert_eq!(status, 200);
+                    serde_json::from_str(&res).unwrap()
+                })
+                .collect()
+        }
+        match meta.open(path, None).unwrap() {
+            Index::L2Mem(lsh) => run(lsh, meta, path, requests),
+            Index::L2Sql(lsh) => run(lsh, meta, path, requests),
+            _ => unreachable!(),
+        }
+    }
+
+    fn nearest(res: &Value) -> Vec<u64> {
+        res["neighbors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n["id"].as_u64().unwrap())
+            .collect()
+    }
+
+    fn check_inserts_and_deletes_persist(backend: Backend) {
+        let dir = std::env::temp_dir().join(format!("floky_server_test_{:?}", backend));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index");
+        let vs = vec![vec![1., 0., 0.], vec![0., 1., 0.], vec![0., 0., 1.]];
+        let data = dir.join("data.fvecs");
+        write_vecs(&data, &vs).unwrap();
+
+        let meta = IndexMeta {
+            family: Family::L2,
+            backend,
+            n_projections: 2,
+            n_hash_tables: 4,
+            dim: 3,
+            seed: 1,
+            r: 4.,
+            U: 0.83,
+            m: 3,
+            n_points: vs.len(),
+            data,
+            records: None,
+        };
+        let mut index = meta.create(&path).unwrap();
+        call_index!(&mut index, lsh => lsh.store_vecs(&vs)).unwrap();
+        index.commit().unwrap();
+        index.save(&path).unwrap();
+        drop(index);
+
+        let res = session(
+            &meta,
+            &path,
+            &[
+                ("/insert", json!({"vectors": [[1., 1., 0.]]})),
+                ("/delete", json!({"ids": [0, 0, 7]})),
+                ("/query", json!({"vector": [1., 0., 0.]})),
+            ],
+        );
+        assert_eq!(res[0]["ids"], json!([3]));
+        assert_eq!(res[1]["deleted"], json!(1));
+        assert!(!nearest(&res[2]).contains(&0));
+
+        // restart the server
+        let res = session(
+            &meta,
+            &path,
+            &[
+                ("/query", json!({"vector": [1., 1., 0.], "k": 1})),
+                ("/query", json!({"vector": [1., 0., 0.]})),
+                ("/delete", json!({"ids": [0]})),
+                ("/insert", json!({"vectors": [[0., 1., 1.]]})),
+            ],
+        );
+        assert_eq!(nearest(&res[0]), vec![3]);
+        assert_eq!(res[0]["neighbors"][0]["distance"], json!(0.));
+        assert!(!nearest(&res[1]).contains(&0));
+        assert_eq!(res[2]["deleted"], json!(0));
+        assert_eq!(res[3]["ids"], json!([4]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_inserts_and_deletes_persist_mem() {
+        check_inserts_and_deletes_persist(Backend::Mem);
+    }
+
+    #[test]
+    fn test_inserts_and_deletes_persist_sqlite() {
+        check_inserts_and_deletes_persist(Backend::Sqlite);
+    }
+}
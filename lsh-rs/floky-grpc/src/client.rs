@@ -0,0 +1,64 @@
+use crate::proto::{
+    lsh_client::LshClient, DeleteRequest, DescribeRequest, DescribeResponse, KnnRequest, Neighbor,
+    QueryIdsRequest, StoreRequest, Vector,
+};
+use std::convert::TryInto;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Status;
+
+fn vector(v: &[f32]) -> Option<Vector> {
+    Some(Vector { values: v.to_vec() })
+}
+
+/// Client of a running [LshService](struct.LshService.html).
+#[derive(Clone)]
+pub struct Client {
+    inner: LshClient<Channel>,
+}
+
+impl Client {
+    /// Connect to a server, i.e. `http://127.0.0.1:50051`.
+    pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Ok(Client {
+            inner: LshClient::connect(dst).await?,
+        })
+    }
+
+    /// Store vectors. Returns their ids.
+    pub async fn store(&mut self, vs: &[Vec<f32>]) -> Result<Vec<u32>, Status> {
+        let req = StoreRequest {
+            vectors: vs.iter().map(|v| Vector { values: v.clone() }).collect(),
+        };
+        Ok(self.inner.store(req).await?.into_inner().ids)
+    }
+
+    /// Ids of the data points that collide with `v` in any of the hash tables.
+    pub async fn query_ids(&mut self, v: &[f32]) -> Result<Vec<u32>, Status> {
+        let req = QueryIdsRequest { vector: vector(v) };
+        Ok(self.inner.query_ids(req).await?.into_inner().ids)
+    }
+
+    /// The `k` nearest neighbors of `v` among the colliding data points, closest first.
+    pub async fn knn(&mut self, v: &[f32], k: u32) -> Result<Vec<Neighbor>, Status> {
+        let req = KnnRequest {
+            vector: vector(v),
+            k,
+        };
+        Ok(self.inner.knn(req).await?.into_inner().neighbors)
+    }
+
+    /// Delete data points by id. Returns the number of deleted data points.
+    pub async fn delete(&mut self, ids: &[u32]) -> Result<u32, Status> {
+        let req = DeleteRequest { ids: ids.to_vec() };
+        Ok(self.inner.delete(req).await?.into_inner().deleted)
+    }
+
+    /// Parameters and statistics of the index.
+    pub async fn describe(&mut self) -> Result<DescribeResponse, Status> {
+        Ok(self.inner.describe(DescribeRequest {}).await?.into_inner())
+    }
+}
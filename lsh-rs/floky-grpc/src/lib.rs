@@ -0,0 +1,152 @@
+//! gRPC service for a [LSH](../lsh_rs/struct.LSH.html) index.
+//!
+//! The protobuf schema is in `proto/lsh.proto`. [LshService](struct.LshService.html) wraps an
+//! index and [Client](struct.Client.html) connects to a running server.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use floky_grpc::{serve, Client, LshService};
+//! use lsh_rs::{eval::Metric, LshMem};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let lsh = LshMem::<f32, _>::new(8, 10, 3).seed(1).l2(2.)?;
+//!     let service = LshService::new(lsh, Metric::L2);
+//!     tokio::spawn(serve(service, "127.0.0.1:50051".parse()?));
+//!
+//!     let mut client = Client::connect("http://127.0.0.1:50051").await?;
+//!     let ids = client.store(&[vec![1., 2., 3.], vec![2., 2., 3.]]).await?;
+//!     let neighbors = client.knn(&[1., 2., 3.], 1).await?;
+//!     assert_eq!(neighbors[0].id, ids[0]);
+//!     Ok(())
+//! }
+//! ```
+mod client;
+mod server;
+
+/// Messages and services generated from `proto/lsh.proto`.
+pub mod proto {
+    tonic::include_proto!("lsh");
+}
+
+pub use client::Client;
+pub use proto::{DescribeResponse, Neighbor};
+pub use server::{serve, LshService};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lsh_rs::{eval::Metric, LshMem, LshSql};
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    /// Address of a free port on the loopback interface.
+    fn free_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    async fn connect(addr: SocketAddr) -> Client {
+        let dst = format!("http://{}", addr);
+        for _ in 0..50 {
+            if let Ok(client) = Client::connect(dst.clone()).await {
+                return client;
+            }
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+        }
+        panic!("could not connect to {}", dst)
+    }
+
+    #[tokio::test]
+    async fn test_loopback() {
+        let lsh = LshMem::<f32, _>::new(5, 10, 3).seed(1).l2(4.).unwrap();
+        let addr = free_addr();
+        tokio::spawn(serve(LshService::new(lsh, Metric::L2), addr));
+        let mut client = connect(addr).await;
+
+        let vs = vec![vec![1., 2., 3.], vec![1.1, 2., 3.], vec![-10., 20., -5.]];
+        let ids = client.store(&vs).await.unwrap();
+        assert_eq!(ids, vec![0, 1, 2]);
+
+        let candidates = client.query_ids(&[1., 2., 3.]).await.unwrap();
+        assert!(candidates.contains(&0));
+        let neighbors = client.knn(&[1.1, 2., 3.], 2).await.unwrap();
+        assert_eq!(neighbors[0].id, 1);
+        assert_eq!(neighbors[0].distance, 0.);
+        assert_eq!(neighbors[1].id, 0);
+
+        assert_eq!(client.delete(&[1, 1, 99]).await.unwrap(), 1);
+        let neighbors = client.knn(&[1.1, 2., 3.], 2).await.unwrap();
+        assert_eq!(neighbors[0].id, 0);
+        assert!(!client.query_ids(&[1.1, 2., 3.]).await.unwrap().contains(&1));
+
+        let desc = client.describe().await.unwrap();
+        assert_eq!(desc.dim, 3);
+        // deleted ids are only filtered
+        assert_eq!(desc.n_points, 3);
+        assert_eq!(desc.n_deleted, 1);
+
+        // deleting a duplicate vector keeps the original
+        assert_eq!(client.store(&[vec![1., 2., 3.]]).await.unwrap(), vec![3]);
+        assert_eq!(client.delete(&[3]).await.unwrap(), 1);
+        assert!(client.query_ids(&[1., 2., 3.]).await.unwrap().contains(&0));
+
+        // wrong dimension
+        let status = client.knn(&[1., 2.], 1).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_sqlite() {
+        let path = std::env::temp_dir().join("floky_grpc_test.db3");
+        let _ = std::fs::remove_file(&path);
+        let lsh = LshSql::<f32, _>::new(5, 10, 3)
+            .seed(1)
+            .set_database_file(path.to_str().unwrap())
+            .srp()
+            .unwrap();
+        let addr = free_addr();
+        tokio::spawn(serve(LshService::new(lsh, Metric::Cosine), addr));
+        let mut client = connect(addr).await;
+
+        let ids = client
+            .store(&[vec![1., 2., 3.], vec![-3., -2., -1.]])
+            .await
+            .unwrap();
+        let neighbors = client.knn(&[1., 2., 3.], 1).await.unwrap();
+        assert_eq!(neighbors[0].id, ids[0]);
+        assert_eq!(client.describe().await.unwrap().n_points, 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deletes_survive_restart() {
+        let path = std::env::temp_dir().join("floky_grpc_test.deleted");
+        let _ = std::fs::remove_file(&path);
+        let vs = vec![vec![1., 2., 3.], vec![1.1, 2., 3.], vec![-10., 20., -5.]];
+        let service = || {
+            let mut lsh = LshMem::<f32, _>::new(5, 10, 3).seed(1).l2(4.).unwrap();
+            lsh.store_vecs(&vs).unwrap();
+            LshService::with_data(lsh, Metric::L2, vs.clone())
+                .deleted_file(&path)
+                .unwrap()
+        };
+
+        let addr = free_addr();
+        tokio::spawn(serve(service(), addr));
+        let mut client = connect(addr).await;
+        assert_eq!(client.delete(&[1, 1]).await.unwrap(), 1);
+
+        // restart
+        let addr = free_addr();
+        tokio::spawn(serve(service(), addr));
+        let mut client = connect(addr).await;
+        assert!(!client.query_ids(&[1.1, 2., 3.]).await.unwrap().contains(&1));
+        assert_eq!(client.describe().await.unwrap().n_deleted, 1);
+        assert_eq!(client.delete(&[1]).await.unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use crate::proto::{
+    lsh_server::{Lsh, LshServer},
+    DeleteRequest, DeleteResponse, DescribeRequest, DescribeResponse, KnnRequest, KnnResponse,
+    Neighbor, QueryIdsRequest, QueryIdsResponse, StoreRequest, StoreResponse, Vector,
+};
+use lsh_rs::{
+    eval::{rerank_with, Metric},
+    Error, HashTables, Persist, VecHash, LSH,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::task;
+use tonic::{transport::Server, Request, Response, Status};
+
+fn to_status(e: Error) -> Status {
+    match e {
+        Error::Failed(msg) => Status::invalid_argument(msg),
+        Error::NotImplemented => Status::unimplemented(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+struct State<T: HashTables<f32>, H: VecHash<f32>> {
+    lsh: LSH<f32, T, H>,
+    /// Data points by id. Used for exact re-ranking in `Knn`.
+    vectors: HashMap<u32, Vec<f32>>,
+    /// The backends cannot delete by id, so deleted ids are filtered from the results.
+    deleted: HashSet<u32>,
+    /// File to which the deleted ids are appended, one per line.
+    deleted_path: Option<PathBuf>,
+}
+
+impl<T: HashTables<f32>, H: VecHash<f32>> State<T, H> {
+    fn query_ids(&self, v: &[f32]) -> lsh_rs::Result<Vec<u32>> {
+        if v.len() != self.lsh.dim {
+            return Err(Error::Failed(format!(
+                "expected a vector of {} values, got {}",
+                self.lsh.dim,
+                v.len()
+            )));
+        }
+        let mut ids = self.lsh.query_bucket_ids(v)?;
+        ids.retain(|id| !self.deleted.contains(id));
+        Ok(ids)
+    }
+
+    /// Delete the `ids`. The ids are written to the deleted file before they are filtered.
+    /// Returns the number of ids that were not deleted yet.
+    fn delete(&mut self, ids: Vec<u32>) -> lsh_rs::Result<u32> {
+        let mut new = HashSet::new();
+        let ids: Vec<u32> = ids
+            .into_iter()
+            .filter(|id| self.vectors.contains_key(id) && new.insert(*id))
+            .collect();
+        if let Some(path) = &self.deleted_path {
+            let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+            for id in &ids {
+                writeln!(f, "{}", id)?;
+            }
+            f.sync_data()?;
+        }
+        for id in &ids {
+            self.vectors.remove(id);
+            self.deleted.insert(*id);
+        }
+        Ok(ids.len() as u32)
+    }
+}
+
+/// gRPC service that wraps a [LSH](../lsh_rs/struct.LSH.html) index.
+///
+/// Calls are serialized by a lock, as the SQLite backends cannot be shared between threads. The
+/// calls run on the blocking thread pool, so that they do not block the async runtime.
+///
+/// Deleted ids are kept in memory and reappear after a restart, unless they are written to a
+/// [deleted_file](#method.deleted_file).
+pub struct LshService<T: HashTables<f32>, H: VecHash<f32>> {
+    state: Arc<Mutex<State<T, H>>>,
+    metric: Metric,
+}
+
+impl<T: HashTables<f32>, H: VecHash<f32>> LshService<T, H> {
+    /// Wrap an index. Neighbors of `Knn` calls are ranked by `metric`.
+    pub fn new(lsh: LSH<f32, T, H>, metric: Metric) -> Self {
+        Self::with_data(lsh, metric, vec![])
+    }
+
+    /// Wrap an index that already contains the data points `vs`. The ids of `vs` are their
+    /// positions. Only known data points are returned by `Knn`.
+    pub fn with_data(lsh: LSH<f32, T, H>, metric: Metric, vs: Vec<Vec<f32>>) -> Self {
+        let vectors = vs
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (i as u32, v))
+            .collect();
+        LshService {
+            state: Arc::new(Mutex::new(State {
+                lsh,
+                vectors,
+                deleted: HashSet::new(),
+                deleted_path: None,
+            })),
+            metric,
+        }
+    }
+
+    /// Append the deleted ids to the file at `path`, one id per line, so that deletes survive a
+    /// restart. The ids that are already in the file are deleted. Without this file the deleted
+    /// ids are only kept in memory and reappear after a restart.
+    pub fn deleted_file<P: AsRef<Path>>(self, path: P) -> lsh_rs::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if path.exists() {
+                for line in BufReader::new(File::open(&path)?).lines() {
+                    let line = line?;
+                    let id = line.trim().parse().map_err(|_| {
+                        Error::Failed(format!("invalid id {} in {}", line, path.display()))
+                    })?;
+                    state.vectors.remove(&id);
+                    state.deleted.insert(id);
+                }
+            }
+            state.deleted_path = Some(path);
+        }
+        Ok(self)
+    }
+}
+
+impl<T, H> LshService<T, H>
+where
+    T: HashTables<f32> + Send + 'static,
+    H: VecHash<f32> + Send + 'static,
+{
+    /// Call `f` with the locked state on the blocking thread pool.
+    async fn with_state<R, F>(&self, f: F) -> Result<R, Status>
+    where
+        F: FnOnce(&mut State<T, H>) -> lsh_rs::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let state = self.state.clone();
+        task::spawn_blocking(move || f(&mut state.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(to_status)
+    }
+}
+
+fn values(v: Option<Vector>) -> Vec<f32> {
+    v.map(|v| v.values).unwrap_or_default()
+}
+
+#[tonic::async_trait]
+impl<T, H> Lsh for LshService<T, H>
+where
+    T: HashTables<f32> + Send + 'static,
+    H: VecHash<f32> + Send + Sync + 'static,
+    LSH<f32, T, H>: Persist,
+{
+    async fn store(&self, req: Request<StoreRequest>) -> Result<Response<StoreResponse>, Status> {
+        let vs: Vec<Vec<f32>> = req
+            .into_inner()
+            .vectors
+            .into_iter()
+            .map(|v| v.values)
+            .collect();
+        let ids = self
+            .with_state(|state| {
+                let ids = state.lsh.store_vecs(&vs)?;
+                // the ids are in the index, even if persisting fails
+                state.vectors.extend(ids.iter().copied().zip(vs));
+                state.lsh.persist(None)?;
+                Ok(ids)
+            })
+            .await?;
+        Ok(Response::new(StoreResponse { ids }))
+    }
+
+    async fn query_ids(
+        &self,
+        req: Request<QueryIdsRequest>,
+    ) -> Result<Response<QueryIdsResponse>, Status> {
+        let v = values(req.into_inner().vector);
+        let ids = self.with_state(move |state| state.query_ids(&v)).await?;
+        Ok(Response::new(QueryIdsResponse { ids }))
+    }
+
+    async fn knn(&self, req: Request<KnnRequest>) -> Result<Response<KnnResponse>, Status> {
+        let req = req.into_inner();
+        let (q, k) = (values(req.vector), req.k as usize);
+        let metric = self.metric;
+        let neighbors = self
+            .with_state(move |state| {
+                let ids = state.query_ids(&q)?;
+                let get = |id| state.vectors.get(&id).map(Vec::as_slice);
+                Ok(rerank_with(&q, &ids, get, k, metric)
+                    .into_iter()
+                    .map(|(id, distance)| Neighbor { id, distance })
+                    .collect::<Vec<_>>())
+            })
+            .await?;
+        Ok(Response::new(KnnResponse { neighbors }))
+    }
+
+    async fn delete(
+        &self,
+        req: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let ids = req.into_inner().ids;
+        // The backends delete the first data point that equals the vector, which is another id
+        // for duplicate vectors. So deleted ids are only filtered.
+        let deleted = self.with_state(move |state| state.delete(ids)).await?;
+        Ok(Response::new(DeleteResponse { deleted }))
+    }
+
+    async fn describe(
+        &self,
+        _req: Request<DescribeRequest>,
+    ) -> Result<Response<DescribeResponse>, Status> {
+        let res = self
+            .with_state(|state| {
+                let stats = state.lsh.stats()?;
+                Ok(DescribeResponse {
+                    dim: state.lsh.dim as u32,
+                    n_hash_tables: state.lsh.n_hash_tables as u32,
+                    n_projections: state.lsh.n_projections as u32,
+                    n_points: stats.n_points as u32,
+                    n_deleted: state.deleted.len() as u32,
+                    description: stats.describe(),
+                })
+            })
+            .await?;
+        Ok(Response::new(res))
+    }
+}
+
+/// Serve `service` on `addr` until the future is dropped.
+pub async fn serve<T, H>(
+    service: LshService<T, H>,
+    addr: SocketAddr,
+) -> Result<(), tonic::transport::Error>
+where
+    T: HashTables<f32> + Send + 'static,
+    H: VecHash<f32> + Send + Sync + 'static,
+    LSH<f32, T, H>: Persist,
+{
+    Server::builder()
+        .add_service(LshServer::new(service))
+        .serve(addr)
+        .await
+}
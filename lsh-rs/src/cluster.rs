@@ -0,0 +1,419 @@
+//! Near-duplicate clustering of the data points in an [LSH](../struct.LSH.html) index.
+//!
+//! All ids that share a bucket in any of the `L` hash tables are candidate pairs. The candidates
+//! are verified with an exact distance and the verified pairs are merged into connected
+//! components with a [UnionFind](struct.UnionFind.html). A pair is only generated by the first
+//! hash table in which its ids share a bucket, so the candidates are verified without collecting
+//! them first.
+//!
+//! # Example
+//! ```
+//! use lsh_rs::{LshMem, eval::Metric};
+//! let vs = vec![
+//!     vec![1., 1., 0.],
+//!     vec![1., 1.01, 0.],
+//!     vec![-1., 0.5, 2.],
+//!     vec![1.01, 1., 0.],
+//! ];
+//! let mut lsh = LshMem::new(4, 10, 3).seed(1).l2(1.).unwrap();
+//! lsh.store_vecs(&vs).unwrap();
+//!
+//! let clusters = lsh.cluster(Metric::L2, 0.1).unwrap();
+//! assert_eq!(clusters.labels, vec![0, 0, 2, 0]);
+//! assert_eq!(clusters.duplicates(), vec![vec![0, 1, 3]]);
+//! ```
+use crate::{data::Numeric, eval::Metric, table::general::Bucket, Error, HashTables, Result, LSH};
+use fnv::FnvHashMap;
+use num::Float;
+use rayon::prelude::*;
+
+/// Disjoint sets of the ids `0..n` with path compression and union by rank.
+#[derive(Debug, Clone)]
+pub struct UnionFind {
+    parent: Vec<u32>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n as u32).collect(),
+            rank: vec![0; n],
+        }
+    }
+
+    /// Representative of the set of `x`.
+    pub fn find(&mut self, x: u32) -> u32 {
+        let mut root = x;
+        while self.parent[root as usize] != root {
+            root = self.parent[root as usize];
+        }
+        // path compression
+        let mut x = x;
+        while self.parent[x as usize] != root {
+            let next = self.parent[x as usize];
+            self.parent[x as usize] = root;
+            x = next;
+        }
+        root
+    }
+
+    /// Merge the sets of `a` and `b`. Returns false if they already were in the same set.
+    pub fn union(&mut self, a: u32, b: u32) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        let (ra, rb) = (self.rank[a as usize], self.rank[b as usize]);
+        if ra < rb {
+            self.parent[a as usize] = b;
+        } else {
+            self.parent[b as usize] = a;
+            if ra == rb {
+                self.rank[a as usize] += 1;
+            }
+        }
+        true
+    }
+
+    /// Label every id with the smallest id in its set.
+    pub fn labels(&mut self) -> Vec<u32> {
+        let mut min_id = FnvHashMap::default();
+        (0..self.parent.len() as u32)
+            .map(|x| *min_id.entry(self.find(x)).or_insert(x))
+            .collect()
+    }
+}
+
+/// Connected components of near-duplicate data points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clusters {
+    /// Sorted ids of the data points in the hash tables. Deleted ids are not included.
+    pub ids: Vec<u32>,
+    /// Cluster label per id in `ids`. The label is the smallest id in the cluster.
+    pub labels: Vec<u32>,
+    /// Number of verified candidate pairs.
+    pub n_pairs: usize,
+}
+
+impl Clusters {
+    /// Ids of every cluster, including clusters with a single id.
+    pub fn components(&self) -> Vec<Vec<u32>> {
+        let mut components: Vec<Vec<u32>> = vec![];
+        let mut position = FnvHashMap::default();
+        for (&id, &label) in self.ids.iter().zip(&self.labels) {
+            let pos = *position.entry(label).or_insert_with(|| {
+                components.push(vec![]);
+                components.len() - 1
+            });
+            components[pos].push(id);
+        }
+        components
+    }
+
+    /// Ids of the clusters with more than one id.
+    pub fn duplicates(&self) -> Vec<Vec<u32>> {
+        self.components()
+            .into_iter()
+            .filter(|c| c.len() > 1)
+            .collect()
+    }
+
+    /// Number of clusters, including clusters with a single id.
+    pub fn n_clusters(&self) -> usize {
+        self.ids
+            .iter()
+            .zip(&self.labels)
+            .filter(|&(id, label)| id == label)
+            .count()
+    }
+}
+
+/// Position of the bucket of every id, per hash table.
+fn bucket_positions(tables: &[Vec<Bucket>]) -> Vec<FnvHashMap<u32, usize>> {
+    tables
+        .par_iter()
+        .map(|buckets| {
+            let mut positions = FnvHashMap::default();
+            for (pos, bucket) in buckets.iter().enumerate() {
+                positions.extend(bucket.iter().map(|&id| (id, pos)));
+            }
+            positions
+        })
+        .collect()
+}
+
+/// Call `f` for all pairs of ids `(a, b)` with `a < b` in a bucket of hash table `t`, except the
+/// pairs that already share a bucket in an earlier hash table. So every candidate pair is
+/// generated once, without collecting the pairs of all hash tables.
+fn for_each_pair<F>(
+    bucket: &Bucket,
+    t: usize,
+    positions: &[FnvHashMap<u32, usize>],
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(u32, u32) -> Result<()>,
+{
+    if bucket.len() < 2 {
+        return Ok(());
+    }
+    let mut ids: Vec<u32> = bucket.iter().copied().collect();
+    ids.sort_unstable();
+    for (i, &a) in ids.iter().enumerate() {
+        for &b in &ids[i + 1..] {
+            let seen = positions[..t]
+                .iter()
+                .any(|p| matches!((p.get(&a), p.get(&b)), (Some(x), Some(y)) if x == y));
+            if !seen {
+                f(a, b)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<N: Numeric, H, T: HashTables<N>> LSH<N, T, H> {
+    /// All pairs of ids `(a, b)` with `a < b` that share a bucket in at least one of the hash
+    /// tables. The pairs of the hash tables are generated in parallel.
+    ///
+    /// The number of pairs is quadratic in the bucket sizes, so the hash length `K` should be
+    /// large enough to keep the buckets small.
+    pub fn candidate_pairs(&self) -> Result<Vec<(u32, u32)>> {
+        let tables = self.all_buckets()?;
+        let positions = bucket_positions(&tables);
+        let pairs = tables
+            .par_iter()
+            .enumerate()
+            .map(|(t, buckets)| {
+                let mut pairs = vec![];
+                for bucket in buckets {
+                    for_each_pair(bucket, t, &positions, |a, b| {
+                        pairs.push((a, b));
+                        Ok(())
+                    })?;
+                }
+                Ok(pairs)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut pairs: Vec<_> = pairs.into_iter().flatten().collect();
+        pairs.sort_unstable();
+        Ok(pairs)
+    }
+
+    /// Buckets of all hash tables.
+    fn all_buckets(&self) -> Result<Vec<Vec<Bucket>>> {
+        let ht = self.hash_tables.as_ref().unwrap();
+        (0..self.n_hash_tables).map(|i| ht.buckets(i)).collect()
+    }
+
+    /// The candidate pairs are verified while they are generated, so only the verified pairs are
+    /// held in memory.
+    fn cluster_candidates<F>(&self, verify: F) -> Result<Clusters>
+    where
+        F: Fn(u32, u32) -> Result<bool> + Sync,
+    {
+        let tables = self.all_buckets()?;
+        let positions = bucket_positions(&tables);
+        let pairs = tables
+            .par_iter()
+            .enumerate()
+            .flat_map(|(t, buckets)| buckets.par_iter().map(move |bucket| (t, bucket)))
+            .map(|(t, bucket)| {
+                let mut verified = vec![];
+                for_each_pair(bucket, t, &positions, |a, b| {
+                    if verify(a, b)? {
+                        verified.push((a, b));
+                    }
+                    Ok(())
+                })?;
+                Ok(verified)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the ids are not necessarily `0..n`, so the union find works on their positions.
+        let mut ids: Vec<u32> = positions.iter().flat_map(|p| p.keys().copied()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let pos = |id| ids.binary_search(&id).unwrap() as u32;
+        let mut uf = UnionFind::new(ids.len());
+        let mut n_pairs = 0;
+        for &(a, b) in pairs.iter().flatten() {
+            uf.union(pos(a), pos(b));
+            n_pairs += 1;
+        }
+        // the smallest position in a set is also the smallest id.
+        let labels = uf.labels().iter().map(|&l| ids[l as usize]).collect();
+        Ok(Clusters {
+            ids,
+            labels,
+            n_pairs,
+        })
+    }
+
+    /// Cluster the ids of the candidate pairs that are verified by `is_duplicate`. Can be used
+    /// when the backend only stores indexes, i.e. the SQLite backends, or for
+    /// [custom inputs](input/index.html).
+    ///
+    /// # Arguments
+    /// * `is_duplicate` - Exact check of a candidate pair of ids.
+    pub fn cluster_by<F>(&self, is_duplicate: F) -> Result<Clusters>
+    where
+        F: Fn(u32, u32) -> bool + Sync,
+    {
+        self.cluster_candidates(|a, b| Ok(is_duplicate(a, b)))
+    }
+}
+
+impl<N: Numeric + Float, H, T: HashTables<N> + Sync> LSH<N, T, H> {
+    /// Cluster the stored data points. Candidate pairs within `threshold` distance of each other
+    /// are near-duplicates. The labels are the connected components of the near-duplicates.
+    ///
+    /// The data points are read from the backend by multiple threads, so the backend needs to be
+    /// `Sync`. Only the memory backend stores the data points and is `Sync`. Use
+    /// [cluster_by](#method.cluster_by) for the SQLite backends, when only the indexes are
+    /// stored or for sparse and custom data points.
+    ///
+    /// # Arguments
+    /// * `metric` - Distance measure of the data points.
+    /// * `threshold` - Maximum distance of near-duplicates.
+    pub fn cluster(&self, metric: Metric, threshold: N) -> Result<Clusters> {
+        if self.only_index_storage {
+            return Err(Error::Failed(
+                "cannot cluster data points, only the indexes are stored. Use cluster_by"
+                    .to_string(),
+            ));
+        }
+        let ht = self.hash_tables.as_ref().unwrap();
+        // the backend may store sparse or custom data points instead of dense ones.
+        let buckets = ht.buckets(0)?;
+        if let Some(&id) = buckets.iter().flat_map(|b| b.iter()).next() {
+            if ht.idx_to_datapoint(id).is_err() {
+                return Err(Error::Failed(
+                    "cannot cluster data points, no dense data points are stored. Use cluster_by"
+                        .to_string(),
+                ));
+            }
+        }
+        self.cluster_candidates(|a, b| {
+            let (a, b) = (ht.idx_to_datapoint(a)?, ht.idx_to_datapoint(b)?);
+            Ok(metric.distance(a, b) <= threshold)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LshMem, LshSqlMem, SparseVec};
+    use fnv::FnvHashSet;
+
+    #[test]
+    fn test_union_find() {
+        let mut uf = UnionFind::new(6);
+        assert!(uf.union(4, 1));
+        assert!(uf.union(1, 3));
+        assert!(!uf.union(3, 4));
+        assert!(uf.union(5, 2));
+        assert_eq!(uf.find(3), uf.find(4));
+        assert_ne!(uf.find(0), uf.find(1));
+        assert_eq!(uf.labels(), vec![0, 1, 2, 1, 1, 2]);
+
+        let c = Clusters {
+            ids: (0..6).collect(),
+            labels: uf.labels(),
+            n_pairs: 3,
+        };
+        assert_eq!(c.n_clusters(), 3);
+        assert_eq!(c.components(), vec![vec![0], vec![1, 3, 4], vec![2, 5]]);
+        assert_eq!(c.duplicates(), vec![vec![1, 3, 4], vec![2, 5]]);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let vs = vec![
+            vec![1., 0., 0.],
+            vec![0., 1., 0.],
+            vec![0.99, 0.01, 0.],
+            vec![0., 0.98, 0.01],
+            vec![0., 0., -1.],
+        ];
+        let mut lsh = LshMem::new(6, 10, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+        let pairs = lsh.candidate_pairs().unwrap();
+        assert!(pairs.contains(&(0, 2)) && pairs.contains(&(1, 3)));
+        assert!(pairs.iter().all(|&(a, b)| a < b));
+
+        let c = lsh.cluster(Metric::Cosine, 0.01).unwrap();
+        assert_eq!(c.labels, vec![0, 1, 0, 1, 4]);
+        assert_eq!(c.n_pairs, 2);
+        // nothing is a duplicate with a zero threshold
+        assert_eq!(lsh.cluster(Metric::Cosine, 0.).unwrap().n_clusters(), 5);
+
+        // only the ids are stored
+        let mut lsh = LshMem::new(6, 10, 3).seed(1).only_index().srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+        assert!(lsh.cluster(Metric::Cosine, 0.01).is_err());
+        let mut lsh = LshSqlMem::new(6, 10, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+        assert_eq!(lsh.candidate_pairs().unwrap(), pairs);
+        let c = lsh
+            .cluster_by(|a, b| Metric::Cosine.distance(&vs[a as usize], &vs[b as usize]) < 0.01)
+            .unwrap();
+        assert_eq!(c.labels, vec![0, 1, 0, 1, 4]);
+
+        // dense data points are needed to compute the distances
+        let mut lsh = LshMem::new(6, 10, 3).seed(1).srp().unwrap();
+        let svs: Vec<_> = vs.iter().map(|v| SparseVec::from_dense(v)).collect();
+        lsh.store_sparse_vecs(&svs).unwrap();
+        assert!(lsh.cluster(Metric::Cosine, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_cluster_deleted() {
+        let vs = vec![
+            vec![1., 0., 0.],
+            vec![0., 1., 0.],
+            vec![0.99, 0.01, 0.],
+            vec![0., 0.98, 0.01],
+            vec![0., 0., -1.],
+        ];
+        let mut lsh = LshMem::new(6, 10, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+        lsh.delete_vec(&vs[1]).unwrap();
+        lsh.delete_vec(&vs[4]).unwrap();
+
+        let c = lsh.cluster(Metric::Cosine, 0.01).unwrap();
+        assert_eq!(c.ids, vec![0, 2, 3]);
+        assert_eq!(c.labels, vec![0, 0, 3]);
+        assert_eq!(c.n_clusters(), 2);
+        assert_eq!(c.components(), vec![vec![0, 2], vec![3]]);
+        assert_eq!(c.duplicates(), vec![vec![0, 2]]);
+    }
+
+    #[test]
+    fn test_candidate_pairs_once() {
+        // a single projection puts many ids in the same buckets of every hash table
+        let vs: Vec<Vec<f32>> = (0..40)
+            .map(|i| vec![(i as f32).sin(), (i as f32).cos(), 0.5])
+            .collect();
+        let mut lsh = LshMem::new(1, 8, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+
+        let ht = lsh.hash_tables.as_ref().unwrap();
+        let mut expected = FnvHashSet::default();
+        for t in 0..8 {
+            for bucket in ht.buckets(t).unwrap() {
+                for &a in &bucket {
+                    expected.extend(bucket.iter().filter(|&&b| a < b).map(|&b| (a, b)));
+                }
+            }
+        }
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        expected.sort_unstable();
+        assert_eq!(lsh.candidate_pairs().unwrap(), expected);
+
+        let c = lsh.cluster_by(|_, _| true).unwrap();
+        assert_eq!(c.n_pairs, expected.len());
+    }
+}
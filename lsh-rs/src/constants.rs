@@ -1 +0,0 @@
-pub const DESCRIBE_MAX: u32 = 5000;
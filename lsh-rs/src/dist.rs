@@ -1,4 +1,5 @@
-use crate::data::Numeric;
+use crate::{data::Numeric, sparse::SparseVec};
+use fnv::FnvHashMap;
 use ndarray::prelude::*;
 use num::Float;
 use rayon::prelude::*;
@@ -22,6 +23,27 @@ pub fn l2_norm<N: Numeric + Float>(x: &[N]) -> N {
     x.dot(&x).sqrt()
 }
 
+/// L1 (Manhattan) distance between two vectors.
+///
+/// # Panics
+///
+/// Panics if `a.len() != b.len()`.
+///
+/// # Examples
+///
+/// ```
+/// use lsh_rs::dist::l1_dist;
+/// let a = vec![1., -1.];
+/// let b = vec![0.5, 1.];
+/// assert_eq!(l1_dist(&a, &b), 2.5);
+/// ```
+pub fn l1_dist<N: Numeric + Float>(a: &[N], b: &[N]) -> N {
+    assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .fold(N::zero(), |acc, (&ai, &bi)| acc + (ai - bi).abs())
+}
+
 /// Dot product between two vectors.
 ///
 /// # Panics
@@ -57,3 +79,82 @@ pub fn inner_prod<N: Numeric + Float>(a: &[N], b: &[N]) -> N {
 pub fn cosine_sim<N: Numeric + Float>(a: &[N], b: &[N]) -> N {
     inner_prod(a, b) / (l2_norm(a) * l2_norm(b))
 }
+
+/// Hamming distance between two binary vectors packed in `u64` words.
+///
+/// # Panics
+///
+/// Panics if `a.len() != b.len()`.
+///
+/// # Examples
+///
+/// ```
+/// use lsh_rs::dist::hamming;
+/// let a = vec![0b1011, 0];
+/// let b = vec![0b0001, 1];
+/// assert_eq!(hamming(&a, &b), 3);
+/// ```
+pub fn hamming(a: &[u64], b: &[u64]) -> u32 {
+    assert_eq!(a.len(), b.len());
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Generalized Jaccard distance between two non-negative vectors.
+/// One minus `Σ min(aᵢ, bᵢ) / Σ max(aᵢ, bᵢ)`.
+///
+/// # Panics
+///
+/// Panics if `a.len() != b.len()`.
+///
+/// # Examples
+///
+/// ```
+/// use lsh_rs::dist::generalized_jaccard;
+/// let a = vec![1., 2., 0.];
+/// let b = vec![1., 1., 2.];
+/// assert_eq!(generalized_jaccard(&a, &b), 0.6);
+/// ```
+pub fn generalized_jaccard<N: Numeric + Float>(a: &[N], b: &[N]) -> N {
+    assert_eq!(a.len(), b.len());
+    let (min, max) = a
+        .iter()
+        .zip(b)
+        .fold((N::zero(), N::zero()), |(min, max), (&ai, &bi)| {
+            (min + ai.min(bi), max + ai.max(bi))
+        });
+    jaccard_from_sums(min, max)
+}
+
+/// Generalized Jaccard distance between two non-negative sparse vectors.
+///
+/// # Examples
+///
+/// ```
+/// use lsh_rs::{dist::generalized_jaccard_sparse, SparseVec};
+/// let a = SparseVec::new(vec![0, 1], vec![1., 2.]).unwrap();
+/// let b = SparseVec::new(vec![2, 0, 1], vec![2., 1., 1.]).unwrap();
+/// assert_eq!(generalized_jaccard_sparse(&a, &b), 0.6);
+/// ```
+pub fn generalized_jaccard_sparse<N: Numeric + Float>(a: &SparseVec<N>, b: &SparseVec<N>) -> N {
+    let mut pairs: FnvHashMap<usize, (N, N)> = FnvHashMap::default();
+    for (i, x) in a.iter() {
+        pairs.entry(i).or_insert((N::zero(), N::zero())).0 = x;
+    }
+    for (i, x) in b.iter() {
+        pairs.entry(i).or_insert((N::zero(), N::zero())).1 = x;
+    }
+    let (min, max) = pairs
+        .values()
+        .fold((N::zero(), N::zero()), |(min, max), &(ai, bi)| {
+            (min + ai.min(bi), max + ai.max(bi))
+        });
+    jaccard_from_sums(min, max)
+}
+
+fn jaccard_from_sums<N: Numeric + Float>(min: N, max: N) -> N {
+    // two all zero vectors are equal.
+    if max == N::zero() {
+        return N::zero();
+    }
+    N::one() - min / max
+}
@@ -13,6 +13,10 @@ pub enum Error {
     #[error(transparent)]
     SerializationFailed(#[from] std::boxed::Box<bincode::ErrorKind>),
     #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error(transparent)]
     SqlFailure(#[from] rusqlite::Error),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -0,0 +1,310 @@
+//! Evaluation of an [LSH](../struct.LSH.html) index against exact (brute-force) nearest neighbors.
+//!
+//! # Example
+//!
+//! ```
+//! use lsh_rs::{LshMem, eval::{brute_force_knn, evaluate, Metric}};
+//! let vs = vec![vec![1., 1., 0.], vec![-1., 2., 0.5], vec![0.5, 0.9, 0.1]];
+//! let qs = vec![vec![1., 1.1, 0.]];
+//! let mut lsh = LshMem::new(4, 10, 3).seed(1).srp().unwrap();
+//! lsh.store_vecs(&vs).unwrap();
+//!
+//! let ground_truth = brute_force_knn(&vs, &qs, 2, Metric::Cosine);
+//! let res = evaluate(&lsh, &vs, &qs, &ground_truth, 2, Metric::Cosine).unwrap();
+//! println!("recall@2: {}", res.recall);
+//! ```
+use crate::{
+    data::Numeric,
+    dist::{cosine_sim, generalized_jaccard, inner_prod, l1_dist},
+    Error, HashTables, Result, VecHash, LSH,
+};
+use fnv::FnvHashSet;
+use num::Float;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::time::Instant;
+
+/// Distance measure used to determine the exact nearest neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Metric {
+    /// Euclidean distance.
+    L2,
+    /// Manhattan distance.
+    L1,
+    /// One minus the cosine similarity.
+    Cosine,
+    /// Negative inner product. (Larger inner products are closer.)
+    InnerProduct,
+    /// Generalized Jaccard distance of non-negative data points.
+    GeneralizedJaccard,
+}
+
+impl Metric {
+    /// Distance between two data points. Lower values are closer.
+    pub fn distance<N: Numeric + Float>(&self, a: &[N], b: &[N]) -> N {
+        match self {
+            Metric::L2 => a
+                .iter()
+                .zip(b)
+                .fold(N::zero(), |acc, (&ai, &bi)| acc + (ai - bi) * (ai - bi))
+                .sqrt(),
+            Metric::L1 => l1_dist(a, b),
+            Metric::Cosine => N::one() - cosine_sim(a, b),
+            Metric::InnerProduct => -inner_prod(a, b),
+            Metric::GeneralizedJaccard => generalized_jaccard(a, b),
+        }
+    }
+}
+
+/// Result of an evaluation run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalRes {
+    /// Number of neighbors evaluated.
+    pub k: usize,
+    /// Number of queries.
+    pub n_queries: usize,
+    /// Mean recall@k over all queries.
+    pub recall: f64,
+    /// Average number of candidates returned by the index.
+    pub avg_candidates: f64,
+    pub min_candidates: usize,
+    pub max_candidates: usize,
+    /// Total time spent on hashing and retrieving the candidates (seconds).
+    pub query_time: f64,
+    /// Total time spent on exact re-ranking of the candidates (seconds).
+    pub rerank_time: f64,
+    /// Per query latency percentiles (seconds).
+    pub latency_p50: f64,
+    pub latency_p90: f64,
+    pub latency_p99: f64,
+}
+
+/// Order of two distances with NaN last, i.e. the cosine distance to a zero vector.
+pub fn cmp_distance<N: Float>(a: &N, b: &N) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(b).unwrap(),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+/// Sort `ids` by their distance to `q` and keep the `k` closest. Ids that are not in `vs` are
+/// skipped.
+pub fn rerank<N: Numeric + Float>(
+    q: &[N],
+    ids: &[u32],
+    vs: &[Vec<N>],
+    k: usize,
+    metric: Metric,
+) -> Vec<u32> {
+    rerank_with(
+        q,
+        ids,
+        |idx| vs.get(idx as usize).map(Vec::as_slice),
+        k,
+        metric,
+    )
+    .into_iter()
+    .map(|(idx, _)| idx)
+    .collect()
+}
+
+/// Same as [rerank](fn.rerank.html), but the data points are looked up by `get` and the
+/// distances are returned with the ids. Ids for which `get` returns `None` are skipped.
+pub fn rerank_with<'a, N, F>(
+    q: &[N],
+    ids: &[u32],
+    get: F,
+    k: usize,
+    metric: Metric,
+) -> Vec<(u32, N)>
+where
+    N: Numeric + Float + 'a,
+    F: Fn(u32) -> Option<&'a [N]>,
+{
+    let mut dist: Vec<(u32, N)> = ids
+        .iter()
+        .filter_map(|&idx| get(idx).map(|v| (idx, metric.distance(q, v))))
+        .collect();
+    // only the k closest need to be sorted
+    if k < dist.len() {
+        dist.select_nth_unstable_by(k, |(_, a), (_, b)| cmp_distance(a, b));
+        dist.truncate(k);
+    }
+    dist.sort_unstable_by(|(_, a), (_, b)| cmp_distance(a, b));
+    dist
+}
+
+/// Exact k nearest neighbors of every query by a linear scan over `vs`. The queries are
+/// processed in parallel.
+///
+/// # Arguments
+/// * `vs` - Data points. The returned ids are the indexes in this slice.
+/// * `qs` - Query points.
+/// * `k` - Number of nearest neighbors.
+/// * `metric` - Distance measure.
+pub fn brute_force_knn<N: Numeric + Float>(
+    vs: &[Vec<N>],
+    qs: &[Vec<N>],
+    k: usize,
+    metric: Metric,
+) -> Vec<Vec<u32>> {
+    let ids: Vec<u32> = (0..vs.len() as u32).collect();
+    qs.par_iter()
+        .map(|q| rerank(q, &ids, vs, k, metric))
+        .collect()
+}
+
+/// Fraction of the `ground_truth` that is found in `retrieved`.
+pub fn recall(retrieved: &[u32], ground_truth: &[u32]) -> f64 {
+    if ground_truth.is_empty() {
+        return 1.;
+    }
+    let retrieved: FnvHashSet<u32> = retrieved.iter().copied().collect();
+    let hits = ground_truth
+        .iter()
+        .filter(|idx| retrieved.contains(idx))
+        .count();
+    hits as f64 / ground_truth.len() as f64
+}
+
+/// Value at percentile `p` (0-100) of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+    let idx = (p / 100. * (sorted.len() - 1) as f64).round() as usize;
+    sorted[idx]
+}
+
+/// Run all queries against an LSH index and compare the re-ranked results with the ground truth.
+///
+/// The ids in the index must correspond to the indexes in `vs`. This is the case if `vs` is
+/// stored in order in an empty index.
+///
+/// # Arguments
+/// * `lsh` - LSH index in which `vs` is stored.
+/// * `vs` - Data points. Used for exact re-ranking of the candidates.
+/// * `qs` - Held out query points.
+/// * `ground_truth` - Exact nearest neighbors of `qs`. See [brute_force_knn](fn.brute_force_knn.html).
+/// * `k` - Number of neighbors evaluated.
+/// * `metric` - Distance measure used for re-ranking.
+pub fn evaluate<N, T, H>(
+    lsh: &LSH<N, T, H>,
+    vs: &[Vec<N>],
+    qs: &[Vec<N>],
+    ground_truth: &[Vec<u32>],
+    k: usize,
+    metric: Metric,
+) -> Result<EvalRes>
+where
+    N: Numeric + Float,
+    T: HashTables<N>,
+    H: VecHash<N>,
+{
+    if qs.is_empty() {
+        return Err(Error::Failed("no query points to evaluate".to_string()));
+    }
+    if ground_truth.len() != qs.len() {
+        return Err(Error::Failed(format!(
+            "got {} query points and ground truth for {}",
+            qs.len(),
+            ground_truth.len()
+        )));
+    }
+    let mut query_time = 0.;
+    let mut rerank_time = 0.;
+    let mut latencies = Vec::with_capacity(qs.len());
+    let mut n_candidates = Vec::with_capacity(qs.len());
+    let mut total_recall = 0.;
+
+    for (q, truth) in qs.iter().zip(ground_truth) {
+        let t0 = Instant::now();
+        let ids = lsh.query_bucket_ids(q)?;
+        let t_query = t0.elapsed().as_secs_f64();
+
+        let t1 = Instant::now();
+        let top_k = rerank(q, &ids, vs, k, metric);
+        let t_rerank = t1.elapsed().as_secs_f64();
+
+        query_time += t_query;
+        rerank_time += t_rerank;
+        latencies.push(t_query + t_rerank);
+        n_candidates.push(ids.len());
+        total_recall += recall(&top_k, &truth[..std::cmp::min(k, truth.len())]);
+    }
+    latencies.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let n_queries = latencies.len();
+
+    Ok(EvalRes {
+        k,
+        n_queries,
+        recall: total_recall / n_queries as f64,
+        avg_candidates: n_candidates.iter().sum::<usize>() as f64 / n_queries as f64,
+        min_candidates: n_candidates.iter().copied().min().unwrap_or(0),
+        max_candidates: n_candidates.iter().copied().max().unwrap_or(0),
+        query_time,
+        rerank_time,
+        latency_p50: percentile(&latencies, 50.),
+        latency_p90: percentile(&latencies, 90.),
+        latency_p99: percentile(&latencies, 99.),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::LshMem;
+
+    #[test]
+    fn test_brute_force_knn() {
+        let vs = vec![vec![0., 0.], vec![1., 1.], vec![3., 3.], vec![0.9, 1.2]];
+        let qs = vec![vec![1., 1.1]];
+        let gt = brute_force_knn(&vs, &qs, 2, Metric::L2);
+        assert_eq!(gt, vec![vec![1, 3]]);
+        assert_eq!(recall(&[3, 2], &gt[0]), 0.5);
+
+        // the cosine distance to a zero vector is NaN and is ranked last.
+        let vs = vec![vec![0., 0.], vec![1., 0.], vec![0., 1.]];
+        let gt = brute_force_knn(&vs, &[vec![1., 0.1]], 3, Metric::Cosine);
+        assert_eq!(gt, vec![vec![1, 2, 0]]);
+        assert_eq!(
+            brute_force_knn(&vs, &[vec![0., 1.]], 0, Metric::L2),
+            vec![Vec::<u32>::new()]
+        );
+    }
+
+    #[test]
+    fn test_rerank() {
+        let vs = vec![vec![0., 0.], vec![3., 4.], vec![1., 1.], vec![-1., 0.]];
+        assert_eq!(Metric::L2.distance(&vs[0], &vs[1]), 5.);
+        let ids = [1, 2, 3, 0];
+        assert_eq!(rerank(&[0., 0.], &ids, &vs, 2, Metric::L2), vec![0, 3]);
+        assert_eq!(
+            rerank(&[0., 0.], &ids, &vs, 10, Metric::L2),
+            vec![0, 3, 2, 1]
+        );
+        // unknown ids are skipped
+        assert_eq!(rerank(&[0., 0.], &[7, 1], &vs, 2, Metric::L2), vec![1]);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let vs: Vec<Vec<f32>> = (0..50)
+            .map(|i| vec![(i as f32).sin(), (i as f32).cos(), 1.])
+            .collect();
+        let qs = vs[..10].to_vec();
+        let mut lsh = LshMem::new(2, 20, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+
+        let gt = brute_force_knn(&vs, &qs, 1, Metric::Cosine);
+        let res = evaluate(&lsh, &vs, &qs, &gt, 1, Metric::Cosine).unwrap();
+        // the query points themselves are stored, so they are always found.
+        assert_eq!(res.recall, 1.);
+        assert_eq!(res.n_queries, 10);
+        assert!(res.latency_p50 <= res.latency_p99);
+
+        assert!(evaluate(&lsh, &vs, &[], &[], 1, Metric::Cosine).is_err());
+        assert!(evaluate(&lsh, &vs, &qs, &gt[..5], 1, Metric::Cosine).is_err());
+    }
+}
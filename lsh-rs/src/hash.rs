@@ -1,10 +1,17 @@
-use crate::{data::Numeric, dist::l2_norm, multi_probe::QueryDirectedProbe, utils::create_rng};
+use crate::{
+    data::Numeric, dist::l2_norm, linalg, multi_probe::QueryDirectedProbe, utils::create_rng,
+    Error, Result,
+};
 use ndarray::prelude::*;
-use ndarray_rand::rand_distr::{StandardNormal, Uniform};
+use ndarray_rand::rand_distr::{Cauchy, StandardNormal, Uniform};
 use ndarray_rand::RandomExt;
 use num::traits::NumCast;
 use num::{Float, Zero};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::ops::Range;
 
 pub type HashPrimitive = i8;
 pub type Hash = Vec<HashPrimitive>;
@@ -19,6 +26,23 @@ pub trait VecHash<N> {
         self.hash_vec_query(v)
     }
 
+    /// Create the hashes of a query data point for the hashers of all hash tables. Hashers that
+    /// share work between the hash tables override this.
+    fn hash_vec_query_tables(hashers: &[Self], v: &[N]) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_vec_query(v)).collect()
+    }
+
+    /// Create the hashes of a data point that is being stored for the hashers of all hash tables.
+    fn hash_vec_put_tables(hashers: &[Self], v: &[N]) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_vec_put(v)).collect()
+    }
+
     fn as_query_directed_probe(&self) -> Option<&dyn QueryDirectedProbe<N>> {
         None
     }
@@ -28,7 +52,7 @@ pub trait VecHash<N> {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SignRandomProjections<N: Numeric> {
     ///  Random unit vectors that will lead to the bits of the hash.
-    hyperplanes: Array2<N>,
+    pub(crate) hyperplanes: Array2<N>,
 }
 
 impl<N: Numeric> SignRandomProjections<N> {
@@ -45,6 +69,43 @@ impl<N: Numeric> SignRandomProjections<N> {
         SignRandomProjections { hyperplanes: hp }
     }
 
+    /// Super-Bit LSH. The hyperplanes are orthogonalized in groups of `depth`, which lowers the
+    /// variance of the estimated angles. [Read more.](https://papers.nips.cc/paper/4847-super-bit-locality-sensitive-hashing.pdf)
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of hyperplanes used for determining the hash.
+    ///   This will also be the hash length.
+    /// * `depth` - Number of hyperplanes that are orthogonalized together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is 0 or larger than `dim`.
+    pub fn new_super_bit(k: usize, dim: usize, depth: usize, seed: u64) -> Self {
+        assert!(
+            depth > 0 && depth <= dim,
+            "super bit depth should be in 1..=dim"
+        );
+        let mut rng = create_rng(seed);
+        let mut hp: Array2<f32> = Array::random_using((dim, k), StandardNormal, &mut rng);
+
+        // Gram-Schmidt per group of `depth` hyperplanes.
+        for start in (0..k).step_by(depth) {
+            for j in start..(start + depth).min(k) {
+                let mut v = hp.column(j).to_owned();
+                for i in start..j {
+                    let u = hp.column(i);
+                    v.scaled_add(-u.dot(&v), &u);
+                }
+                v /= v.dot(&v).sqrt();
+                hp.column_mut(j).assign(&v);
+            }
+        }
+        let hp = hp.mapv(|v| N::from_f32(v).unwrap());
+
+        SignRandomProjections { hyperplanes: hp }
+    }
+
     fn hash_vec(&self, v: &[N]) -> Hash {
         let v = aview1(v);
         self.hyperplanes
@@ -61,6 +122,99 @@ impl<N: Numeric> VecHash<N> for SignRandomProjections<N> {
     }
 }
 
+/// Data dependent hashing with learned projections. PCA-hash and Iterative Quantization (ITQ).
+/// [Read more.](http://slazebni.cs.illinois.edu/publications/ITQ.pdf)
+///
+/// Hashes like [SignRandomProjections](struct.SignRandomProjections.html), but after
+/// [fit](struct.ITQ.html#method.fit) the data points are centered and the projections are the
+/// top `k` principal components of the data. ITQ rotates the principal components such that the
+/// quantization error of the hash bits is minimal. Random projections are used before fitting.
+///
+/// PCA-hash (`n_iter = 0`) has no randomness, so all fitted hash tables are the same.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ITQ<N> {
+    /// Mean of the fitted data points.
+    mean: Array1<N>,
+    /// Projections with shape `(dim, k)`.
+    pub(crate) projections: Array2<N>,
+    /// ITQ iterations. 0 for PCA-hash.
+    n_iter: usize,
+    seed: u64,
+}
+
+impl<N: Numeric + Float> ITQ<N> {
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of projections. This will also be the hash length. At most `dim`.
+    /// * `n_iter` - Number of ITQ iterations. Use 0 for PCA-hash.
+    pub fn new(k: usize, dim: usize, n_iter: usize, seed: u64) -> Self {
+        let mut rng = create_rng(seed);
+        let p: Array2<f32> = Array::random_using((dim, k), StandardNormal, &mut rng);
+        ITQ {
+            mean: Array1::zeros(dim),
+            projections: p.mapv(|v| N::from_f32(v).unwrap()),
+            n_iter,
+            seed: rng.gen(),
+        }
+    }
+
+    /// Learn the projections from data points.
+    ///
+    /// # Arguments
+    /// * `x` - Data points with shape `(n, dim)`.
+    pub fn fit(&mut self, x: &ArrayView2<N>) -> Result<()> {
+        let (dim, k) = self.projections.dim();
+        if x.ncols() != dim || x.nrows() < 2 || k > dim {
+            return Err(Error::Failed(
+                "fit needs at least 2 data points and k <= dim".to_string(),
+            ));
+        }
+        if x.iter().any(|v| !v.is_finite()) {
+            return Err(Error::Failed(
+                "fit needs finite data points, found NaN or infinity".to_string(),
+            ));
+        }
+        let x = x.mapv(|v| v.to_f64().unwrap());
+        let mean = x.mean_axis(Axis(0)).unwrap();
+        let xc = &x - &mean;
+        let cov = xc.t().dot(&xc) / (x.nrows() - 1) as f64;
+        if cov.iter().any(|v| !v.is_finite()) {
+            return Err(Error::Failed(
+                "the covariance of the data points overflows".to_string(),
+            ));
+        }
+
+        let mut rng = create_rng(self.seed);
+        let mut w = linalg::top_eigenvectors(&cov, k, &mut rng);
+        if self.n_iter > 0 {
+            // minimize ||B - VR|| by alternating B = sign(VR) and the orthogonal R.
+            let v = xc.dot(&w);
+            let mut r = Array2::random_using((k, k), StandardNormal, &mut rng);
+            linalg::orthonormalize(&mut r);
+            for _ in 0..self.n_iter {
+                let b = v.dot(&r).mapv(|x| if x > 0. { 1. } else { -1. });
+                r = linalg::polar(&v.t().dot(&b));
+            }
+            w = w.dot(&r);
+        }
+        self.mean = mean.mapv(|v| N::from_f64(v).unwrap());
+        self.projections = w.mapv(|v| N::from_f64(v).unwrap());
+        Ok(())
+    }
+}
+
+impl<N: Numeric + Float> VecHash<N> for ITQ<N> {
+    fn hash_vec_query(&self, v: &[N]) -> Hash {
+        let v = &aview1(v) - &self.mean;
+        self.projections
+            .t()
+            .dot(&v)
+            .mapv(|ai| if ai > Zero::zero() { 1 } else { 0 })
+            .to_vec()
+    }
+}
+
 /// L2 Hasher family. [Read more.](https://arxiv.org/pdf/1411.3787.pdf)
 #[derive(Serialize, Deserialize, Clone)]
 pub struct L2<N> {
@@ -116,6 +270,68 @@ impl<N: Numeric + Float> VecHash<N> for L2<N> {
     }
 }
 
+/// L1 Hasher family. Uses Cauchy (1-stable) projections. [Read more.](https://www.cs.princeton.edu/courses/archive/spring05/cos598E/bib/p253-datar.pdf)
+///
+/// The hash values are `floor((a^T v + b) / r)`. The Cauchy distribution has heavy tails, so
+/// hash values that don't fit in the Hash number type i8 saturate at `i8::MIN` and `i8::MAX`.
+/// Panics on NaN values.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct L1<N> {
+    pub a: Array2<N>,
+    pub r: N,
+    pub b: Array1<N>,
+    n_projections: usize,
+}
+
+impl<N: Numeric + Float> L1<N> {
+    pub fn new(dim: usize, r: f32, n_projections: usize, seed: u64) -> Self {
+        let mut rng = create_rng(seed);
+        let cauchy = Cauchy::new(0f32, 1.).unwrap();
+        let a = Array::random_using((n_projections, dim), cauchy, &mut rng);
+        let uniform_dist = Uniform::new(0., r);
+        let b = Array::random_using(n_projections, uniform_dist, &mut rng);
+
+        // cast to generic
+        let a = a.mapv(|v| N::from_f32(v).unwrap());
+        let b = b.mapv(|v| N::from_f32(v).unwrap());
+        let r = N::from_f32(r).unwrap();
+
+        L1 {
+            a,
+            r,
+            b,
+            n_projections,
+        }
+    }
+
+    /// Projections `a^T v + b` of `v`. The hash values are `floor((a^T v + b) / r)`.
+    pub(crate) fn project(&self, v: &[N]) -> Array1<N> {
+        self.a.dot(&aview1(v)) + &self.b
+    }
+}
+
+impl<N: Numeric + Float> VecHash<N> for L1<N> {
+    fn hash_vec_query(&self, v: &[N]) -> Hash {
+        (self.project(v) / self.r)
+            .mapv(|x| {
+                let hp: HashPrimitive = NumCast::from(x.floor()).unwrap_or_else(|| {
+                    assert!(!x.is_nan(), "Hash value is NaN");
+                    if x > N::zero() {
+                        HashPrimitive::MAX
+                    } else {
+                        HashPrimitive::MIN
+                    }
+                });
+                hp
+            })
+            .to_vec()
+    }
+
+    fn as_query_directed_probe(&self) -> Option<&dyn QueryDirectedProbe<N>> {
+        Some(self)
+    }
+}
+
 /// Maximum Inner Product Search. [Read more.](https://papers.nips.cc/paper/5329-asymmetric-lsh-alsh-for-sublinear-time-maximum-inner-product-search-mips.pdf)
 #[derive(Serialize, Deserialize, Clone)]
 pub struct MIPS<N> {
@@ -198,6 +414,370 @@ impl<N: Numeric + Float> VecHash<N> for MIPS<N> {
     }
 }
 
+/// Weighted MinHash for the generalized Jaccard similarity of non-negative data points.
+/// Uses Improved Consistent Weighted Sampling (ICWS). [Read more.](https://static.googleusercontent.com/media/research.google.com/en//pubs/archive/36928.pdf)
+///
+/// Every sample is a dimension and a quantized weight. Only 8 bits of a hash of this pair are
+/// kept (b-bit MinHash), so different samples still collide with a probability of 1/256.
+/// Values `<= 0`, infinite values and NaN are ignored. All zero data points get the same hash.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WeightedMinHash<N> {
+    /// Number of samples. This will also be the hash length.
+    k: usize,
+    /// Seed of the hash function that derives the samples.
+    seed: u64,
+    _type: PhantomData<N>,
+}
+
+impl<N: Numeric + Float> WeightedMinHash<N> {
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of samples. This will also be the hash length.
+    pub fn new(k: usize, _dim: usize, seed: u64) -> Self {
+        let seed = create_rng(seed).gen();
+        WeightedMinHash {
+            k,
+            seed,
+            _type: PhantomData,
+        }
+    }
+
+    /// Gamma(2, 1) samples `r` and `c` and Uniform(0, 1) sample `beta` of dimension `i` and hash
+    /// value `j`. Returns `(r, ln(c), beta)`. The samples are derived from a hash of `(i, j)`,
+    /// so they don't need to be stored for every dimension.
+    fn samples(&self, i: usize, j: usize) -> (N, N, N) {
+        let mut state = self.seed ^ mix64(((i as u64) << 32) | j as u64);
+        let mut uniform = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            // 53 random bits in the open interval (0, 1)
+            ((mix64(state) >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        };
+        // the sum of two Exponential(1) samples is Gamma(2, 1) distributed
+        let r = -(uniform().ln() + uniform().ln());
+        let c = -(uniform().ln() + uniform().ln());
+        let beta = uniform();
+        let cast = |v: f64| N::from_f64(v).unwrap();
+        (cast(r), cast(c.ln()), cast(beta))
+    }
+
+    /// Hash the non-zero `(index, value)` pairs of a data point.
+    pub(crate) fn hash_weights<I: Iterator<Item = (usize, N)>>(&self, weights: I) -> Hash {
+        // logarithm of the minimal `a` and the sample (index, t) for every hash value.
+        let mut min_ln_a = vec![N::infinity(); self.k];
+        let mut samples = vec![(0, 0); self.k];
+
+        for (i, w) in weights.filter(|&(_, w)| w > Zero::zero() && w.is_finite()) {
+            let ln_w = w.ln();
+            for j in 0..self.k {
+                let (r, ln_c, beta) = self.samples(i, j);
+                let t = (ln_w / r + beta).floor();
+                // a = c / (y * exp(r)) with y = exp(r * (t - beta))
+                let ln_a = ln_c - r * (t - beta + N::one());
+                if ln_a < min_ln_a[j] {
+                    min_ln_a[j] = ln_a;
+                    samples[j] = (i, t.to_i64().unwrap());
+                }
+            }
+        }
+        samples
+            .into_iter()
+            .map(|(i, t)| {
+                b_bit(mix64(
+                    (i as u64) ^ (t as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl<N: Numeric + Float> VecHash<N> for WeightedMinHash<N> {
+    fn hash_vec_query(&self, v: &[N]) -> Hash {
+        self.hash_weights(v.iter().copied().enumerate())
+    }
+}
+
+/// splitmix64 finalizer.
+fn mix64(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+/// Keep the highest 8 bits of a MinHash value.
+fn b_bit(h: u64) -> HashPrimitive {
+    (h >> 56) as u8 as HashPrimitive
+}
+
+/// Densified One Permutation Hashing. A fast MinHash for the Jaccard similarity of sets.
+/// [Read more.](https://arxiv.org/pdf/1703.04664.pdf)
+///
+/// The elements of a set are the indexes of the non-zero values of a data point. A single pass
+/// over the set randomly divides the elements over the bins and keeps the minimal hash value per
+/// bin, instead of `k` permutations for MinHash. Empty bins are filled with the value of a
+/// randomly chosen non-empty bin (optimal densification).
+///
+/// The hashers of [new_tables](#method.new_tables) hash a set once into `k * n_tables` bins and
+/// every hash table takes `k` of the bins.
+///
+/// Only 8 bits of every minimum are kept (b-bit MinHash), so different values still collide
+/// with a probability of 1/256. All empty sets get the same hash.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DensifiedMinHash {
+    /// Number of bins per hash table. This will also be the hash length.
+    k: usize,
+    /// Seed of the hash function that replaces the permutation. Shared by all hash tables.
+    seed: u64,
+    /// Hash table of this hasher.
+    table: usize,
+    n_tables: usize,
+}
+
+impl DensifiedMinHash {
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of bins. This will also be the hash length. Panics if `k` is 0.
+    pub fn new(k: usize, seed: u64) -> Self {
+        Self::new_tables(k, 1, seed).pop().unwrap()
+    }
+
+    /// Hashers for `n_tables` hash tables that share a single pass over a set.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of bins per hash table. This will also be the hash length. Panics if `k`
+    ///   is 0.
+    /// * `n_tables` - Number of hash tables.
+    pub fn new_tables(k: usize, n_tables: usize, seed: u64) -> Vec<Self> {
+        assert!(k > 0, "the number of bins should be larger than 0");
+        let seed = create_rng(seed).gen();
+        (0..n_tables)
+            .map(|table| DensifiedMinHash {
+                k,
+                seed,
+                table,
+                n_tables,
+            })
+            .collect()
+    }
+
+    /// Hash the elements of a set into the bins of all hash tables. Returns the hashes of
+    /// `tables`.
+    fn hash_set_tables<I: Iterator<Item = usize>>(
+        &self,
+        set: I,
+        tables: Range<usize>,
+    ) -> Vec<Hash> {
+        let n_bins = (self.k * self.n_tables) as u64;
+        let mut bins: Vec<Option<u64>> = vec![None; n_bins as usize];
+        for x in set {
+            let h = mix64(x as u64 ^ self.seed);
+            let (bin, value) = ((h % n_bins) as usize, h / n_bins);
+            match bins[bin] {
+                Some(min) if min <= value => {}
+                _ => bins[bin] = Some(value),
+            }
+        }
+        if bins.iter().all(Option::is_none) {
+            return tables.map(|_| vec![0; self.k]).collect();
+        }
+
+        let densify = |j: usize| {
+            let mut value = bins[j];
+            let mut attempt = 0u64;
+            // optimal densification: probe bins with a hash of (j, attempt).
+            while value.is_none() {
+                attempt += 1;
+                let h = mix64(self.seed ^ mix64(((j as u64) << 32) | attempt));
+                value = bins[(h % n_bins) as usize];
+            }
+            // spread the value over all 64 bits before b-bit hashing.
+            b_bit(mix64(value.unwrap()))
+        };
+        tables
+            .map(|t| (t * self.k..(t + 1) * self.k).map(densify).collect())
+            .collect()
+    }
+
+    /// Hash the elements of a set.
+    pub(crate) fn hash_set<I: Iterator<Item = usize>>(&self, set: I) -> Hash {
+        self.hash_set_tables(set, self.table..self.table + 1)
+            .pop()
+            .unwrap()
+    }
+
+    /// Hash the elements of a set for all `hashers`. The hashers of
+    /// [new_tables](#method.new_tables) share a single pass over the set.
+    pub(crate) fn hash_set_hashers<I>(hashers: &[Self], set: I) -> Vec<Hash>
+    where
+        I: Iterator<Item = usize> + Clone,
+    {
+        match hashers.first() {
+            Some(first)
+                if hashers.len() == first.n_tables
+                    && hashers
+                        .iter()
+                        .enumerate()
+                        .all(|(t, h)| h.table == t && h.k == first.k && h.seed == first.seed) =>
+            {
+                first.hash_set_tables(set, 0..first.n_tables)
+            }
+            _ => hashers.iter().map(|h| h.hash_set(set.clone())).collect(),
+        }
+    }
+}
+
+/// Indexes of the non-zero values.
+fn non_zero<N: Numeric>(v: &[N]) -> impl Iterator<Item = usize> + Clone + '_ {
+    v.iter()
+        .enumerate()
+        .filter(|(_, &x)| x != Zero::zero())
+        .map(|(i, _)| i)
+}
+
+impl<N: Numeric> VecHash<N> for DensifiedMinHash {
+    fn hash_vec_query(&self, v: &[N]) -> Hash {
+        self.hash_set(non_zero(v))
+    }
+
+    fn hash_vec_query_tables(hashers: &[Self], v: &[N]) -> Vec<Hash> {
+        Self::hash_set_hashers(hashers, non_zero(v))
+    }
+
+    fn hash_vec_put_tables(hashers: &[Self], v: &[N]) -> Vec<Hash> {
+        Self::hash_set_hashers(hashers, non_zero(v))
+    }
+}
+
+/// Densified Winner Take All hashing for sparse data points, i.e. the activations of a sparse
+/// neural network. [Read more.](https://arxiv.org/pdf/1903.03129.pdf)
+///
+/// Every hash value is the position of the maximum value in a bin of randomly chosen
+/// dimensions. Zeros are treated as missing values. Bins without non-zero values are filled with
+/// the value of a randomly chosen non-empty bin. For data points without zeros this is Winner
+/// Take All (WTA) hashing.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DWTA {
+    /// Dimensions per bin. One bin for every hash value.
+    pub(crate) bins: Vec<Vec<u32>>,
+    /// (bin, position) pairs of every dimension.
+    dim_to_bins: Vec<Vec<(u32, u8)>>,
+    seed: u64,
+}
+
+impl DWTA {
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - Number of bins. This will also be the hash length.
+    /// * `bin_size` - Number of dimensions per bin. At most `dim` and 128.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bin_size` is 0 or larger than `dim` or 128.
+    pub fn new(k: usize, dim: usize, bin_size: usize, seed: u64) -> Self {
+        assert!(
+            bin_size > 0 && bin_size <= dim && bin_size <= 128,
+            "bin size should be in 1..=min(dim, 128)"
+        );
+        let mut rng = create_rng(seed);
+        // chunks of permutations, so that the dimensions are used evenly and are unique per bin.
+        let mut perm: Vec<u32> = vec![];
+        let mut bins = Vec::with_capacity(k);
+        let mut dim_to_bins = vec![vec![]; dim];
+        for bin in 0..k {
+            if perm.len() < bin_size {
+                perm = (0..dim as u32).collect();
+                perm.shuffle(&mut rng);
+            }
+            let b: Vec<u32> = perm.drain(..bin_size).collect();
+            for (pos, &i) in b.iter().enumerate() {
+                dim_to_bins[i as usize].push((bin as u32, pos as u8));
+            }
+            bins.push(b);
+        }
+        DWTA {
+            bins,
+            dim_to_bins,
+            seed: rng.gen(),
+        }
+    }
+
+    /// Hash the non-zero `(index, value)` pairs of a data point.
+    pub(crate) fn hash_values<N: Numeric, I: Iterator<Item = (usize, N)>>(&self, v: I) -> Hash {
+        let k = self.bins.len();
+        // (max value, position) per bin.
+        let mut max: Vec<Option<(N, u8)>> = vec![None; k];
+        for (i, x) in v.filter(|&(_, x)| x != Zero::zero()) {
+            for &(bin, pos) in &self.dim_to_bins[i] {
+                let m = &mut max[bin as usize];
+                match *m {
+                    // ties go to the lowest position, so the order of `v` doesn't matter.
+                    Some((mx, mpos)) if mx > x || (mx == x && mpos < pos) => {}
+                    _ => *m = Some((x, pos)),
+                }
+            }
+        }
+        if max.iter().all(Option::is_none) {
+            return vec![0; k];
+        }
+
+        (0..k)
+            .map(|j| {
+                let mut value = max[j];
+                let mut attempt = 0u64;
+                // densification: probe bins with a hash of (j, attempt).
+                while value.is_none() {
+                    attempt += 1;
+                    let h = mix64(self.seed ^ mix64(((j as u64) << 32) | attempt));
+                    value = max[(h % k as u64) as usize];
+                }
+                value.unwrap().1 as HashPrimitive
+            })
+            .collect()
+    }
+}
+
+impl<N: Numeric> VecHash<N> for DWTA {
+    fn hash_vec_query(&self, v: &[N]) -> Hash {
+        self.hash_values(v.iter().copied().enumerate())
+    }
+}
+
+/// Bit sampling hasher family for the Hamming distance between binary vectors.
+/// [Read more.](http://people.csail.mit.edu/indyk/p117-andoni.pdf)
+///
+/// The binary vectors are packed in `u64` words, bit `i` is bit `i % 64` of word `i / 64`.
+/// Every hash value is a randomly sampled bit of the data point.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BitSampling {
+    /// Sampled bit positions. One for every hash value.
+    pub(crate) bits: Vec<u32>,
+}
+
+impl BitSampling {
+    ///
+    /// # Arguments
+    ///
+    /// * `n_bits` - Number of bits of the binary vectors.
+    /// * `k` - Number of sampled bits. This will also be the hash length.
+    pub fn new(n_bits: usize, k: usize, seed: u64) -> Self {
+        let mut rng = create_rng(seed);
+        let bits = (0..k).map(|_| rng.gen_range(0, n_bits as u32)).collect();
+        BitSampling { bits }
+    }
+}
+
+impl VecHash<u64> for BitSampling {
+    fn hash_vec_query(&self, v: &[u64]) -> Hash {
+        self.bits
+            .iter()
+            .map(|&b| ((v[b as usize / 64] >> (b % 64)) & 1) as HashPrimitive)
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -217,4 +797,250 @@ mod test {
         assert_eq!(h1, h2);
         assert_ne!(h1, h3);
     }
+
+    #[test]
+    fn test_super_bit() {
+        let sb = SignRandomProjections::<f64>::new_super_bit(10, 4, 4, 1);
+        // hyperplanes in a group are orthonormal.
+        let hp = sb.hyperplanes.slice(s![.., 4..8]);
+        let gram = hp.t().dot(&hp);
+        assert!((gram - Array2::<f64>::eye(4))
+            .iter()
+            .all(|x| x.abs() < 1e-5));
+
+        // same expected collision probability as SRP, but a lower variance.
+        let p = [1., 0.5, 0.2, -0.3, 0.8, 0.1, 0.4, -0.6];
+        let q = [0.9, 0.7, -0.1, 0.2, 0.3, 0.5, -0.2, -0.4];
+        let expected = crate::stats::srp_ph(crate::dist::cosine_sim(&p, &q));
+        let collisions = |h: SignRandomProjections<f64>| {
+            let (hp, hq) = (h.hash_vec_query(&p), h.hash_vec_query(&q));
+            hp.iter().zip(&hq).filter(|(a, b)| a == b).count() as f64 / 8.
+        };
+        let mean_var = |xs: Vec<f64>| {
+            let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+            let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+            (mean, var)
+        };
+        let (mean_srp, var_srp) = mean_var(
+            (1..2000)
+                .map(|s| collisions(SignRandomProjections::new(8, 8, s)))
+                .collect(),
+        );
+        let (mean_sb, var_sb) = mean_var(
+            (1..2000)
+                .map(|s| collisions(SignRandomProjections::new_super_bit(8, 8, 8, s)))
+                .collect(),
+        );
+        assert!((mean_srp - expected).abs() < 0.02);
+        assert!((mean_sb - expected).abs() < 0.02);
+        assert!(var_sb < var_srp, "{} {}", var_sb, var_srp);
+    }
+
+    fn itq_data() -> Array2<f64> {
+        // points along the diagonal of the first two dimensions with little noise.
+        let mut rng = create_rng(1);
+        let t: Array1<f64> = Array::random_using(300, StandardNormal, &mut rng);
+        let noise: Array2<f64> = Array::random_using((300, 3), StandardNormal, &mut rng);
+        let mut x = noise * 0.1;
+        x.column_mut(0).scaled_add(1., &t);
+        x.column_mut(1).scaled_add(1., &t);
+        x + 5.
+    }
+
+    #[test]
+    fn test_pca_hash() {
+        let x = itq_data();
+        let mut pca = ITQ::new(1, 3, 0, 1);
+        pca.fit(&x.view()).unwrap();
+        // first principal component is the diagonal
+        let p = pca.projections.column(0);
+        assert!((p[0].abs() - 0.5f64.sqrt()).abs() < 0.05);
+        assert!((p[1].abs() - 0.5f64.sqrt()).abs() < 0.05);
+        // centered data points are split in half
+        let ones = x
+            .outer_iter()
+            .filter(|v| pca.hash_vec_query(v.as_slice().unwrap())[0] == 1)
+            .count();
+        assert!(ones > 100 && ones < 200);
+        assert!(pca.fit(&x.slice(s![..1, ..])).is_err());
+
+        // the eigen decomposition needs finite values
+        let mut y = x.clone();
+        y[[3, 1]] = f64::NAN;
+        assert!(pca.fit(&y.view()).is_err());
+        y[[3, 1]] = f64::INFINITY;
+        assert!(pca.fit(&y.view()).is_err());
+        y[[3, 1]] = 1e300;
+        assert!(pca.fit(&y.view()).is_err());
+    }
+
+    #[test]
+    fn test_itq() {
+        let x = itq_data();
+        let xc = &x - &x.mean_axis(Axis(0)).unwrap();
+        let quantization_error = |n_iter| {
+            let mut itq = ITQ::new(2, 3, n_iter, 1);
+            itq.fit(&x.view()).unwrap();
+            // projections stay orthonormal
+            let gram = itq.projections.t().dot(&itq.projections);
+            assert!((gram - Array2::<f64>::eye(2))
+                .iter()
+                .all(|x| x.abs() < 1e-8));
+            let v = xc.dot(&itq.projections);
+            let b = v.mapv(|x| if x > 0. { 1. } else { -1. });
+            (b - v).mapv(|x| x * x).sum()
+        };
+        // the same random start, ITQ only lowers the quantization error.
+        assert!(quantization_error(30) <= quantization_error(1) + 1e-9);
+    }
+
+    #[test]
+    fn test_l1() {
+        let l1 = L1::new(5, 4., 7, 1);
+        let h1 = l1.hash_vec_query(&[1., 2., 3., 1., 3.]);
+        let h2 = l1.hash_vec_query(&[1., 2., 3., 1., 3.01]);
+        let h3 = l1.hash_vec_query(&[10., -10., 10., 10., 10.]);
+        assert_eq!(h1, h2);
+        assert_ne!(h1, h3);
+        // large projections saturate instead of wrapping around
+        let h = l1.hash_vec_query(&[1e6; 5]);
+        let proj = l1.project(&[1e6; 5]);
+        for (&hv, &p) in h.iter().zip(&proj) {
+            assert!(p.abs() / 4. > 1e3);
+            let expected = if p > 0. {
+                HashPrimitive::MAX
+            } else {
+                HashPrimitive::MIN
+            };
+            assert_eq!(hv, expected);
+        }
+        let h = l1.hash_vec_query(&[f64::INFINITY, 0., 0., 0., 0.]);
+        assert!(h
+            .iter()
+            .all(|&hv| hv == HashPrimitive::MAX || hv == HashPrimitive::MIN));
+    }
+
+    #[test]
+    fn test_densified_minhash() {
+        let dmh = DensifiedMinHash::new(256, 1);
+        let set = |idx: &[usize]| {
+            let mut v = vec![0u8; 1000];
+            idx.iter().for_each(|&i| v[i] = 1);
+            v
+        };
+        let a: Vec<usize> = (0..100).collect();
+        let b: Vec<usize> = (50..150).collect();
+        let ha = dmh.hash_vec_query(&set(&a));
+        let hb = dmh.hash_vec_query(&set(&b));
+        assert_eq!(ha.len(), 256);
+
+        // the fraction of equal hash values estimates the Jaccard similarity of 1/3
+        let eq = ha.iter().zip(&hb).filter(|(x, y)| x == y).count() as f64 / 256.;
+        assert!((eq - 1. / 3.).abs() < 0.1, "{}", eq);
+
+        // sets smaller than k are densified
+        let hc = dmh.hash_vec_query(&set(&[3, 7]));
+        assert_eq!(hc, dmh.hash_vec_query(&set(&[7, 3])));
+        let mut distinct = hc.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert!(distinct.len() <= 2);
+        assert_eq!(dmh.hash_vec_query(&set(&[])), vec![0; 256]);
+
+        // the hash tables share a single pass over the set
+        let hashers = DensifiedMinHash::new_tables(16, 4, 1);
+        let v = set(&a);
+        let hashes: Vec<_> = hashers.iter().map(|h| h.hash_vec_query(&v)).collect();
+        assert_eq!(
+            DensifiedMinHash::hash_vec_query_tables(&hashers, &v),
+            hashes
+        );
+        assert_eq!(
+            DensifiedMinHash::hash_vec_put_tables(&hashers[1..3], &v),
+            &hashes[1..3]
+        );
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_densified_minhash_zero_bins() {
+        DensifiedMinHash::new(0, 1);
+    }
+
+    #[test]
+    fn test_dwta() {
+        let dwta = DWTA::new(6, 8, 3, 1);
+        // the dimensions in a bin are unique
+        for bin in &dwta.bins {
+            let mut b = bin.clone();
+            b.sort_unstable();
+            b.dedup();
+            assert_eq!(b.len(), 3);
+        }
+
+        // the hash values are the positions of the maximum in every bin
+        let v = [0.1, 0.5, 0.3, 0.9, 0.2, 0.8, 0.4, 0.7];
+        let h = dwta.hash_vec_query(&v);
+        for (bin, &hv) in dwta.bins.iter().zip(&h) {
+            let argmax = (0..3)
+                .max_by(|&a, &b| v[bin[a] as usize].partial_cmp(&v[bin[b] as usize]).unwrap())
+                .unwrap();
+            assert_eq!(hv as usize, argmax);
+        }
+        // order preserving transformations don't change the hash
+        let v2: Vec<f64> = v.iter().map(|x| x * 3. + 1.).collect();
+        assert_eq!(h, dwta.hash_vec_query(&v2));
+
+        // a single non-zero value fills all bins
+        let i = dwta.bins[0][0] as usize;
+        let mut sparse = [0.; 8];
+        sparse[i] = 1.;
+        let positions: Vec<HashPrimitive> = dwta.dim_to_bins[i]
+            .iter()
+            .map(|&(_, pos)| pos as HashPrimitive)
+            .collect();
+        let h = dwta.hash_vec_query(&sparse);
+        assert!(h.iter().all(|hv| positions.contains(hv)));
+    }
+
+    #[test]
+    fn test_weighted_minhash() {
+        let wmh = WeightedMinHash::<f64>::new(200, 4, 1);
+        let a = [1., 2., 0., 4.];
+        let h = wmh.hash_vec_query(&a);
+        // scaling the weights of a single dimension changes some samples
+        assert_ne!(h, wmh.hash_vec_query(&[1., 2., 0., 8.]));
+        assert_eq!(h, wmh.hash_vec_query(&a));
+        // negative and non-finite values are ignored
+        assert_eq!(h, wmh.hash_vec_query(&[1., 2., -3., 4.]));
+        assert_eq!(h, wmh.hash_vec_query(&[1., 2., f64::INFINITY, 4.]));
+        assert_eq!(h, wmh.hash_vec_query(&[1., 2., f64::NAN, 4.]));
+
+        // the fraction of equal hash values estimates the generalized Jaccard similarity
+        let b = [1., 1., 1., 3.];
+        let sim = 1. - crate::dist::generalized_jaccard(&a, &b);
+        let hb = wmh.hash_vec_query(&b);
+        let eq = h.iter().zip(&hb).filter(|(x, y)| x == y).count() as f64 / 200.;
+        assert!((eq - sim).abs() < 0.1, "{} {}", eq, sim);
+
+        // the samples are not stored, so the size does not depend on the dimension
+        let wmh = WeightedMinHash::<f32>::new(16, 10_000_000, 1);
+        assert!(bincode::serialize(&wmh).unwrap().len() < 32);
+    }
+
+    #[test]
+    fn test_bit_sampling() {
+        let bs = BitSampling::new(128, 20, 1);
+        assert!(bs.bits.iter().all(|&b| b < 128));
+        assert_eq!(bs.hash_vec_query(&[0, 0]), vec![0; 20]);
+        assert_eq!(bs.hash_vec_query(&[u64::MAX, u64::MAX]), vec![1; 20]);
+
+        let v = [0b1011, 1 << 63];
+        let h = bs.hash_vec_query(&v);
+        for (&b, &hv) in bs.bits.iter().zip(&h) {
+            let expected = [0, 1, 3, 127].contains(&b) as HashPrimitive;
+            assert_eq!(hv, expected);
+        }
+    }
 }
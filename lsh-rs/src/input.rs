@@ -0,0 +1,438 @@
+//! Custom data point types, i.e. documents, strings, sets or graphs.
+//!
+//! [VecHash](../trait.VecHash.html) hashes dense data points (`&[N]`). Other data point types can
+//! be indexed by the same [LSH](../struct.LSH.html) by implementing [Input](trait.Input.html) for
+//! the data point and [InputHash](trait.InputHash.html) for the hash functions. Dense and
+//! [sparse](../sparse/index.html) data points are implementations of these traits, so custom
+//! inputs share the backends, multi-probing and [stats](../struct.LSH.html#method.stats).
+//!
+//! The memory backend stores custom inputs serialized with bincode, they are deserialized again
+//! by [query_input_bucket](../struct.LSH.html#method.query_input_bucket).
+//!
+//! # Limitations
+//!
+//! * The SQLite backends only store the ids of custom inputs, so only
+//!   [query_input](../struct.LSH.html#method.query_input) can be used.
+//! * Query directed probing needs the projections of a dense data point, so multi-probing of
+//!   custom and sparse inputs always uses step wise probing.
+//!
+//! # Example
+//! ```
+//! use lsh_rs::{Hash, InputHash, LshMem};
+//! use serde::{Deserialize, Serialize};
+//! use std::collections::hash_map::DefaultHasher;
+//! use std::hash::{Hash as _, Hasher};
+//!
+//! /// MinHash of the words in a document.
+//! #[derive(Serialize, Deserialize)]
+//! struct WordMinHash {
+//!     seeds: Vec<u64>,
+//! }
+//!
+//! impl InputHash<str> for WordMinHash {
+//!     fn hash_input_query(&self, doc: &str) -> Hash {
+//!         self.seeds
+//!             .iter()
+//!             .map(|&seed| {
+//!                 let min = doc.split_whitespace().map(|w| {
+//!                     let mut h = DefaultHasher::new();
+//!                     (seed, w).hash(&mut h);
+//!                     h.finish()
+//!                 }).min();
+//!                 min.unwrap_or(0) as i8
+//!             })
+//!             .collect()
+//!     }
+//! }
+//!
+//! let (n_projections, n_hash_tables) = (2, 10);
+//! let hashers = (0..n_hash_tables as u64)
+//!     .map(|i| WordMinHash { seeds: vec![2 * i, 2 * i + 1] })
+//!     .collect();
+//! // `dim` isn't used by documents.
+//! let mut lsh = LshMem::<f32, _>::new(n_projections, n_hash_tables, 0)
+//!     .custom_hashers(hashers)
+//!     .unwrap();
+//! let id = lsh.store_input("the quick brown fox jumps").unwrap();
+//! assert!(lsh.query_input("the quick brown fox sleeps").unwrap().contains(&id));
+//! let docs: Vec<String> = lsh.query_input_bucket("the quick brown fox sleeps").unwrap();
+//! assert!(docs.contains(&"the quick brown fox jumps".to_string()));
+//! ```
+use crate::{
+    data::Numeric,
+    hash::{Hash, VecHash},
+    multi_probe::step_wise_probing,
+    sparse::{SparseVec, SparseVecHash},
+    table::general::{Bucket, HashTables},
+    Error, Result, LSH,
+};
+use fnv::FnvHashSet;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::hash::BuildHasher;
+
+/// A data point type that can be indexed by [LSH](../struct.LSH.html).
+///
+/// The default methods accept every data point and store it serialized with bincode. Implement
+/// this trait (with an empty `impl` block) for custom data point types that implement
+/// `Serialize`.
+pub trait Input<N: Numeric>: Serialize {
+    /// Check the data point before it is hashed.
+    ///
+    /// # Arguments
+    /// * `dim` - Dimensions of the LSH.
+    fn validate(&self, _dim: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Store the data point in a hash table of the backend. Returns id.
+    ///
+    /// # Arguments
+    /// * `ht` - Storage backend.
+    /// * `hash` - Hash of the data point.
+    /// * `hash_table` - Number of the hash table. Ranging from 0 to L.
+    fn put<T: HashTables<N>>(&self, ht: &mut T, hash: Hash, hash_table: usize) -> Result<u32> {
+        ht.put_input(hash, &bincode::serialize(self)?, hash_table)
+    }
+}
+
+impl<N: Numeric> Input<N> for [N] {
+    fn validate(&self, dim: usize) -> Result<()> {
+        if self.len() != dim {
+            return Err(Error::Failed(
+                "data point is not valid, are the dimensions correct?".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn put<T: HashTables<N>>(&self, ht: &mut T, hash: Hash, hash_table: usize) -> Result<u32> {
+        ht.put(hash, self, hash_table)
+    }
+}
+
+impl<N: Numeric> Input<N> for SparseVec<N> {
+    fn validate(&self, dim: usize) -> Result<()> {
+        SparseVec::validate(self, dim)
+    }
+
+    fn put<T: HashTables<N>>(&self, ht: &mut T, hash: Hash, hash_table: usize) -> Result<u32> {
+        ht.put_sparse(hash, self, hash_table)
+    }
+}
+
+impl<N: Numeric> Input<N> for str {}
+impl<N: Numeric> Input<N> for String {}
+impl<N: Numeric, A: Serialize + Ord> Input<N> for BTreeSet<A> {}
+impl<N: Numeric, A: Serialize + Eq + std::hash::Hash, S: BuildHasher> Input<N> for HashSet<A, S> {}
+
+/// Hash functions for the data point type `I`.
+pub trait InputHash<I: ?Sized> {
+    /// Create a hash for a query data point.
+    fn hash_input_query(&self, x: &I) -> Hash;
+    /// Create a hash for a data point that is being stored.
+    fn hash_input_put(&self, x: &I) -> Hash {
+        self.hash_input_query(x)
+    }
+    /// Create the hashes of a query data point for the hashers of all hash tables.
+    fn hash_input_query_tables(hashers: &[Self], x: &I) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_input_query(x)).collect()
+    }
+    /// Create the hashes of a data point that is being stored for the hashers of all hash tables.
+    fn hash_input_put_tables(hashers: &[Self], x: &I) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_input_put(x)).collect()
+    }
+}
+
+impl<N: Numeric, H: VecHash<N>> InputHash<[N]> for H {
+    fn hash_input_query(&self, v: &[N]) -> Hash {
+        self.hash_vec_query(v)
+    }
+
+    fn hash_input_put(&self, v: &[N]) -> Hash {
+        self.hash_vec_put(v)
+    }
+
+    fn hash_input_query_tables(hashers: &[Self], v: &[N]) -> Vec<Hash> {
+        H::hash_vec_query_tables(hashers, v)
+    }
+
+    fn hash_input_put_tables(hashers: &[Self], v: &[N]) -> Vec<Hash> {
+        H::hash_vec_put_tables(hashers, v)
+    }
+}
+
+impl<N: Numeric, H: SparseVecHash<N>> InputHash<SparseVec<N>> for H {
+    fn hash_input_query(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_sparse_query(v)
+    }
+
+    fn hash_input_put(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_sparse_put(v)
+    }
+
+    fn hash_input_query_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash> {
+        H::hash_sparse_query_tables(hashers, v)
+    }
+
+    fn hash_input_put_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash> {
+        H::hash_sparse_put_tables(hashers, v)
+    }
+}
+
+impl<N: Numeric, H, T: HashTables<N>> LSH<N, T, H> {
+    /// Store a single data point of any [Input](input/trait.Input.html) type. Returns id.
+    ///
+    /// # Arguments
+    /// * `x` - Data point.
+    pub fn store_input<I: Input<N> + ?Sized>(&mut self, x: &I) -> Result<u32>
+    where
+        H: InputHash<I>,
+    {
+        x.validate(self.dim)?;
+
+        let mut idx = 0;
+        let mut ht = self.hash_tables.take().unwrap();
+        let hashes = H::hash_input_put_tables(&self.hashers, x);
+        for (i, hash) in hashes.into_iter().enumerate() {
+            match x.put(&mut ht, hash, i) {
+                Ok(id) => idx = id,
+                Err(e) => {
+                    self.hash_tables.replace(ht);
+                    return Err(e);
+                }
+            }
+        }
+        self.hash_tables.replace(ht);
+        Ok(idx)
+    }
+
+    /// Store multiple data points of any [Input](input/trait.Input.html) type. Returns the ids.
+    ///
+    /// # Arguments
+    /// * `xs` - Data points.
+    pub fn store_inputs<I: Input<N> + ?Sized>(&mut self, xs: &[&I]) -> Result<Vec<u32>>
+    where
+        H: InputHash<I>,
+    {
+        self.hash_tables
+            .as_mut()
+            .unwrap()
+            .increase_storage(xs.len());
+        xs.iter().map(|x| self.store_input(x)).collect()
+    }
+
+    /// Update the hashes of a data point of any [Input](input/trait.Input.html) type.
+    ///
+    /// # Arguments
+    /// * `idx` - Id of the data point.
+    /// * `new_x` - New data point that needs to be hashed.
+    /// * `old_x` - Old data point. Needed to remove the old hash.
+    pub fn update_input_by_idx<I: Input<N> + ?Sized>(
+        &mut self,
+        idx: u32,
+        new_x: &I,
+        old_x: &I,
+    ) -> Result<()>
+    where
+        H: InputHash<I>,
+    {
+        new_x.validate(self.dim)?;
+        old_x.validate(self.dim)?;
+        let mut ht = self.hash_tables.take().unwrap();
+        let new_hashes = H::hash_input_put_tables(&self.hashers, new_x);
+        let old_hashes = H::hash_input_put_tables(&self.hashers, old_x);
+        for (i, (new_hash, old_hash)) in new_hashes.into_iter().zip(old_hashes).enumerate() {
+            if let Err(e) = ht.update_by_idx(&old_hash, new_hash, idx, i) {
+                self.hash_tables.replace(ht);
+                return Err(e);
+            }
+        }
+        self.hash_tables.replace(ht);
+        Ok(())
+    }
+
+    pub(crate) fn query_input_union<I: Input<N> + ?Sized>(&self, x: &I) -> Result<Bucket>
+    where
+        H: InputHash<I>,
+    {
+        x.validate(self.dim)?;
+        let mut bucket_union = FnvHashSet::default();
+        // Query directed probing needs the projections of a dense query, so other inputs
+        // always use step wise probing.
+        let probing_seq = if self._multi_probe {
+            step_wise_probing(self.n_projections, self._multi_probe_budget)
+        } else {
+            vec![]
+        };
+
+        let hashes = H::hash_input_query_tables(&self.hashers, x);
+        for (i, original_hash) in hashes.into_iter().enumerate() {
+            self.process_bucket_union_result(&original_hash, i, &mut bucket_union)?;
+            for pertub in &probing_seq {
+                let hash = original_hash
+                    .iter()
+                    .zip(pertub)
+                    .map(|(&a, &b)| a.saturating_add(b))
+                    .collect();
+                self.process_bucket_union_result(&hash, i, &mut bucket_union)?;
+            }
+        }
+        Ok(bucket_union)
+    }
+
+    /// Query all buckets in the hash tables with a data point of any
+    /// [Input](input/trait.Input.html) type and return the data point indexes. The union of the
+    /// matching buckets of `L` hash tables is returned.
+    ///
+    /// Multi-probing uses step wise probing for all inputs.
+    ///
+    /// # Arguments
+    /// * `x` - Query data point.
+    pub fn query_input<I: Input<N> + ?Sized>(&self, x: &I) -> Result<Vec<u32>>
+    where
+        H: InputHash<I>,
+    {
+        let bucket_union = self.query_input_union(x)?;
+        Ok(bucket_union.iter().copied().collect())
+    }
+
+    /// Query all buckets in the hash tables with a custom [Input](input/trait.Input.html) data
+    /// point. The stored data points of the union of the matching buckets over the `L` hash
+    /// tables are deserialized as `O`, i.e. `String` for `str` data points.
+    ///
+    /// # Arguments
+    /// * `x` - Query data point.
+    pub fn query_input_bucket<I, O>(&self, x: &I) -> Result<Vec<O>>
+    where
+        I: Input<N> + ?Sized,
+        H: InputHash<I>,
+        O: DeserializeOwned,
+    {
+        if self.only_index_storage {
+            return Err(Error::Failed(
+                "cannot query bucket, use query_input".to_string(),
+            ));
+        }
+        let bucket_union = self.query_input_union(x)?;
+        let ht = self.hash_tables.as_ref().unwrap();
+        bucket_union
+            .iter()
+            .map(|&idx| Ok(bincode::deserialize(ht.idx_to_input(idx)?)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LshMem, LshSqlMem};
+    use serde::Deserialize;
+
+    /// Hashes the characters at fixed positions of a string.
+    #[derive(Serialize, Deserialize)]
+    struct CharSampling {
+        positions: Vec<usize>,
+    }
+
+    impl InputHash<str> for CharSampling {
+        fn hash_input_query(&self, s: &str) -> Hash {
+            let bytes = s.as_bytes();
+            self.positions
+                .iter()
+                .map(|&p| bytes.get(p).copied().unwrap_or(0) as i8)
+                .collect()
+        }
+    }
+
+    fn hashers() -> Vec<CharSampling> {
+        vec![
+            CharSampling {
+                positions: vec![0, 1],
+            },
+            CharSampling {
+                positions: vec![2, 3],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_custom_input() {
+        let mut lsh = LshMem::<f32, _>::new(2, 2, 0)
+            .only_index()
+            .custom_hashers(hashers())
+            .unwrap();
+        let ids = lsh.store_inputs(&["abcd", "abxy", "zzzz"]).unwrap();
+        assert_eq!(ids, vec![0, 1, 2]);
+
+        let mut q = lsh.query_input("abcz").unwrap();
+        q.sort_unstable();
+        assert_eq!(q, vec![0, 1]);
+        assert_eq!(lsh.query_input("xxcd").unwrap(), vec![0]);
+        assert!(lsh.query_input("....").unwrap().is_empty());
+        assert_eq!(lsh.stats().unwrap().n_points, 3);
+
+        lsh.update_input_by_idx(2, "..cd", "zzzz").unwrap();
+        let mut q = lsh.query_input("xxcd").unwrap();
+        q.sort_unstable();
+        assert_eq!(q, vec![0, 2]);
+
+        // step wise probing changes the hash values by one.
+        assert!(lsh.query_input("abcc").unwrap().contains(&1));
+        assert!(lsh.query_input("xxcc").unwrap().is_empty());
+        lsh.multi_probe(4);
+        assert_eq!(lsh.query_input("xxcc").unwrap().len(), 2);
+
+        // one hasher per hash table
+        assert!(LshMem::<f32, CharSampling>::new(2, 3, 0)
+            .custom_hashers(hashers())
+            .is_err());
+        assert!(lsh.query_input_bucket::<_, String>("abcd").is_err());
+
+        // the memory backend stores the custom inputs
+        let mut lsh = LshMem::<f32, _>::new(2, 2, 0)
+            .custom_hashers(hashers())
+            .unwrap();
+        lsh.store_inputs(&["abcd", "abxy", "zzzz"]).unwrap();
+        let mut docs: Vec<String> = lsh.query_input_bucket("abcz").unwrap();
+        docs.sort_unstable();
+        assert_eq!(docs, vec!["abcd", "abxy"]);
+        let mut loaded = LshMem::<f32, _>::new(2, 2, 0)
+            .custom_hashers(hashers())
+            .unwrap();
+        loaded.load_bytes(&lsh.dump_bytes().unwrap()).unwrap();
+        let docs: Vec<String> = loaded.query_input_bucket("zzzz").unwrap();
+        assert_eq!(docs, vec!["zzzz"]);
+
+        let mut lsh = LshSqlMem::<f32, _>::new(2, 2, 0)
+            .custom_hashers(hashers())
+            .unwrap();
+        assert_eq!(lsh.store_input("abcd").unwrap(), 0);
+        assert_eq!(lsh.query_input("abxx").unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn test_dense_sparse_input() {
+        let v = vec![1., 0., 2.];
+        let sv = SparseVec::from_dense(&v);
+        let mut lsh = LshMem::<f32, _>::new(4, 3, 3).seed(1).srp().unwrap();
+        assert_eq!(lsh.store_input(v.as_slice()).unwrap(), 0);
+        assert_eq!(
+            lsh.query_input(&sv).unwrap(),
+            lsh.query_bucket_ids(&v).unwrap()
+        );
+        assert!(lsh.store_input(&[1., 2.][..]).is_err());
+        // the old data point is hashed as well
+        assert!(lsh
+            .update_input_by_idx(0, v.as_slice(), &[1., 2.][..])
+            .is_err());
+        assert!(lsh.store_input(&sv).is_err());
+        assert_eq!(lsh.query_bucket(&v).unwrap(), vec![&v]);
+    }
+}
@@ -0,0 +1,365 @@
+//! Readers and writers for the `.fvecs`, `.ivecs` and `.bvecs` files of the standard ANN
+//! benchmark data sets (SIFT1M, GIST1M, Deep1B). [Read more.](http://corpus-texmex.irisa.fr/)
+//!
+//! Every vector is stored as a little endian `i32` dimension followed by `dim` values. The values
+//! are `f32` in `.fvecs`, `i32` in `.ivecs` and `u8` in `.bvecs` files.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use lsh_rs::{LshMem, eval::{evaluate, Metric}, io::{read_fvecs, read_ivecs}};
+//! let mut lsh = LshMem::<f32, _>::new(16, 20, 128).seed(1).l2(4.).unwrap();
+//! // stream the base vectors in chunks of 10,000.
+//! lsh.store_vecs_file("sift_base.fvecs", 10_000).unwrap();
+//!
+//! let qs = read_fvecs("sift_query.fvecs").unwrap();
+//! let ground_truth = read_ivecs("sift_groundtruth.ivecs").unwrap();
+//! ```
+use crate::{data::Numeric, Error, Result};
+use ndarray::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Value type of a vecs file.
+pub trait VecsElement: Numeric {
+    /// Number of bytes of a single value.
+    const SIZE: usize;
+
+    fn from_le_slice(b: &[u8]) -> Self;
+
+    fn write_le<W: Write>(self, w: &mut W) -> std::io::Result<()>;
+}
+
+impl VecsElement for f32 {
+    const SIZE: usize = 4;
+
+    fn from_le_slice(b: &[u8]) -> Self {
+        f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    fn write_le<W: Write>(self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl VecsElement for f64 {
+    const SIZE: usize = 8;
+
+    fn from_le_slice(b: &[u8]) -> Self {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&b[..8]);
+        f64::from_le_bytes(bytes)
+    }
+
+    fn write_le<W: Write>(self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl VecsElement for i32 {
+    const SIZE: usize = 4;
+
+    fn from_le_slice(b: &[u8]) -> Self {
+        i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    fn write_le<W: Write>(self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl VecsElement for u8 {
+    const SIZE: usize = 1;
+
+    fn from_le_slice(b: &[u8]) -> Self {
+        b[0]
+    }
+
+    fn write_le<W: Write>(self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&[self])
+    }
+}
+
+/// Fill `buf` completely. Returns `false` if the reader is at the end before reading a single byte.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(Error::Failed("vecs file is truncated".to_string())),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Streaming reader of vecs files. Vectors are read one by one or in chunks, so that files
+/// larger than memory can be indexed.
+///
+/// # Example
+///
+/// ```no_run
+/// use lsh_rs::io::FvecsReader;
+/// let mut reader = FvecsReader::open("sift_base.fvecs").unwrap();
+/// while let Some(chunk) = reader.read_chunk::<f32>(10_000).unwrap() {
+///     println!("{:?}", chunk.dim());
+/// }
+/// ```
+pub struct VecsReader<T, R> {
+    reader: R,
+    dim: Option<usize>,
+    _type: PhantomData<T>,
+}
+
+pub type FvecsReader<R> = VecsReader<f32, R>;
+pub type IvecsReader<R> = VecsReader<i32, R>;
+pub type BvecsReader<R> = VecsReader<u8, R>;
+
+impl<T: VecsElement> VecsReader<T, BufReader<File>> {
+    /// Open a vecs file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(VecsReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<T: VecsElement, R: Read> VecsReader<T, R> {
+    pub fn new(reader: R) -> Self {
+        VecsReader {
+            reader,
+            dim: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// Dimension of the vectors. Known after the first vector is read.
+    pub fn dim(&self) -> Option<usize> {
+        self.dim
+    }
+
+    /// Read the next vector. Returns `None` at the end of the file.
+    pub fn read_vec(&mut self) -> Result<Option<Vec<T>>> {
+        let mut dim_buf = [0u8; 4];
+        if !read_exact_or_eof(&mut self.reader, &mut dim_buf)? {
+            return Ok(None);
+        }
+        let dim = i32::from_le_bytes(dim_buf);
+        if dim <= 0 {
+            return Err(Error::Failed(format!("invalid vector dimension: {}", dim)));
+        }
+        let dim = dim as usize;
+        match self.dim {
+            Some(d) if d != dim => {
+                return Err(Error::Failed(format!(
+                    "vector dimensions differ: {} and {}",
+                    d, dim
+                )))
+            }
+            _ => self.dim = Some(dim),
+        }
+
+        let n_bytes = dim
+            .checked_mul(T::SIZE)
+            .ok_or_else(|| Error::Failed(format!("invalid vector dimension: {}", dim)))?;
+        // The buffer grows while the values are read, so that the dimension of a corrupt file
+        // can not allocate more memory than the file holds.
+        let mut buf = Vec::with_capacity(n_bytes.min(1 << 20));
+        (&mut self.reader)
+            .take(n_bytes as u64)
+            .read_to_end(&mut buf)?;
+        if buf.len() != n_bytes {
+            return Err(Error::Failed("vecs file is truncated".to_string()));
+        }
+        Ok(Some(
+            buf.chunks_exact(T::SIZE).map(T::from_le_slice).collect(),
+        ))
+    }
+
+    /// Read up to `n` vectors in a 2D array with a vector per row. The values are cast to `N`.
+    /// Returns `None` at the end of the file.
+    pub fn read_chunk<N: Numeric>(&mut self, n: usize) -> Result<Option<Array2<N>>> {
+        let mut values = vec![];
+        let mut rows = 0;
+        while rows < n {
+            match self.read_vec()? {
+                Some(v) => {
+                    for x in v {
+                        values.push(N::from(x).ok_or_else(|| {
+                            Error::Failed(format!("value {} does not fit the data type", x))
+                        })?)
+                    }
+                    rows += 1;
+                }
+                None => break,
+            }
+        }
+        if rows == 0 {
+            return Ok(None);
+        }
+        let dim = self.dim.unwrap();
+        let arr = Array2::from_shape_vec((rows, dim), values)
+            .map_err(|e| Error::Failed(e.to_string()))?;
+        Ok(Some(arr))
+    }
+}
+
+impl<T: VecsElement, R: Read> Iterator for VecsReader<T, R> {
+    type Item = Result<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_vec().transpose()
+    }
+}
+
+/// Writer of vecs files.
+pub struct VecsWriter<T, W: Write> {
+    writer: W,
+    _type: PhantomData<T>,
+}
+
+impl<T: VecsElement> VecsWriter<T, BufWriter<File>> {
+    /// Create a vecs file. An existing file is truncated.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(VecsWriter::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Append to a vecs file. The file is created if it does not exist.
+    pub fn append<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(VecsWriter::new(BufWriter::new(f)))
+    }
+}
+
+impl<T: VecsElement, W: Write> VecsWriter<T, W> {
+    pub fn new(writer: W) -> Self {
+        VecsWriter {
+            writer,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn write_vec(&mut self, v: &[T]) -> Result<()> {
+        self.writer.write_all(&(v.len() as i32).to_le_bytes())?;
+        for &x in v {
+            x.write_le(&mut self.writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a complete vecs file in memory.
+pub fn read_vecs<T: VecsElement, P: AsRef<Path>>(path: P) -> Result<Vec<Vec<T>>> {
+    VecsReader::open(path)?.collect()
+}
+
+/// Read a complete `.fvecs` file in memory.
+pub fn read_fvecs<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<f32>>> {
+    read_vecs(path)
+}
+
+/// Read the nearest neighbor ids of a ground truth `.ivecs` file. Negative ids are an error.
+pub fn read_ivecs<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u32>>> {
+    VecsReader::<i32, _>::open(path)?
+        .map(|v| {
+            v?.into_iter()
+                .map(|x| {
+                    num::cast(x)
+                        .ok_or_else(|| Error::Failed(format!("invalid id in ivecs file: {}", x)))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Write vectors to a vecs file.
+pub fn write_vecs<T: VecsElement, P: AsRef<Path>>(path: P, vs: &[Vec<T>]) -> Result<()> {
+    let mut writer = VecsWriter::create(path)?;
+    for v in vs {
+        writer.write_vec(v)?;
+    }
+    writer.flush()
+}
+
+/// Write the nearest neighbor ids to a ground truth `.ivecs` file.
+/// See [brute_force_knn](../eval/fn.brute_force_knn.html).
+pub fn write_ivecs<P: AsRef<Path>>(path: P, ground_truth: &[Vec<u32>]) -> Result<()> {
+    let mut writer = VecsWriter::<i32, _>::create(path)?;
+    for ids in ground_truth {
+        let ids = ids
+            .iter()
+            .map(|&x| {
+                num::cast(x).ok_or_else(|| Error::Failed(format!("id {} does not fit in ivecs", x)))
+            })
+            .collect::<Result<Vec<i32>>>()?;
+        writer.write_vec(&ids)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_vecs_round_trip() {
+        let vs = [vec![1., 2.5, -3.], vec![0.5, 0., 1e6]];
+        let mut writer = VecsWriter::<f32, _>::new(vec![]);
+        vs.iter().for_each(|v| writer.write_vec(v).unwrap());
+        let buf = writer.writer;
+        assert_eq!(buf.len(), 2 * (4 + 3 * 4));
+
+        let mut reader = FvecsReader::new(Cursor::new(&buf));
+        let chunk = reader.read_chunk::<f64>(5).unwrap().unwrap();
+        assert_eq!(chunk, array![[1., 2.5, -3.], [0.5, 0., 1e6]]);
+        assert!(reader.read_chunk::<f64>(5).unwrap().is_none());
+
+        // truncated file
+        let mut reader = FvecsReader::new(Cursor::new(&buf[..buf.len() - 1]));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+
+        // a corrupt dimension is not allocated up front
+        let mut corrupt = i32::MAX.to_le_bytes().to_vec();
+        corrupt.extend_from_slice(&[0; 8]);
+        let mut reader = FvecsReader::new(Cursor::new(&corrupt));
+        assert!(reader.read_vec().is_err());
+        // the dimension differs from the first vector
+        let mut differs = buf[..16].to_vec();
+        differs.extend_from_slice(&1i32.to_le_bytes());
+        differs.extend_from_slice(&1f32.to_le_bytes());
+        let mut reader = FvecsReader::new(Cursor::new(&differs));
+        assert!(reader.read_vec().unwrap().is_some());
+        assert!(reader.read_vec().is_err());
+    }
+
+    #[test]
+    fn test_ivecs_file() {
+        let path = std::env::temp_dir().join("lsh_rs_test_ground_truth.ivecs");
+        let gt = vec![vec![3, 1, 2], vec![0, 5, 4]];
+        write_ivecs(&path, &gt).unwrap();
+        assert_eq!(read_ivecs(&path).unwrap(), gt);
+
+        let mut writer = VecsWriter::<i32, _>::append(&path).unwrap();
+        writer.write_vec(&[6, 7, 8]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(
+            read_ivecs(&path).unwrap(),
+            vec![gt[0].clone(), gt[1].clone(), vec![6, 7, 8]]
+        );
+
+        // negative ids
+        write_vecs(&path, &[vec![1i32, -2]]).unwrap();
+        assert!(read_ivecs(&path).is_err());
+        assert!(write_ivecs(&path, &[vec![u32::MAX]]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -11,16 +11,29 @@
 //!
 //! * **Base LSH**
 //!     - Signed Random Projections (Cosine similarity)
+//!     - Super-Bit LSH (orthogonalized Signed Random Projections)
 //!     - L2 distance
+//!     - L1 distance (Manhattan)
 //!     - Maximum Inner Product (Dot products)
+//!     - Bit Sampling (Hamming distance)
+//!     - Weighted MinHash (Generalized Jaccard similarity)
+//!     - Densified One Permutation Hashing (Jaccard similarity)
+//!     - Densified Winner Take All (Sparse activations)
+//!     - PCA-hash and Iterative Quantization (learned projections)
 //! * **Multi Probe LSH**
 //!     - **Step wise probing**
 //!         - SRP
 //!         - L2
+//!         - L1
 //!         - MIPS
 //!     - **Query directed probing**
 //!         - L2
+//!         - L1
+//! * **LSH Forest** (self-tuning hash length)
+//! * [Near-duplicate clustering](cluster/index.html) (connected components of verified bucket collisions)
 //! * Generic numeric types
+//! * [Sparse data points](sparse/index.html) (SRP, L2, MinHash and DWTA)
+//! * [Custom data point types](input/index.html) (documents, strings, sets or graphs)
 //!
 //! ## Getting started
 //!
@@ -66,6 +79,18 @@
 //! let mut lsh = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim).l2(bucket_width).unwrap();
 //! ```
 //!
+//! ## L1
+//! LSH for minimal L1 (Manhattan) distance search.
+//!
+//! ```
+//! use lsh_rs::LshMem;
+//! let bucket_width = 4.;
+//! let n_projections = 9;
+//! let n_hash_tables = 10;
+//! let dim = 10;
+//! let mut lsh = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim).l1(bucket_width).unwrap();
+//! ```
+//!
 //! ## Maximum Inner Product (MIPS)
 //! LSH for maximum inner product search.
 //! ```rust
@@ -82,6 +107,61 @@
 //! let mut lsh: LshMem<f32, _> = LshMem::new(n_projections, n_hash_tables, dim).mips(r, U, m).unwrap();
 //! ```
 //!
+//! ## Weighted MinHash
+//! LSH for the generalized Jaccard similarity of non-negative data points, i.e. term counts.
+//! Candidates can be re-ranked with [generalized_jaccard](dist/fn.generalized_jaccard.html).
+//! ```rust
+//! use lsh_rs::{LshMem, SparseVec};
+//! let n_projections = 4;
+//! let n_hash_tables = 10;
+//! let dim = 50_000;
+//! let mut lsh = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim).weighted_minhash().unwrap();
+//! let counts = SparseVec::new(vec![12, 800, 4021], vec![3., 1., 2.]).unwrap();
+//! lsh.store_sparse(&counts).unwrap();
+//! ```
+//!
+//! ## Densified MinHash
+//! LSH for the Jaccard similarity of sets. The elements of a set are the non-zero indexes of a
+//! data point. One pass over a set computes all `K` hash values of a hash table.
+//! ```rust
+//! use lsh_rs::{LshMem, SparseVec};
+//! let n_projections = 4;
+//! let n_hash_tables = 10;
+//! let dim = 1_000_000;
+//! let mut lsh = LshMem::<u8, _>::new(n_projections, n_hash_tables, dim).densified_minhash().unwrap();
+//! let set = SparseVec::new(vec![10, 2_000, 900_000], vec![1, 1, 1]).unwrap();
+//! lsh.store_sparse(&set).unwrap();
+//! ```
+//!
+//! ## Iterative Quantization
+//! Data dependent LSH for cosine similarity. The projections are learned from the data points.
+//! ```rust
+//! use lsh_rs::LshMem;
+//! use ndarray::prelude::*;
+//! let x = array![[1., 2., 0.5], [-1., 0.3, 2.], [0.9, 2.1, 0.4], [-0.8, 0.1, 2.2]];
+//! let mut lsh = LshMem::<f64, _>::new(2, 5, 3).seed(1).itq(50).unwrap();
+//! for h in lsh.hashers.iter_mut() {
+//!     h.fit(&x.view()).unwrap();
+//! }
+//! lsh.store_array(x.view()).unwrap();
+//! ```
+//!
+//! ## Bit Sampling
+//! LSH for minimal Hamming distance search between binary vectors, i.e. perceptual hashes or
+//! SimHash fingerprints. The bits are packed in `u64` words and `dim` is the number of words.
+//! ```rust
+//! use lsh_rs::{dist::hamming, LshMem};
+//! let n_bits = 128;
+//! let n_projections = 16;
+//! let n_hash_tables = 10;
+//! let dim = 2;
+//! let mut lsh = LshMem::new(n_projections, n_hash_tables, dim).bit_sampling(n_bits).unwrap();
+//! let v = vec![0xdead_beef, 0xff];
+//! lsh.store_vec(&v).unwrap();
+//! let q = vec![0xdead_beee, 0xff];
+//! assert_eq!(hamming(&v, &q), 1);
+//! ```
+//!
 //! ## Seed
 //! Random projections are used to generate the hash functions. The default seeding of randomness
 //! is taken from the system. If you want to have reproducable outcomes, you can set a manual seed.
@@ -106,12 +186,53 @@
 //! let mut lsh = LshMem::<f32, _>::new(n_projections, n_hash_tables, dim).only_index().srp();
 //! ```
 //!
+//! ## LSH Forest
+//! Choosing the number of projections `K` is hard. An [LSH Forest](struct.LshForest.html) stores
+//! the hashes in sorted prefix trees and backs off to shorter hash prefixes until enough
+//! candidates are found. Here `K` only is the maximum prefix length.
+//! ```rust
+//! use lsh_rs::LshForest;
+//! let max_projections = 32;
+//! let n_trees = 10;
+//! let dim = 10;
+//! let mut forest = LshForest::<f32, _>::new(max_projections, n_trees, dim).srp().unwrap();
+//! ```
+//!
+//! ## Near-duplicate clustering
+//! [cluster](struct.LSH.html#method.cluster) verifies all pairs of ids that share a bucket with an
+//! exact distance and returns the connected components of the near-duplicates. See the
+//! [cluster](cluster/index.html) module.
+//! ```rust
+//! use lsh_rs::{eval::Metric, LshMem};
+//! let vs = vec![vec![1., 0.5, 2.], vec![1., 0.5, 2.01], vec![-1., 3., 0.]];
+//! let mut lsh = LshMem::new(9, 10, 3).seed(1).srp().unwrap();
+//! lsh.store_vecs(&vs).unwrap();
+//! let clusters = lsh.cluster(Metric::Cosine, 0.01).unwrap();
+//! assert_eq!(clusters.labels, vec![0, 0, 2]);
+//! ```
+//!
+//! ## Parameter tuning
+//! The [tune](tune/index.html) module searches `K`, `L`, `r` and the multi-probe budget that reach
+//! a target recall@k within a memory limit. The recall is measured against brute-force ground
+//! truth, see the [eval](eval/index.html) module.
+//!
+//! ## Benchmark data sets
+//! The `.fvecs`, `.ivecs` and `.bvecs` files of the standard ANN benchmarks (SIFT1M, GIST1M,
+//! Deep1B) can be streamed in an index with
+//! [store_vecs_file](struct.LSH.html#method.store_vecs_file). Ground truth is read and written
+//! with the [io](io/index.html) module.
+//!
+//! ## NumPy
+//! Memory mapped `.npy` files can be stored with [store_npy](struct.LSH.html#method.store_npy).
+//! See the [npy](npy/index.html) module for reading and writing `.npy` and `.npz` files.
+//!
 //! ## Builder pattern methods
 //! The following methods can be used to change internal state during object initialization:
 //! * [only_index](struct.LSH.html#method.only_index)
 //! * [seed](struct.LSH.html#method.seed)
 //! * [set_database_file](struct.LSH.html#method.set_database_file)
 //! * [multi_probe](struct.LSH.html#method.multi_probe)
+//! * [super_bit](struct.LSH.html#method.super_bit)
 //! * [increase_storage](struct.LSH.html#method.increase_storage)
 //!
 //! ## BLAS support
@@ -136,26 +257,47 @@
 extern crate blas_src;
 extern crate ndarray;
 mod hash;
+mod linalg;
 mod lsh {
+    pub mod forest;
     pub mod lsh;
     mod test;
 }
+pub mod cluster;
 pub mod dist;
+pub mod input;
 mod multi_probe;
+pub mod sparse;
 mod table {
     pub mod general;
     pub mod mem;
     pub mod sqlite;
     pub mod sqlite_mem;
 }
-mod constants;
 mod error;
 pub mod utils;
-pub use crate::lsh::lsh::{LshMem, LshSql, LshSqlMem, LSH};
-pub use hash::{Hash, HashPrimitive, SignRandomProjections, VecHash, L2, MIPS};
-pub use table::{general::HashTables, mem::MemoryTable, sqlite::SqlTable, sqlite_mem::SqlTableMem};
+pub use crate::lsh::{
+    forest::LshForest,
+    lsh::{LshMem, LshSql, LshSqlMem, Persist, LSH},
+};
+pub use hash::{
+    BitSampling, DensifiedMinHash, Hash, HashPrimitive, SignRandomProjections, VecHash,
+    WeightedMinHash, DWTA, ITQ, L1, L2, MIPS,
+};
+pub use input::{Input, InputHash};
+pub use sparse::{SparseVec, SparseVecHash};
+pub use table::{
+    general::{HashTables, Stats, TableStats},
+    mem::MemoryTable,
+    sqlite::SqlTable,
+    sqlite_mem::SqlTableMem,
+};
 pub mod data;
+pub mod eval;
+pub mod io;
+pub mod npy;
 pub mod stats;
+pub mod tune;
 
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
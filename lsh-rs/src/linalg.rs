@@ -0,0 +1,173 @@
+//! Small dense linear algebra routines for the data dependent hashers.
+use ndarray::prelude::*;
+use ndarray_rand::rand_distr::StandardNormal;
+use ndarray_rand::RandomExt;
+use rand::Rng;
+
+/// Orthonormalize the columns of `a` with modified Gram-Schmidt.
+pub(crate) fn orthonormalize(a: &mut Array2<f64>) {
+    for j in 0..a.ncols() {
+        let mut v = a.column(j).to_owned();
+        for i in 0..j {
+            let u = a.column(i);
+            v.scaled_add(-u.dot(&v), &u);
+        }
+        let norm = v.dot(&v).sqrt();
+        if norm > 0. {
+            v /= norm;
+        }
+        a.column_mut(j).assign(&v);
+    }
+}
+
+/// Eigen decomposition of a symmetric matrix with the cyclic Jacobi method.
+/// Returns the eigenvalues in descending order and the eigenvectors as columns.
+pub(crate) fn sym_eig(a: &Array2<f64>) -> (Array1<f64>, Array2<f64>) {
+    let n = a.nrows();
+    let mut a = a.clone();
+    let mut v = Array2::eye(n);
+
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|p| (0..n).filter(move |&q| q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[[p, q]].powi(2))
+            .sum();
+        if off < 1e-22 {
+            break;
+        }
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if a[[p, q]].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[[q, q]] - a[[p, p]]) / (2. * a[[p, q]]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                // A = Jᵀ A J
+                for k in 0..n {
+                    let (akp, akq) = (a[[k, p]], a[[k, q]]);
+                    a[[k, p]] = c * akp - s * akq;
+                    a[[k, q]] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[[p, k]], a[[q, k]]);
+                    a[[p, k]] = c * apk - s * aqk;
+                    a[[q, k]] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[[k, p]], v[[k, q]]);
+                    v[[k, p]] = c * vkp - s * vkq;
+                    v[[k, q]] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_unstable_by(|&i, &j| a[[j, j]].partial_cmp(&a[[i, i]]).unwrap());
+    let values = order.iter().map(|&i| a[[i, i]]).collect();
+    let vectors = v.select(Axis(1), &order);
+    (values, vectors)
+}
+
+/// Top `k` eigenvectors (as columns) of a symmetric positive semi-definite matrix with subspace
+/// iteration.
+pub(crate) fn top_eigenvectors<R: Rng>(a: &Array2<f64>, k: usize, rng: &mut R) -> Array2<f64> {
+    let mut q = Array2::random_using((a.nrows(), k), StandardNormal, rng);
+    orthonormalize(&mut q);
+    for _ in 0..100 {
+        q = a.dot(&q);
+        orthonormalize(&mut q);
+    }
+    // Rayleigh-Ritz to order the vectors by eigenvalue.
+    let (_, w) = sym_eig(&q.t().dot(a).dot(&q));
+    q.dot(&w)
+}
+
+/// Orthogonal factor `Q` of the polar decomposition `A = QP` of a square matrix. This is the
+/// orthogonal matrix closest to `A`.
+pub(crate) fn polar(a: &Array2<f64>) -> Array2<f64> {
+    // With the SVD A = UΣVᵀ, Q = UVᵀ. V and Σ² are the eigen decomposition of AᵀA.
+    let n = a.nrows();
+    let (values, v) = sym_eig(&a.t().dot(a));
+    let mut u = a.dot(&v);
+    let rank = values
+        .iter()
+        .filter(|&&s| s > 1e-12 * values[0].max(1.))
+        .count();
+    for j in 0..n {
+        if j < rank {
+            let mut col = u.column_mut(j);
+            col /= values[j].sqrt();
+        } else {
+            u.column_mut(j).fill(0.);
+        }
+    }
+    // complete U with the standard basis if A is singular.
+    let mut e = 0;
+    for j in rank..n {
+        loop {
+            let mut col = Array1::zeros(n);
+            col[e] = 1.;
+            e += 1;
+            for i in 0..j {
+                let ui = u.column(i);
+                col.scaled_add(-ui.dot(&col), &ui);
+            }
+            let norm = col.dot(&col).sqrt();
+            if norm > 1e-6 {
+                u.column_mut(j).assign(&(col / norm));
+                break;
+            }
+        }
+    }
+    u.dot(&v.t())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_rng;
+
+    fn assert_close(a: &Array2<f64>, b: &Array2<f64>) {
+        assert!((a - b).iter().all(|x| x.abs() < 1e-8), "{} {}", a, b);
+    }
+
+    #[test]
+    fn test_sym_eig() {
+        let a = arr2(&[[4., 1., 0.5], [1., 3., 0.], [0.5, 0., 1.]]);
+        let (values, vectors) = sym_eig(&a);
+        assert!(values[0] >= values[1] && values[1] >= values[2]);
+        assert_close(&vectors.t().dot(&vectors), &Array2::eye(3));
+        assert_close(&(&vectors * &values).dot(&vectors.t()), &a);
+    }
+
+    #[test]
+    fn test_top_eigenvectors() {
+        let a = arr2(&[[4., 1., 0.5], [1., 3., 0.], [0.5, 0., 1.]]);
+        let (_, vectors) = sym_eig(&a);
+        let q = top_eigenvectors(&a, 2, &mut create_rng(1));
+        for j in 0..2 {
+            // equal up to the sign
+            assert!((q.column(j).dot(&vectors.column(j)).abs() - 1.).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_polar() {
+        let a = arr2(&[[2., 1.], [-1., 3.]]);
+        let q = polar(&a);
+        assert_close(&q.t().dot(&q), &Array2::eye(2));
+        // P = QᵀA is symmetric
+        let p = q.t().dot(&a);
+        assert_close(&p, &p.t().to_owned());
+
+        // singular matrices still have an orthogonal factor
+        let a = arr2(&[[1., 1., 0.], [1., 1., 0.], [0., 0., 2.]]);
+        let q = polar(&a);
+        assert_close(&q.t().dot(&q), &Array2::eye(3));
+        let p = q.t().dot(&a);
+        assert_close(&p, &p.t().to_owned());
+    }
+}
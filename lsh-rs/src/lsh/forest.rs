@@ -0,0 +1,330 @@
+use crate::{
+    data::Numeric,
+    hash::{Hash, SignRandomProjections, VecHash, L2, MIPS},
+    table::general::Bucket,
+    utils::create_rng,
+    Error, Result,
+};
+use fnv::FnvHashSet as HashSet;
+use ndarray::prelude::*;
+use num::Float;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+/// A single prefix tree of the forest. Hashes are kept sorted so that all hashes sharing a
+/// prefix are stored contiguously.
+type Tree = BTreeMap<Hash, Bucket>;
+
+/// LSH Forest. [Read more.](http://infolab.stanford.edu/~bawa/Pub/similarity.pdf)
+///
+/// Instead of requiring an exact match on all `K` hash values, every tree stores the full hashes
+/// in sorted order. A query descends to the longest matching prefix in every tree and backs off
+/// to shorter prefixes until enough candidates are found. This makes the choice of `K`
+/// (`n_projections`) far less critical; `K` only is an upper bound on the prefix length.
+///
+/// # Example
+///
+/// ```
+/// use lsh_rs::LshForest;
+/// let n_projections = 16;
+/// let n_trees = 10;
+/// let dim = 3;
+/// let mut forest = LshForest::new(n_projections, n_trees, dim).seed(1).srp().unwrap();
+/// forest.store_vecs(&[vec![1., 1.5, 2.], vec![2., 1.1, -0.3]]).unwrap();
+///
+/// // Retrieve at least 1 candidate.
+/// let ids = forest.query_bucket_ids(&[1.1, 1.2, 1.2], 1).unwrap();
+/// ```
+#[derive(Serialize, Deserialize)]
+pub struct LshForest<N, H> {
+    /// Number of prefix trees. `L` in literature.
+    pub n_trees: usize,
+    /// Maximum prefix length. `K` in literature.
+    pub n_projections: usize,
+    /// Hash functions.
+    pub hashers: Vec<H>,
+    /// Dimensions of p and q
+    pub dim: usize,
+    trees: Vec<Tree>,
+    counter: u32,
+    /// seed for hash functions. If 0, randomness is seeded from the os.
+    _seed: u64,
+    _type: PhantomData<N>,
+}
+
+/// Length of the common prefix of two hashes.
+fn common_prefix_len(a: &[i8], b: &[i8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Longest prefix of `hash` that is shared with any hash in the tree.
+/// As the hashes are sorted, the longest match is found with the predecessor or successor.
+fn longest_prefix_match(tree: &Tree, hash: &Hash) -> usize {
+    let before = tree
+        .range(..=hash.clone())
+        .next_back()
+        .map(|(k, _)| common_prefix_len(k, hash))
+        .unwrap_or(0);
+    let after = tree
+        .range(hash.clone()..)
+        .next()
+        .map(|(k, _)| common_prefix_len(k, hash))
+        .unwrap_or(0);
+    std::cmp::max(before, after)
+}
+
+/// Add all indexes stored under the given prefix to the candidate set.
+fn collect_prefix(tree: &Tree, prefix: &[i8], candidates: &mut Bucket) {
+    for (_, bucket) in tree
+        .range(prefix.to_vec()..)
+        .take_while(|(k, _)| k.starts_with(prefix))
+    {
+        candidates.extend(bucket.iter().copied())
+    }
+}
+
+impl<N: Numeric, H: VecHash<N>> LshForest<N, H> {
+    /// Create a new LSH Forest.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_projections` - Maximum hash length/ prefix length.
+    /// * `n_trees` - Number of prefix trees.
+    /// * `dim` - Dimensions of the data points.
+    pub fn new(n_projections: usize, n_trees: usize, dim: usize) -> Self {
+        LshForest {
+            n_trees,
+            n_projections,
+            hashers: Vec::with_capacity(0),
+            dim,
+            trees: Vec::with_capacity(0),
+            counter: 0,
+            _seed: 0,
+            _type: PhantomData,
+        }
+    }
+
+    fn validate_vec<A>(&self, v: &[A]) -> Result<()> {
+        if v.len() != self.dim {
+            return Err(Error::Failed(
+                "data point is not valid, are the dimensions correct?".to_string(),
+            ));
+        };
+        Ok(())
+    }
+
+    /// Set seed of the LSH Forest
+    /// # Arguments
+    /// * `seed` - Seed for the RNG's if 0, RNG's are seeded randomly.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self._seed = seed;
+        self
+    }
+
+    fn with_hashers(&self, hashers: Vec<H>) -> Self {
+        LshForest {
+            n_trees: self.n_trees,
+            n_projections: self.n_projections,
+            hashers,
+            dim: self.dim,
+            trees: vec![BTreeMap::new(); self.n_trees],
+            counter: 0,
+            _seed: self._seed,
+            _type: PhantomData,
+        }
+    }
+
+    /// Store a single vector. Returns id.
+    ///
+    /// # Arguments
+    /// * `v` - Data point.
+    pub fn store_vec(&mut self, v: &[N]) -> Result<u32> {
+        self.validate_vec(v)?;
+        let idx = self.counter;
+        let counter = idx
+            .checked_add(1)
+            .ok_or_else(|| Error::Failed("ids of the forest overflow u32".to_string()))?;
+        for (tree, proj) in self.trees.iter_mut().zip(&self.hashers) {
+            let hash = proj.hash_vec_put(v);
+            tree.entry(hash).or_default().insert(idx);
+        }
+        self.counter = counter;
+        Ok(idx)
+    }
+
+    /// Store multiple vectors. Returns the ids.
+    ///
+    /// # Arguments
+    /// * `vs` - Array of data points.
+    pub fn store_vecs(&mut self, vs: &[Vec<N>]) -> Result<Vec<u32>> {
+        vs.iter().map(|v| self.store_vec(v)).collect()
+    }
+
+    /// Store a 2D array. Returns the ids. Rows that are not contiguous in memory, i.e. of a
+    /// transposed view, are copied.
+    ///
+    /// # Arguments
+    /// * `vs` - Array of data points.
+    pub fn store_array(&mut self, vs: ArrayView2<N>) -> Result<Vec<u32>> {
+        vs.axis_iter(Axis(0))
+            .map(|v| match v.as_slice() {
+                Some(v) => self.store_vec(v),
+                None => self.store_vec(&v.to_vec()),
+            })
+            .collect()
+    }
+
+    /// Query the forest and return the data point indexes.
+    ///
+    /// All trees are descended to their longest matching prefix. Starting from the longest
+    /// prefix found, the candidates of all trees are collected. If fewer than `m` candidates are
+    /// found, the prefix is shortened by one and the collection is repeated.
+    ///
+    /// # Arguments
+    /// * `v` - Query vector
+    /// * `m` - Minimum number of candidates to retrieve (if the forest holds that many points).
+    pub fn query_bucket_ids(&self, v: &[N], m: usize) -> Result<Vec<u32>> {
+        self.validate_vec(v)?;
+        let hashes: Vec<Hash> = self.hashers.iter().map(|h| h.hash_vec_query(v)).collect();
+        let depths: Vec<usize> = self
+            .trees
+            .iter()
+            .zip(&hashes)
+            .map(|(tree, hash)| longest_prefix_match(tree, hash))
+            .collect();
+        let max_depth = depths.iter().copied().max().unwrap_or(0);
+
+        let mut candidates = HashSet::default();
+        for depth in (0..=max_depth).rev() {
+            for ((tree, hash), &tree_depth) in self.trees.iter().zip(&hashes).zip(&depths) {
+                if tree_depth >= depth {
+                    collect_prefix(tree, &hash[..depth], &mut candidates);
+                }
+            }
+            if candidates.len() >= m {
+                break;
+            }
+        }
+        Ok(candidates.into_iter().collect())
+    }
+
+    /// Query a batch of data points.
+    ///
+    /// # Arguments
+    /// * `vs` - Array of data points.
+    /// * `m` - Minimum number of candidates to retrieve per query.
+    pub fn query_bucket_ids_batch(&self, vs: &[Vec<N>], m: usize) -> Result<Vec<Vec<u32>>> {
+        vs.iter().map(|v| self.query_bucket_ids(v, m)).collect()
+    }
+}
+
+impl<N: Numeric, H: VecHash<N> + Sync> LshForest<N, H> {
+    /// Query a batch of data points in parallel.
+    ///
+    /// # Arguments
+    /// * `vs` - Array of data points.
+    /// * `m` - Minimum number of candidates to retrieve per query.
+    pub fn query_bucket_ids_batch_par(&self, vs: &[Vec<N>], m: usize) -> Result<Vec<Vec<u32>>> {
+        vs.into_par_iter()
+            .map(|v| self.query_bucket_ids(v, m))
+            .collect()
+    }
+}
+
+impl<N: Numeric> LshForest<N, SignRandomProjections<N>> {
+    /// Create a new SignRandomProjections LSH Forest
+    pub fn srp(&mut self) -> Result<Self> {
+        let mut rng = create_rng(self._seed);
+        let hashers = (0..self.n_trees)
+            .map(|_| SignRandomProjections::new(self.n_projections, self.dim, rng.gen()))
+            .collect();
+        Ok(self.with_hashers(hashers))
+    }
+}
+
+impl<N: Numeric + Float> LshForest<N, L2<N>> {
+    /// Create a new L2 LSH Forest
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - Parameter of hash function.
+    pub fn l2(&mut self, r: f32) -> Result<Self> {
+        let mut rng = create_rng(self._seed);
+        let hashers = (0..self.n_trees)
+            .map(|_| L2::new(self.dim, r, self.n_projections, rng.gen()))
+            .collect();
+        Ok(self.with_hashers(hashers))
+    }
+}
+
+impl<N: Numeric + Float> LshForest<N, MIPS<N>> {
+    /// Create a new MIPS LSH Forest
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - Parameter of hash function.
+    /// * `U` - Parameter of hash function.
+    /// * `m` - Parameter of hash function.
+    pub fn mips(&mut self, r: f32, U: N, m: usize) -> Result<Self> {
+        let mut rng = create_rng(self._seed);
+        let hashers = (0..self.n_trees)
+            .map(|_| MIPS::new(self.dim, r, U, m, self.n_projections, rng.gen()))
+            .collect();
+        Ok(self.with_hashers(hashers))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let mut tree = Tree::new();
+        tree.insert(vec![0, 1, 1, 0], HashSet::default());
+        tree.insert(vec![1, 1, 0, 0], HashSet::default());
+        assert_eq!(longest_prefix_match(&tree, &vec![0, 1, 0, 0]), 2);
+        assert_eq!(longest_prefix_match(&tree, &vec![1, 1, 0, 1]), 3);
+        assert_eq!(longest_prefix_match(&tree, &vec![1, 1, 0, 0]), 4);
+    }
+
+    #[test]
+    fn test_forest_backs_off() {
+        let mut forest = LshForest::new(16, 5, 3).seed(1).srp().unwrap();
+        let vs = vec![
+            vec![1., 2., 3.],
+            vec![1.1, 2., 3.1],
+            vec![-3., 1., -2.],
+            vec![2., -2., 0.5],
+        ];
+        forest.store_vecs(&vs).unwrap();
+
+        // exact match is found at full depth
+        assert!(forest.query_bucket_ids(&vs[0], 1).unwrap().contains(&0));
+        // backing off to the root returns everything
+        assert_eq!(forest.query_bucket_ids(&vs[0], 4).unwrap().len(), 4);
+        assert!(forest.query_bucket_ids(&[1., 2.], 1).is_err());
+    }
+
+    #[test]
+    fn test_store_array() {
+        let vs = array![[1., 1.1, -3.], [2., 2., 1.], [3., 3.1, -2.]];
+        let mut forest = LshForest::new(16, 5, 3).seed(1).srp().unwrap();
+        // the rows of a transposed view are not contiguous
+        assert_eq!(forest.store_array(vs.t()).unwrap(), vec![0, 1, 2]);
+        assert!(forest
+            .query_bucket_ids(&[1., 2., 3.], 1)
+            .unwrap()
+            .contains(&0));
+
+        forest.counter = u32::MAX;
+        assert!(forest.store_vec(&[1., 2., 3.]).is_err());
+        assert!(forest
+            .trees
+            .iter()
+            .all(|t| t.values().map(|b| b.len()).sum::<usize>() == 3));
+    }
+}
@@ -1,8 +1,17 @@
-use crate::table::general::Bucket;
+use crate::table::general::{Bucket, Stats};
 use crate::{
     data::Numeric,
-    hash::{Hash, SignRandomProjections, VecHash, L2, MIPS},
-    table::{general::HashTables, mem::MemoryTable, sqlite_mem::SqlTableMem},
+    hash::{
+        BitSampling, DensifiedMinHash, Hash, SignRandomProjections, VecHash, WeightedMinHash, DWTA,
+        ITQ, L1, L2, MIPS,
+    },
+    io::{BvecsReader, FvecsReader, VecsElement, VecsReader},
+    npy::NpyFile,
+    table::{
+        general::HashTables,
+        mem::{deserialize_tables, MemoryTable},
+        sqlite_mem::SqlTableMem,
+    },
     utils::create_rng,
     Error, Result, SqlTable,
 };
@@ -43,8 +52,9 @@ pub type LshMem<N, H> = LSH<N, MemoryTable<N>, H>;
 /// * [seed](struct.LSH.html#method.seed)
 /// * [set_database_file](struct.LSH.html#method.set_database_file)
 /// * [multi_probe](struct.LSH.html#method.multi_probe)
+/// * [super_bit](struct.LSH.html#method.super_bit)
 /// * [increase_storage](struct.LSH.html#method.increase_storage)
-pub struct LSH<N: Numeric, T: HashTables<N>, H: VecHash<N>> {
+pub struct LSH<N: Numeric, T: HashTables<N>, H> {
     /// Number of hash tables. `L` in literature.
     pub n_hash_tables: usize,
     /// Number of hash functions. `K` in literature.
@@ -58,20 +68,31 @@ pub struct LSH<N: Numeric, T: HashTables<N>, H: VecHash<N>> {
     /// seed for hash functions. If 0, randomness is seeded from the os.
     _seed: u64,
     /// store only indexes and no data points.
-    only_index_storage: bool,
-    _multi_probe: bool,
+    pub(crate) only_index_storage: bool,
+    pub(crate) _multi_probe: bool,
     /// multi probe budget
     pub(crate) _multi_probe_budget: usize,
+    /// orthogonalize SRP hyperplanes in groups of this size.
+    _super_bit_depth: Option<usize>,
     _db_path: String,
     // TODO: Delete
     _type: Option<N>,
 }
 
+fn validate_chunk_size(chunk_size: usize) -> Result<()> {
+    if chunk_size == 0 {
+        return Err(Error::Failed(
+            "chunk size should be larger than 0".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 /// Create a new LSH instance. Used in the builder pattern
 fn lsh_from_lsh<
     N: Numeric + DeserializeOwned,
     T: HashTables<N>,
-    H: VecHash<N> + Serialize + DeserializeOwned,
+    H: Serialize + DeserializeOwned,
 >(
     lsh: &mut LSH<N, T, H>,
     hashers: Vec<H>,
@@ -96,6 +117,7 @@ fn lsh_from_lsh<
         only_index_storage: lsh.only_index_storage,
         _multi_probe: lsh._multi_probe,
         _multi_probe_budget: lsh._multi_probe_budget,
+        _super_bit_depth: lsh._super_bit_depth,
         _db_path: lsh._db_path.clone(),
         _type: None,
     };
@@ -104,13 +126,28 @@ fn lsh_from_lsh<
 
 impl<N: Numeric + DeserializeOwned, T: HashTables<N>> LSH<N, T, SignRandomProjections<N>> {
     /// Create a new SignRandomProjections LSH
+    ///
+    /// Uses Super-Bit LSH if [super_bit](struct.LSH.html#method.super_bit) is set.
     pub fn srp(&mut self) -> Result<Self> {
+        if let Some(depth) = self._super_bit_depth {
+            if depth == 0 || depth > self.dim {
+                return Err(Error::Failed(format!(
+                    "super bit depth should be in 1..={}",
+                    self.dim
+                )));
+            }
+        }
         let mut rng = create_rng(self._seed);
         let mut hashers = Vec::with_capacity(self.n_hash_tables);
 
         for _ in 0..self.n_hash_tables {
             let seed = rng.gen();
-            let hasher = SignRandomProjections::new(self.n_projections, self.dim, seed);
+            let hasher = match self._super_bit_depth {
+                Some(depth) => {
+                    SignRandomProjections::new_super_bit(self.n_projections, self.dim, depth, seed)
+                }
+                None => SignRandomProjections::new(self.n_projections, self.dim, seed),
+            };
             hashers.push(hasher);
         }
         lsh_from_lsh(self, hashers)
@@ -141,6 +178,69 @@ impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, L2<N>> {
     }
 }
 
+impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, L1<N>> {
+    /// Create a new L1 LSH
+    ///
+    /// See hash function:
+    /// https://www.cs.princeton.edu/courses/archive/spring05/cos598E/bib/p253-datar.pdf
+    /// in paragraph 3.2, with Cauchy distributed `a`.
+    ///
+    /// h(v) = floor(a^Tv + b / r)
+    ///
+    /// # Arguments
+    ///
+    /// * `r` - Parameter of hash function.
+    pub fn l1(&mut self, r: f32) -> Result<Self> {
+        let mut rng = create_rng(self._seed);
+        let mut hashers = Vec::with_capacity(self.n_hash_tables);
+        for _ in 0..self.n_hash_tables {
+            let seed = rng.gen();
+            let hasher = L1::new(self.dim, r, self.n_projections, seed);
+            hashers.push(hasher);
+        }
+        lsh_from_lsh(self, hashers)
+    }
+}
+
+impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, ITQ<N>> {
+    /// Create a new Iterative Quantization LSH. The hashers need to be fitted on the data points
+    /// before storing them, see [ITQ](struct.ITQ.html).
+    ///
+    /// See hash function:
+    /// http://slazebni.cs.illinois.edu/publications/ITQ.pdf
+    ///
+    /// # Arguments
+    ///
+    /// * `n_iter` - Number of ITQ iterations.
+    pub fn itq(&mut self, n_iter: usize) -> Result<Self> {
+        if self.n_projections > self.dim {
+            return Err(Error::Failed(
+                "n_projections should not be larger than dim".to_string(),
+            ));
+        }
+        let mut rng = create_rng(self._seed);
+        let mut hashers = Vec::with_capacity(self.n_hash_tables);
+
+        for _ in 0..self.n_hash_tables {
+            let seed = rng.gen();
+            let hasher = ITQ::new(self.n_projections, self.dim, n_iter, seed);
+            hashers.push(hasher);
+        }
+        lsh_from_lsh(self, hashers)
+    }
+
+    /// Create a new PCA-hash LSH. All fitted hash tables are the same, so only a single hash
+    /// table is allowed.
+    pub fn pca_hash(&mut self) -> Result<Self> {
+        if self.n_hash_tables > 1 {
+            return Err(Error::Failed(
+                "pca_hash uses a single hash table, set n_hash_tables to 1".to_string(),
+            ));
+        }
+        self.itq(0)
+    }
+}
+
 impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, MIPS<N>> {
     /// Create a new MIPS LSH
     ///
@@ -167,6 +267,121 @@ impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, MIPS<N>>
     }
 }
 
+impl<N: Numeric + Float + DeserializeOwned, T: HashTables<N>> LSH<N, T, WeightedMinHash<N>> {
+    /// Create a new Weighted MinHash LSH for the generalized Jaccard similarity of non-negative
+    /// data points.
+    ///
+    /// See hash function:
+    /// https://static.googleusercontent.com/media/research.google.com/en//pubs/archive/36928.pdf
+    pub fn weighted_minhash(&mut self) -> Result<Self> {
+        let mut rng = create_rng(self._seed);
+        let mut hashers = Vec::with_capacity(self.n_hash_tables);
+
+        for _ in 0..self.n_hash_tables {
+            let seed = rng.gen();
+            let hasher = WeightedMinHash::new(self.n_projections, self.dim, seed);
+            hashers.push(hasher);
+        }
+        lsh_from_lsh(self, hashers)
+    }
+}
+
+impl<N: Numeric + DeserializeOwned, T: HashTables<N>> LSH<N, T, DensifiedMinHash> {
+    /// Create a new Densified One Permutation Hashing LSH for the Jaccard similarity of sets.
+    /// The elements of a set are the indexes of the non-zero values of a data point.
+    ///
+    /// See hash function:
+    /// https://arxiv.org/pdf/1703.04664.pdf
+    ///
+    /// A data point is hashed once into the `K * L` bins of all hash tables.
+    pub fn densified_minhash(&mut self) -> Result<Self> {
+        if self.n_projections == 0 {
+            return Err(Error::Failed(
+                "number of projections should be larger than 0".to_string(),
+            ));
+        }
+        let hashers =
+            DensifiedMinHash::new_tables(self.n_projections, self.n_hash_tables, self._seed);
+        lsh_from_lsh(self, hashers)
+    }
+}
+
+impl<N: Numeric + DeserializeOwned, T: HashTables<N>> LSH<N, T, DWTA> {
+    /// Create a new Densified Winner Take All LSH for sparse data points.
+    ///
+    /// See hash function:
+    /// https://arxiv.org/pdf/1903.03129.pdf
+    ///
+    /// # Arguments
+    ///
+    /// * `bin_size` - Number of dimensions per hash value. At most `dim` and 128.
+    pub fn dwta(&mut self, bin_size: usize) -> Result<Self> {
+        if bin_size == 0 || bin_size > self.dim.min(128) {
+            return Err(Error::Failed(format!(
+                "bin_size should be in 1..={}",
+                self.dim.min(128)
+            )));
+        }
+        let mut rng = create_rng(self._seed);
+        let mut hashers = Vec::with_capacity(self.n_hash_tables);
+
+        for _ in 0..self.n_hash_tables {
+            let seed = rng.gen();
+            let hasher = DWTA::new(self.n_projections, self.dim, bin_size, seed);
+            hashers.push(hasher);
+        }
+        lsh_from_lsh(self, hashers)
+    }
+}
+
+impl<T: HashTables<u64>> LSH<u64, T, BitSampling> {
+    /// Create a new Bit sampling LSH for binary vectors and the Hamming distance.
+    ///
+    /// The binary vectors are packed in `u64` words, so `dim` is the number of words.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_bits` - Number of bits of the binary vectors. At most `64 * dim`.
+    pub fn bit_sampling(&mut self, n_bits: usize) -> Result<Self> {
+        if n_bits == 0 || n_bits > 64 * self.dim {
+            return Err(Error::Failed(format!(
+                "n_bits should be in 1..={} for {} u64 words",
+                64 * self.dim,
+                self.dim
+            )));
+        }
+        let mut rng = create_rng(self._seed);
+        let mut hashers = Vec::with_capacity(self.n_hash_tables);
+
+        for _ in 0..self.n_hash_tables {
+            let seed = rng.gen();
+            let hasher = BitSampling::new(n_bits, self.n_projections, seed);
+            hashers.push(hasher);
+        }
+        lsh_from_lsh(self, hashers)
+    }
+}
+
+impl<N: Numeric + DeserializeOwned, T: HashTables<N>, H: Serialize + DeserializeOwned>
+    LSH<N, T, H>
+{
+    /// Create a new LSH with custom hash functions. The hash functions can hash any
+    /// [Input](trait.Input.html) type they implement [InputHash](trait.InputHash.html) for.
+    ///
+    /// # Arguments
+    /// * `hashers` - One hash function per hash table.
+    pub fn custom_hashers(&mut self, hashers: Vec<H>) -> Result<Self> {
+        if hashers.len() != self.n_hash_tables {
+            return Err(Error::Failed(format!(
+                "expected {} hashers, one per hash table, got {}",
+                self.n_hash_tables,
+                hashers.len()
+            )));
+        }
+        lsh_from_lsh(self, hashers)
+    }
+}
+
 impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N> + Sync> LSH<N, T, H> {
     /// Query bucket collision for a batch of data points in parallel.
     ///
@@ -206,6 +421,9 @@ impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N>> LSH<N, T, H> {
     /// let ids = lsh.store_vecs(vs);
     /// ```
     pub fn store_vecs(&mut self, vs: &[Vec<N>]) -> Result<Vec<u32>> {
+        if vs.is_empty() {
+            return Ok(vec![]);
+        }
         self.validate_vec(&vs[0])?;
         self.hash_tables
             .as_mut()
@@ -214,9 +432,11 @@ impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N>> LSH<N, T, H> {
 
         let mut ht = self.hash_tables.take().unwrap();
         let mut insert_idx = Vec::with_capacity(vs.len());
-        for (i, proj) in self.hashers.iter().enumerate() {
-            for v in vs.iter() {
-                let hash = proj.hash_vec_put(v);
+        // The backends assign a new id once a data point is stored in all `L` hash tables.
+        // So every data point needs to be stored in all tables before the next is stored.
+        for v in vs.iter() {
+            let hashes = H::hash_vec_put_tables(&self.hashers, v);
+            for (i, hash) in hashes.into_iter().enumerate() {
                 match (ht.put(hash, v, i), i) {
                     // only for the first hash table save the index as it will be the same for all
                     (Ok(idx), 0) => insert_idx.push(idx),
@@ -244,6 +464,9 @@ impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N>> LSH<N, T, H> {
     /// let ids = lsh.store_array(vs.view());
     /// ```
     pub fn store_array(&mut self, vs: ArrayView2<N>) -> Result<Vec<u32>> {
+        if vs.nrows() == 0 {
+            return Ok(vec![]);
+        }
         self.validate_vec(vs.slice(s![0, ..]).as_slice().unwrap())?;
         self.hash_tables
             .as_mut()
@@ -252,9 +475,9 @@ impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N>> LSH<N, T, H> {
 
         let mut ht = self.hash_tables.take().unwrap();
         let mut insert_idx = Vec::with_capacity(vs.len());
-        for (i, proj) in self.hashers.iter().enumerate() {
-            for v in vs.axis_iter(Axis(0)) {
-                let hash = proj.hash_vec_put(v.as_slice().unwrap());
+        for v in vs.axis_iter(Axis(0)) {
+            let hashes = H::hash_vec_put_tables(&self.hashers, v.as_slice().unwrap());
+            for (i, hash) in hashes.into_iter().enumerate() {
                 match (ht.put(hash, v.as_slice().unwrap(), i), i) {
                     // only for the first hash table save the index as it will be the same for all
                     (Ok(idx), 0) => insert_idx.push(idx),
@@ -266,9 +489,71 @@ impl<N: Numeric, H: VecHash<N> + Sync, T: HashTables<N>> LSH<N, T, H> {
         self.hash_tables.replace(ht);
         Ok(insert_idx)
     }
+
+    /// Stream a `.fvecs` or `.bvecs` file in storage. The file is read in chunks of `chunk_size`
+    /// vectors that are stored with [store_array](#method.store_array). See the
+    /// [io module](io/index.html).
+    ///
+    /// # Arguments
+    /// * `path` - File path. The format is determined by the extension.
+    /// * `chunk_size` - Number of vectors held in memory. Must be larger than zero.
+    pub fn store_vecs_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        chunk_size: usize,
+    ) -> Result<Vec<u32>> {
+        validate_chunk_size(chunk_size)?;
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("fvecs") => self.store_chunks(FvecsReader::open(path)?, chunk_size),
+            Some("bvecs") => self.store_chunks(BvecsReader::open(path)?, chunk_size),
+            _ => Err(Error::Failed(format!(
+                "{} is not an .fvecs or .bvecs file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Store a memory mapped `.npy` file. If the dtype of the file equals `N` the data is stored
+    /// without copying, otherwise chunks of `chunk_size` vectors are cast to `N`.
+    /// See the [npy module](npy/index.html).
+    ///
+    /// # Arguments
+    /// * `path` - File path.
+    /// * `chunk_size` - Number of vectors held in memory if the data needs to be cast. Must be
+    ///   larger than zero.
+    pub fn store_npy<P: AsRef<Path>>(&mut self, path: P, chunk_size: usize) -> Result<Vec<u32>>
+    where
+        N: 'static,
+    {
+        validate_chunk_size(chunk_size)?;
+        let npy = NpyFile::open(path)?;
+        if let Some(view) = npy.view::<N>() {
+            return self.store_array(view);
+        }
+        let (n, _) = npy.shape();
+        let mut ids = Vec::with_capacity(n);
+        for start in (0..n).step_by(chunk_size) {
+            let chunk = npy.read_rows::<N>(start, start + chunk_size)?;
+            ids.extend(self.store_array(chunk.view())?);
+        }
+        Ok(ids)
+    }
+
+    fn store_chunks<E: VecsElement, R: std::io::Read>(
+        &mut self,
+        mut reader: VecsReader<E, R>,
+        chunk_size: usize,
+    ) -> Result<Vec<u32>> {
+        let mut ids = vec![];
+        while let Some(chunk) = reader.read_chunk(chunk_size)? {
+            ids.extend(self.store_array(chunk.view())?);
+        }
+        Ok(ids)
+    }
 }
 
-impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
+impl<N: Numeric, H, T: HashTables<N>> LSH<N, T, H> {
     /// Create a new Base LSH
     ///
     /// # Arguments
@@ -288,6 +573,7 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
             only_index_storage: false,
             _multi_probe: false,
             _multi_probe_budget: 16,
+            _super_bit_depth: None,
             _db_path: "./lsh.db3".to_string(),
             _type: None,
         };
@@ -328,6 +614,16 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
         self
     }
 
+    /// Use Super-Bit LSH for Sign Random Projections. The hyperplanes are orthogonalized in
+    /// groups of `depth`, which lowers the variance of the hash collisions.
+    ///
+    /// # Arguments
+    /// * `depth` - Number of orthogonalized hyperplanes per group. At most `dim`.
+    pub fn super_bit(&mut self, depth: usize) -> &mut Self {
+        self._super_bit_depth = Some(depth);
+        self
+    }
+
     pub fn base(&mut self) -> &mut Self {
         self._multi_probe = false;
         self
@@ -355,16 +651,45 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
         self
     }
 
-    /// Collects statistics of the buckets in the `hash_tables`.
+    /// Collects statistics of the buckets in every hash table of the `hash_tables`.
     /// # Statistics
-    /// * average bucket length
-    /// * minimal bucket length
-    /// * maximum bucket length
-    /// * bucket lenght standard deviation
+    /// * number of buckets and a histogram of the bucket sizes
+    /// * minimal, maximum, average bucket length and its standard deviation
+    /// * number of stored data points
+    /// * approximate memory usage
+    /// * unique hash values
+    pub fn stats(&self) -> Result<Stats> {
+        self.hash_tables.as_ref().unwrap().stats()
+    }
+
+    /// Human readable summary of the [stats](#method.stats).
     pub fn describe(&self) -> Result<String> {
         self.hash_tables.as_ref().unwrap().describe()
     }
 
+    pub(crate) fn process_bucket_union_result(
+        &self,
+        hash: &Hash,
+        hash_table_idx: usize,
+        bucket_union: &mut Bucket,
+    ) -> Result<()> {
+        match self
+            .hash_tables
+            .as_ref()
+            .unwrap()
+            .query_bucket(hash, hash_table_idx)
+        {
+            Err(Error::NotFound) => Ok(()),
+            Ok(bucket) => {
+                *bucket_union = bucket_union.union(&bucket).copied().collect();
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
     /// Store a single vector in storage. Returns id.
     ///
     /// # Arguments
@@ -378,16 +703,7 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
     /// let id = lsh.store_vec(v);
     /// ```
     pub fn store_vec(&mut self, v: &[N]) -> Result<u32> {
-        self.validate_vec(v)?;
-
-        let mut idx = 0;
-        let mut ht = self.hash_tables.take().unwrap();
-        for (i, proj) in self.hashers.iter().enumerate() {
-            let hash = proj.hash_vec_put(v);
-            idx = ht.put(hash, &v, i)?;
-        }
-        self.hash_tables.replace(ht);
-        Ok(idx)
+        self.store_input(v)
     }
 
     /// Update a data point in the `hash_tables`.
@@ -398,9 +714,9 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
     /// * `old_v` - Old data point. Needed to remove the old hash.
     pub fn update_by_idx(&mut self, idx: u32, new_v: &[N], old_v: &[N]) -> Result<()> {
         let mut ht = self.hash_tables.take().unwrap();
-        for (i, proj) in self.hashers.iter().enumerate() {
-            let new_hash = proj.hash_vec_put(new_v);
-            let old_hash = proj.hash_vec_put(old_v);
+        let new_hashes = H::hash_vec_put_tables(&self.hashers, new_v);
+        let old_hashes = H::hash_vec_put_tables(&self.hashers, old_v);
+        for (i, (new_hash, old_hash)) in new_hashes.into_iter().zip(old_hashes).enumerate() {
             ht.update_by_idx(&old_hash, new_hash, idx, i)?;
         }
         self.hash_tables.replace(ht);
@@ -415,9 +731,9 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
 
         let mut bucket_union = HashSet::default();
 
-        for (i, proj) in self.hashers.iter().enumerate() {
-            let hash = proj.hash_vec_query(v);
-            self.process_bucket_union_result(&hash, i, &mut bucket_union)?;
+        let hashes = H::hash_vec_query_tables(&self.hashers, v);
+        for (i, hash) in hashes.iter().enumerate() {
+            self.process_bucket_union_result(hash, i, &mut bucket_union)?;
         }
         Ok(bucket_union)
     }
@@ -477,38 +793,17 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
     /// * `v` - Data point
     pub fn delete_vec(&mut self, v: &[N]) -> Result<()> {
         self.validate_vec(v)?;
-        for (i, proj) in self.hashers.iter().enumerate() {
-            let hash = proj.hash_vec_query(v);
+        let hashes = H::hash_vec_query_tables(&self.hashers, v);
+        for (i, hash) in hashes.iter().enumerate() {
             let mut ht = self.hash_tables.take().unwrap();
-            ht.delete(&hash, v, i).unwrap_or_default();
+            ht.delete(hash, v, i).unwrap_or_default();
             self.hash_tables = Some(ht)
         }
         Ok(())
     }
-
-    pub(crate) fn process_bucket_union_result(
-        &self,
-        hash: &Hash,
-        hash_table_idx: usize,
-        bucket_union: &mut Bucket,
-    ) -> Result<()> {
-        match self
-            .hash_tables
-            .as_ref()
-            .unwrap()
-            .query_bucket(hash, hash_table_idx)
-        {
-            Err(Error::NotFound) => Ok(()),
-            Ok(bucket) => {
-                *bucket_union = bucket_union.union(&bucket).copied().collect();
-                Ok(())
-            }
-            Err(e) => Err(e),
-        }
-    }
 }
 
-impl<N: Numeric, T: VecHash<N> + Serialize> LSH<N, SqlTable<N>, T> {
+impl<N: Numeric, H> LSH<N, SqlTable<N>, H> {
     /// Commit SqlTable backend
     pub fn commit(&mut self) -> Result<()> {
         let ht = self.hash_tables.as_mut().unwrap();
@@ -538,7 +833,7 @@ struct IntermediatBlob {
 
 impl<N, H> LSH<N, MemoryTable<N>, H>
 where
-    H: Serialize + DeserializeOwned + VecHash<N>,
+    H: Serialize + DeserializeOwned,
     N: Numeric + DeserializeOwned,
 {
     /// Deserialize MemoryTable backend
@@ -546,10 +841,22 @@ where
         let mut f = File::open(path)?;
         let mut buf: Vec<u8> = vec![];
         f.read_to_end(&mut buf)?;
+        self.load_bytes(&buf)
+    }
+
+    /// Serialize MemoryTable backend
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let blob = self.dump_bytes()?;
+        let mut f = File::create(path)?;
+        f.write_all(&blob)?;
+        Ok(())
+    }
 
-        let ib: IntermediatBlob = bincode::deserialize(&buf)?;
+    /// Deserialize MemoryTable backend from a buffer created by [dump_bytes](#method.dump_bytes).
+    pub fn load_bytes(&mut self, buf: &[u8]) -> Result<()> {
+        let ib: IntermediatBlob = bincode::deserialize(buf)?;
         self.hashers = bincode::deserialize(&ib.hashers)?;
-        self.hash_tables = bincode::deserialize(&ib.hash_tables)?;
+        self.hash_tables = deserialize_tables(&ib.hash_tables)?;
         self.n_hash_tables = ib.n_hash_tables;
         self.n_projections = ib.n_projections;
         self.dim = ib.dim;
@@ -558,8 +865,8 @@ where
         Ok(())
     }
 
-    /// Serialize MemoryTable backend
-    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    /// Serialize MemoryTable backend to a buffer. Same format as [dump](#method.dump).
+    pub fn dump_bytes(&self) -> Result<Vec<u8>> {
         let hash_tables = bincode::serialize(&self.hash_tables)?;
         let hashers = bincode::serialize(&self.hashers)?;
 
@@ -571,9 +878,44 @@ where
             dim: self.dim,
             _seed: self._seed,
         };
-        let mut f = File::create(path)?;
-        let blob = bincode::serialize(&ib)?;
-        f.write(&blob)?;
+        Ok(bincode::serialize(&ib)?)
+    }
+}
+
+/// Make the stored data points durable, i.e. after every insert of a server.
+pub trait Persist {
+    /// Write the stored data points to disk. A `MemoryTable` backend is dumped to `path`, if
+    /// given. A `SqlTable` backend is committed to its database. A `SqlTableMem` backend is only
+    /// written to disk by [to_db](struct.SqlTableMem.html#method.to_db).
+    fn persist(&mut self, path: Option<&Path>) -> Result<()>;
+}
+
+impl<N, H> Persist for LSH<N, MemoryTable<N>, H>
+where
+    H: Serialize + DeserializeOwned,
+    N: Numeric + DeserializeOwned,
+{
+    fn persist(&mut self, path: Option<&Path>) -> Result<()> {
+        if let Some(path) = path {
+            // dump next to `path` first, so that a failed dump does not corrupt the dump at `path`.
+            let mut tmp = path.as_os_str().to_owned();
+            tmp.push(".tmp");
+            self.dump(&tmp)?;
+            std::fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<N: Numeric, H> Persist for LSH<N, SqlTable<N>, H> {
+    fn persist(&mut self, _path: Option<&Path>) -> Result<()> {
+        self.commit()?;
+        self.init_transaction()
+    }
+}
+
+impl<N: Numeric, H> Persist for LSH<N, SqlTableMem<N>, H> {
+    fn persist(&mut self, _path: Option<&Path>) -> Result<()> {
         Ok(())
     }
 }
@@ -50,6 +50,34 @@ fn test_serialization() {
     println!("{:?}", lsh.hash_tables)
 }
 
+#[test]
+fn test_serialization_bytes() {
+    let mut lsh: LshMem<f32, _> = LSH::new(5, 9, 3).seed(1).l2(2.).unwrap();
+    let v1 = &[2., 3., 4.];
+    lsh.store_vec(v1).unwrap();
+    let buf = lsh.dump_bytes().unwrap();
+
+    let mut loaded: LshMem<f32, _> = LSH::new(5, 9, 3).seed(2).l2(2.).unwrap();
+    loaded.load_bytes(&buf).unwrap();
+    assert_eq!(loaded.query_bucket_ids(v1).unwrap(), vec![0]);
+    assert!(loaded.load_bytes(&buf[..buf.len() / 2]).is_err());
+}
+
+#[test]
+fn test_persist() {
+    let path = std::env::temp_dir().join("lsh_rs_test_persist.bincode");
+    let mut lsh: LshMem<f32, _> = LSH::new(5, 9, 3).seed(1).l2(2.).unwrap();
+    lsh.persist(None).unwrap();
+    assert!(!path.exists());
+    lsh.store_vec(&[2., 3., 4.]).unwrap();
+    lsh.persist(Some(&path)).unwrap();
+
+    let mut loaded: LshMem<f32, _> = LSH::new(5, 9, 3).seed(2).l2(2.).unwrap();
+    loaded.load(&path).unwrap();
+    assert_eq!(loaded.query_bucket_ids(&[2., 3., 4.]).unwrap(), vec![0]);
+    std::fs::remove_file(&path).unwrap();
+}
+
 #[test]
 fn test_db() {
     let v1 = &[2., 3., 4.];
@@ -75,3 +103,245 @@ fn test_mem_db() {
     assert!(lsh.query_bucket_ids(v1).unwrap().contains(&0));
     lsh.describe().unwrap();
 }
+
+#[test]
+fn test_same_id_in_every_table() {
+    use crate::{HashTables, VecHash};
+    use ndarray::prelude::*;
+
+    let vs = &[vec![2., 3., 4.], vec![-1., -1., 1.], vec![0.5, -2., 3.]];
+    let mut lsh = LshMem::new(5, 4, 3).seed(1).srp().unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    let arr = array![[1., 1., 1.], [-3., 0., 2.]];
+    assert_eq!(lsh.store_array(arr.view()).unwrap(), vec![3, 4]);
+    for (id, v) in vs.iter().enumerate() {
+        for (i, proj) in lsh.hashers.iter().enumerate() {
+            let bucket = lsh
+                .hash_tables
+                .as_ref()
+                .unwrap()
+                .query_bucket(&proj.hash_vec_query(v), i)
+                .unwrap();
+            assert!(bucket.contains(&(id as u32)));
+        }
+    }
+
+    // a single hash table
+    let mut lsh = LshMem::new(5, 1, 3).seed(1).srp().unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    assert_eq!(lsh.store_vec(&vs[0]).unwrap(), 3);
+    assert_eq!(lsh.hash_tables.unwrap().vec_store.map.len(), 4);
+}
+
+#[test]
+fn test_stats() {
+    let vs = &[vec![2., 3., 4.], vec![-1., -1., 1.], vec![2., 3., 4.1]];
+    let mut lsh = LshMem::new(5, 3, 3).seed(2).srp().unwrap();
+    lsh.store_vecs(vs).unwrap();
+    let mut lsh_sql = LshSqlMem::new(5, 3, 3).seed(2).srp().unwrap();
+    lsh_sql.store_vecs(vs).unwrap();
+
+    for stats in &[lsh.stats().unwrap(), lsh_sql.stats().unwrap()] {
+        assert_eq!(stats.n_hash_tables, 3);
+        assert_eq!(stats.n_points, 3);
+        assert!(stats.tables.iter().all(|t| t.n_points == 3));
+        assert!(stats.to_json().unwrap().contains("bucket_size_histogram"));
+    }
+    // both backends share the same hashers so the bucket distribution should be equal.
+    let (a, b) = (lsh.stats().unwrap(), lsh_sql.stats().unwrap());
+    assert_eq!(
+        a.combined().bucket_size_histogram,
+        b.combined().bucket_size_histogram
+    );
+}
+
+#[test]
+fn test_store_vecs_file() {
+    let path = std::env::temp_dir().join("lsh_rs_test_store.fvecs");
+    let vs = vec![vec![2., 3., 4.], vec![-1., -1., 1.], vec![2., 3., 4.1]];
+    crate::io::write_vecs::<f32, _>(&path, &vs).unwrap();
+
+    let mut lsh = LshMem::new(5, 3, 3).seed(2).srp().unwrap();
+    // chunk size does not divide the number of vectors
+    assert_eq!(lsh.store_vecs_file(&path, 2).unwrap(), vec![0, 1, 2]);
+    assert!(lsh.query_bucket_ids(&vs[1]).unwrap().contains(&1));
+    assert!(lsh.store_vecs_file("data.csv", 2).is_err());
+    assert!(lsh.store_vecs_file(&path, 0).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_store_npy() {
+    use ndarray::prelude::*;
+    let path = std::env::temp_dir().join("lsh_rs_test_store.npy");
+    let vs = array![[2., 3., 4.], [-1., -1., 1.], [2., 3., 4.1]];
+    crate::npy::write_npy::<f64, _>(&path, vs.view()).unwrap();
+
+    // zero copy
+    let mut lsh = LshMem::<f64, _>::new(5, 3, 3).seed(2).srp().unwrap();
+    assert_eq!(lsh.store_npy(&path, 2).unwrap(), vec![0, 1, 2]);
+    // cast to f32 in chunks
+    let mut lsh = LshMem::<f32, _>::new(5, 3, 3).seed(2).srp().unwrap();
+    assert_eq!(lsh.store_npy(&path, 2).unwrap(), vec![0, 1, 2]);
+    assert!(lsh.query_bucket_ids(&[-1., -1., 1.]).unwrap().contains(&1));
+    assert!(lsh.store_npy(&path, 0).is_err());
+
+    // no data points
+    let empty = Array2::<f64>::zeros((0, 3));
+    crate::npy::write_npy::<f64, _>(&path, empty.view()).unwrap();
+    assert!(lsh.store_npy(&path, 2).unwrap().is_empty());
+    let mut lsh = LshMem::<f64, _>::new(5, 3, 3).seed(2).srp().unwrap();
+    assert!(lsh.store_npy(&path, 2).unwrap().is_empty());
+    assert!(lsh.store_array(empty.view()).unwrap().is_empty());
+    assert!(lsh.store_vecs(&[]).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_db_reopen_continues_ids() {
+    let path = std::env::temp_dir().join("lsh_rs_test_reopen.db3");
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_str().unwrap();
+    let vs = &[vec![2., 3., 4.], vec![-1., -1., 1.]];
+    {
+        let mut lsh = LshSql::new(5, 2, 3)
+            .seed(2)
+            .set_database_file(path)
+            .srp()
+            .unwrap();
+        assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1]);
+        lsh.commit().unwrap();
+    }
+    let mut lsh = LshSql::new(5, 2, 3).set_database_file(path).srp().unwrap();
+    assert_eq!(lsh.store_vec(&[1., 1., 1.]).unwrap(), 2);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_bit_sampling() {
+    let vs = &[
+        vec![0xdead_beef_0000_ffff, 0x0123],
+        vec![0x1234_5678_9abc_def0, 0xffff],
+    ];
+    assert!(LshMem::new(8, 5, 2).bit_sampling(129).is_err());
+    let mut lsh = LshMem::new(8, 5, 2).seed(3).bit_sampling(80).unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1]);
+    assert!(lsh.query_bucket(&vs[0]).unwrap().contains(&&vs[0]));
+    // a single flipped bit mostly lands in the same buckets
+    let q = &[vs[1][0] ^ 1, vs[1][1]];
+    assert!(lsh.query_bucket_ids(q).unwrap().contains(&1));
+    lsh.multi_probe(8);
+    assert!(lsh.query_bucket_ids(&vs[1]).unwrap().contains(&1));
+
+    let mut lsh = LshSqlMem::new(8, 5, 2).seed(3).bit_sampling(128).unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1]);
+    assert!(lsh.query_bucket_ids(&vs[1]).unwrap().contains(&1));
+}
+
+#[test]
+fn test_weighted_minhash() {
+    use crate::{dist::generalized_jaccard, eval::Metric};
+    let vs = &[
+        vec![3., 0., 1., 0., 2.],
+        vec![0., 4., 0., 1., 0.],
+        vec![3., 0., 1., 0., 1.],
+    ];
+    let mut lsh = LshMem::new(3, 10, 5).seed(1).weighted_minhash().unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    let ids = lsh.query_bucket_ids(&vs[0]).unwrap();
+    assert!(ids.contains(&0));
+    assert!(ids.contains(&2));
+    assert_eq!(generalized_jaccard(&vs[0], &vs[1]), 1.);
+    let dist: f64 = Metric::GeneralizedJaccard.distance(&vs[0], &vs[2]);
+    assert!((dist - 1. / 6.).abs() < 1e-12);
+}
+
+#[test]
+fn test_l1() {
+    use crate::{dist::l1_dist, eval::Metric};
+    let vs = &[vec![2., 3., 4.], vec![-1., -1., 1.], vec![2., 3., 4.1]];
+    let mut lsh = LshMem::new(5, 4, 3).seed(1).l1(4.).unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    assert!(lsh.query_bucket_ids(&vs[0]).unwrap().contains(&2));
+    lsh.multi_probe(10);
+    assert!(lsh.query_bucket_ids(&[2., 3., 4.05]).unwrap().contains(&0));
+    assert_eq!(Metric::L1.distance(&vs[0], &vs[1]), l1_dist(&vs[1], &vs[0]));
+}
+
+#[test]
+fn test_super_bit() {
+    assert!(LshMem::<f32, _>::new(5, 2, 3).super_bit(4).srp().is_err());
+    let mut lsh = LshMem::new(5, 2, 3).seed(1).super_bit(3).srp().unwrap();
+    let vs = &[vec![2., 3., 4.], vec![-1., -1., 1.]];
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1]);
+    assert!(lsh.query_bucket_ids(&vs[1]).unwrap().contains(&1));
+}
+
+#[test]
+fn test_densified_minhash() {
+    assert!(LshMem::<u8, _>::new(0, 10, 6).densified_minhash().is_err());
+    let vs = &[
+        vec![1u8, 1, 0, 1, 0, 1],
+        vec![0, 0, 1, 0, 1, 0],
+        vec![1, 1, 0, 1, 0, 0],
+    ];
+    let mut lsh = LshMem::new(3, 10, 6).seed(1).densified_minhash().unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    let ids = lsh.query_bucket_ids(&vs[0]).unwrap();
+    assert!(ids.contains(&0));
+    assert!(ids.contains(&2));
+
+    let mut lsh = LshSqlMem::new(3, 10, 6)
+        .seed(1)
+        .densified_minhash()
+        .unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    assert!(lsh.query_bucket_ids(&vs[1]).unwrap().contains(&1));
+}
+
+#[test]
+fn test_dwta() {
+    assert!(LshMem::<f32, _>::new(4, 5, 3).dwta(4).is_err());
+    let vs = &[
+        vec![0., 0.9, 0., 0.1, 0.5],
+        vec![0.7, 0., 0.2, 0., 0.],
+        vec![0., 0.8, 0., 0.2, 0.4],
+    ];
+    let mut lsh = LshMem::new(2, 10, 5).seed(1).dwta(2).unwrap();
+    assert_eq!(lsh.store_vecs(vs).unwrap(), vec![0, 1, 2]);
+    let ids = lsh.query_bucket_ids(&vs[0]).unwrap();
+    assert!(ids.contains(&0));
+    assert!(ids.contains(&2));
+}
+
+#[test]
+fn test_itq() {
+    use ndarray::prelude::*;
+    let x = array![
+        [1., 2., 0.5, 0.],
+        [-1., 0.3, 2., 0.1],
+        [0.9, 2.1, 0.4, 0.],
+        [-0.8, 0.1, 2.2, 0.2],
+    ];
+    assert!(LshMem::<f64, _>::new(5, 2, 4).itq(10).is_err());
+    assert!(LshMem::<f64, _>::new(2, 4, 4).pca_hash().is_err());
+    assert!(LshMem::<f64, _>::new(2, 1, 4).pca_hash().is_ok());
+    let mut lsh = LshMem::new(2, 4, 4).seed(1).itq(20).unwrap();
+    for h in lsh.hashers.iter_mut() {
+        h.fit(&x.view()).unwrap();
+    }
+    assert_eq!(lsh.store_array(x.view()).unwrap(), vec![0, 1, 2, 3]);
+    let ids = lsh.query_bucket_ids(&[1., 2., 0.45, 0.]).unwrap();
+    assert!(ids.contains(&0));
+    assert!(ids.contains(&2));
+
+    // the fitted hashers are serialized
+    let bytes = lsh.dump_bytes().unwrap();
+    let mut loaded = LshMem::new(2, 4, 4).seed(2).itq(20).unwrap();
+    loaded.load_bytes(&bytes).unwrap();
+    let q = x.row(1).to_vec();
+    assert_eq!(
+        loaded.query_bucket_ids(&q).unwrap(),
+        lsh.query_bucket_ids(&q).unwrap()
+    );
+}
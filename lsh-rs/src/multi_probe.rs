@@ -1,6 +1,6 @@
 use crate::data::Numeric;
 use crate::utils::create_rng;
-use crate::{Error, Hash, HashPrimitive, HashTables, Result, VecHash, L2, LSH};
+use crate::{Error, Hash, HashPrimitive, HashTables, Result, VecHash, L1, L2, LSH};
 use fnv::FnvHashSet;
 use itertools::Itertools;
 use ndarray::prelude::*;
@@ -200,8 +200,9 @@ where
     fn gen_hash(&mut self) -> Hash {
         let mut hash = self.original_hash.take().expect("hash already taken");
         for (i, delta) in self.i_delta() {
+            // hash values of L1 saturate.
             let ptr = &mut hash[i];
-            *ptr += delta
+            *ptr = ptr.saturating_add(delta)
         }
         hash
     }
@@ -253,50 +254,111 @@ where
     N: Numeric + Float,
 {
     fn query_directed_probe(&self, q: &[N], budget: usize) -> Result<Vec<Hash>> {
-        // https://www.cs.princeton.edu/cass/papers/mplsh_vldb07.pdf
-        // https://www.youtube.com/watch?v=c5DHtx5VxX8
         let hash = self.hash_vec_query(q);
         let (xi_min, xi_plus) = self.distance_to_bound(q, Some(&hash));
-        // >= this point = +1
-        // < this point = -1
-        let switchpoint = xi_min.len();
-
-        let distances: Vec<N> = stack!(Axis(0), xi_min, xi_plus).to_vec();
-
-        // indexes of the least scores to the highest
-        // all below is an argsort
-        let z = distances.clone();
-        let mut z = z.iter().enumerate().collect::<Vec<_>>();
-        z.sort_unstable_by(|(_idx_a, a), (_idx_b, b)| a.partial_cmp(b).unwrap());
-        let z = z.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
-
-        let mut hashes = Vec::with_capacity(budget + 1);
-        hashes.push(hash.clone());
-        // Algorithm 1 from paper
-        let mut heap = BinaryHeap::new();
-        let a0 = PerturbState::new(&z, &distances, switchpoint, hash);
-        heap.push(a0);
-        for _ in 0..budget {
-            let mut ai = match heap.pop() {
-                Some(ai) => ai,
-                None => {
-                    return Err(Error::Failed(
-                        "All query directed probing combinations depleted".to_string(),
-                    ))
-                }
-            };
-            let mut a_s = ai.clone();
-            let mut a_e = ai.clone();
-            if a_s.shift().is_ok() {
-                heap.push(a_s);
-            }
-            if a_e.expand().is_ok() {
-                heap.push(a_e);
+        query_directed_probe_pstable(hash, xi_min, xi_plus, budget)
+    }
+}
+
+impl<N> L1<N>
+where
+    N: Numeric + Float,
+{
+    /// Distance of the projected query to the boundaries of its slots. The hash values may be
+    /// saturated, so the distances are computed from the projections.
+    fn distance_to_bound(&self, q: &[N]) -> (Array1<N>, Array1<N>) {
+        let xi_min1 = self.project(q).mapv(|x| x - (x / self.r).floor() * self.r);
+        let xi_plus1 = xi_min1.mapv(|x| self.r - x);
+        (xi_min1, xi_plus1)
+    }
+}
+
+impl<N> QueryDirectedProbe<N> for L1<N>
+where
+    N: Numeric + Float,
+{
+    fn query_directed_probe(&self, q: &[N], budget: usize) -> Result<Vec<Hash>> {
+        let hash = self.hash_vec_query(q);
+        let (xi_min, xi_plus) = self.distance_to_bound(q);
+        query_directed_probe_pstable(hash, xi_min, xi_plus, budget)
+    }
+}
+
+/// Query directed probing for the p-stable hash families (L2 and L1).
+///
+/// # Arguments
+/// * `hash` - Hash of the query.
+/// * `xi_min` - Distances of the query to the lower boundaries of its slots.
+/// * `xi_plus` - Distances of the query to the upper boundaries of its slots.
+/// * `budget` - Number of perturbed hashes.
+fn query_directed_probe_pstable<N>(
+    hash: Hash,
+    xi_min: Array1<N>,
+    xi_plus: Array1<N>,
+    budget: usize,
+) -> Result<Vec<Hash>>
+where
+    N: Numeric + Float,
+{
+    // https://www.cs.princeton.edu/cass/papers/mplsh_vldb07.pdf
+    // https://www.youtube.com/watch?v=c5DHtx5VxX8
+    // >= this point = +1
+    // < this point = -1
+    let switchpoint = xi_min.len();
+
+    let distances: Vec<N> = stack!(Axis(0), xi_min, xi_plus).to_vec();
+
+    // indexes of the least scores to the highest
+    // all below is an argsort
+    let z = distances.clone();
+    let mut z = z.iter().enumerate().collect::<Vec<_>>();
+    z.sort_unstable_by(|(_idx_a, a), (_idx_b, b)| a.partial_cmp(b).unwrap());
+    // Perturbing a saturated hash value (L1) results in the same hash, so these are skipped.
+    let z = z
+        .iter()
+        .map(|(idx, _)| *idx)
+        .filter(|&zj| {
+            if zj >= switchpoint {
+                hash[zj - switchpoint] != HashPrimitive::MAX
+            } else {
+                hash[zj] != HashPrimitive::MIN
             }
-            hashes.push(ai.gen_hash())
+        })
+        .collect::<Vec<_>>();
+
+    let mut hashes = Vec::with_capacity(budget + 1);
+    hashes.push(hash.clone());
+    if z.is_empty() {
+        return if budget == 0 {
+            Ok(hashes)
+        } else {
+            Err(depleted_err())
+        };
+    }
+    // Algorithm 1 from paper
+    let mut heap = BinaryHeap::new();
+    let a0 = PerturbState::new(&z, &distances, switchpoint, hash);
+    heap.push(a0);
+    for _ in 0..budget {
+        let mut ai = match heap.pop() {
+            Some(ai) => ai,
+            None => return Err(depleted_err()),
+        };
+        let mut a_s = ai.clone();
+        let mut a_e = ai.clone();
+        if a_s.shift().is_ok() {
+            heap.push(a_s);
+        }
+        if a_e.expand().is_ok() {
+            heap.push(a_e);
         }
-        Ok(hashes)
+        hashes.push(ai.gen_hash())
     }
+    Ok(hashes)
+}
+
+fn depleted_err() -> Error {
+    Error::Failed("All query directed probing combinations depleted".to_string())
 }
 
 impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
@@ -305,7 +367,7 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
         let mut bucket_union = FnvHashSet::default();
 
         // Check if hasher has implemented this trait. If so follow this more specialized path.
-        // Only L2 should have implemented it. This is the trick to choose a different function
+        // Only L2 and L1 should have implemented it. This is the trick to choose a different function
         // path for the L2 struct.
         if self.hashers[0].as_query_directed_probe().is_some() {
             for (i, hasher) in self.hashers.iter().enumerate() {
@@ -318,9 +380,9 @@ impl<N: Numeric, H: VecHash<N>, T: HashTables<N>> LSH<N, T, H> {
             }
         } else {
             let probing_seq = step_wise_probing(self.n_projections, self._multi_probe_budget);
-            for (i, proj) in self.hashers.iter().enumerate() {
+            let hashes = H::hash_vec_query_tables(&self.hashers, v);
+            for (i, original_hash) in hashes.into_iter().enumerate() {
                 // fist process the original query
-                let original_hash = proj.hash_vec_query(v);
                 self.process_bucket_union_result(&original_hash, i, &mut bucket_union)?;
 
                 for pertub in &probing_seq {
@@ -426,4 +488,32 @@ mod test {
         lsh.store_vec(&[1.]).unwrap();
         assert!(lsh.query_bucket_ids(&[1.]).is_err())
     }
+
+    #[test]
+    fn test_query_directed_probe_saturated_l1() {
+        let l1 = L1::<f32>::new(2, 0.01, 3, 1);
+        let q = [1e6, -1e6];
+        let hash = l1.hash_vec_query(&q);
+        let n_saturated = hash
+            .iter()
+            .filter(|&&h| h == HashPrimitive::MAX || h == HashPrimitive::MIN)
+            .count();
+        assert!(n_saturated > 0);
+
+        // a saturated hash value can only be perturbed in one direction.
+        let n_perturbations = 6 - n_saturated;
+        for (i, single) in l1
+            .query_directed_probe(&q, n_perturbations)
+            .unwrap()
+            .iter()
+            .enumerate()
+            .skip(1)
+            .take(n_perturbations)
+        {
+            assert_ne!(single, &hash, "{}", i);
+        }
+        let budget = 2usize.pow(n_perturbations as u32) - 1;
+        assert!(l1.query_directed_probe(&q, budget).is_ok());
+        assert!(l1.query_directed_probe(&q, budget + 1).is_err());
+    }
 }
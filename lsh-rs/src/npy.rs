@@ -0,0 +1,380 @@
+//! Reading and writing of NumPy `.npy` and `.npz` files.
+//! [Read more.](https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html)
+//!
+//! Only 2D (or 1D) little endian `f32` and `f64` arrays in C order are supported. `.npy` files
+//! are memory mapped, so that data sets larger than memory can be stored in an index without
+//! copying.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use lsh_rs::{LshMem, npy::{read_npz, NpyFile}};
+//! let npy = NpyFile::open("embeddings.npy").unwrap();
+//! let (n, dim) = npy.shape();
+//!
+//! let mut lsh = LshMem::<f32, _>::new(16, 20, dim).seed(1).srp().unwrap();
+//! lsh.store_npy("embeddings.npy", 10_000).unwrap();
+//!
+//! let arrays = read_npz::<f32, _>("queries.npz").unwrap();
+//! ```
+use crate::{data::Numeric, io::VecsElement, Error, Result};
+use memmap::Mmap;
+use ndarray::prelude::*;
+use std::any::TypeId;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Value type of an npy file.
+pub trait NpyElement: VecsElement {
+    /// Array protocol type string.
+    const DESCR: &'static str;
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+}
+
+impl NpyElement for f64 {
+    const DESCR: &'static str = "<f8";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DType {
+    F32,
+    F64,
+}
+
+impl DType {
+    fn size(self) -> usize {
+        match self {
+            DType::F32 => 4,
+            DType::F64 => 8,
+        }
+    }
+
+    /// Is `N` the Rust type of this dtype?
+    fn is<N: 'static>(self) -> bool {
+        match self {
+            DType::F32 => TypeId::of::<N>() == TypeId::of::<f32>(),
+            DType::F64 => TypeId::of::<N>() == TypeId::of::<f64>(),
+        }
+    }
+}
+
+/// Parsed header of an npy file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyHeader {
+    pub dtype: DType,
+    /// Number of rows and columns. A 1D array is a single row.
+    pub shape: (usize, usize),
+    /// Start of the data in bytes.
+    data_offset: usize,
+}
+
+fn failed<S: Into<String>>(msg: S) -> Error {
+    Error::Failed(msg.into())
+}
+
+/// Value of `key` in the header dictionary, i.e. everything after `'key':`.
+fn dict_value<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let pattern = format!("'{}':", key);
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| failed(format!("npy header has no {}", key)))?;
+    Ok(header[start + pattern.len()..].trim_start())
+}
+
+impl NpyHeader {
+    /// Parse the header at the start of `bytes`.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 10 || &bytes[..6] != MAGIC {
+            return Err(failed("not an npy file"));
+        }
+        let (header_len, header_start) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 if bytes.len() >= 12 => (
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+                12,
+            ),
+            v => return Err(failed(format!("unsupported npy version: {}", v))),
+        };
+        let data_offset = header_start + header_len;
+        if bytes.len() < data_offset {
+            return Err(failed("npy header is truncated"));
+        }
+        let header = std::str::from_utf8(&bytes[header_start..data_offset])
+            .map_err(|_| failed("npy header is not valid utf-8"))?;
+
+        let descr = dict_value(header, "descr")?;
+        let dtype = if descr.starts_with("'<f4'") {
+            DType::F32
+        } else if descr.starts_with("'<f8'") {
+            DType::F64
+        } else {
+            return Err(failed(format!(
+                "unsupported dtype {}, expected '<f4' or '<f8'",
+                descr.split(',').next().unwrap_or(descr)
+            )));
+        };
+        if dict_value(header, "fortran_order")?.starts_with("True") {
+            return Err(failed("fortran ordered arrays are not supported"));
+        }
+
+        let shape = dict_value(header, "shape")?;
+        let end = shape
+            .find(')')
+            .ok_or_else(|| failed("npy shape is not valid"))?;
+        let dims = shape[1..end]
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<usize>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| failed("npy shape is not valid"))?;
+        let shape = match dims[..] {
+            [d] => (1, d),
+            [n, d] => (n, d),
+            _ => return Err(failed("only 1D and 2D arrays are supported")),
+        };
+
+        let header = NpyHeader {
+            dtype,
+            shape,
+            data_offset,
+        };
+        let data_end = header
+            .n_bytes()
+            .and_then(|n| n.checked_add(header.data_offset))
+            .ok_or_else(|| failed("npy shape is too large"))?;
+        if bytes.len() < data_end {
+            return Err(failed("npy data is truncated"));
+        }
+        Ok(header)
+    }
+
+    /// Number of data bytes. `None` if the shape overflows.
+    fn n_bytes(&self) -> Option<usize> {
+        self.shape
+            .0
+            .checked_mul(self.shape.1)?
+            .checked_mul(self.dtype.size())
+    }
+}
+
+/// Decode rows `start..end` of the data and cast the values to `N`.
+fn decode_rows<N: Numeric>(
+    header: &NpyHeader,
+    bytes: &[u8],
+    start: usize,
+    end: usize,
+) -> Result<Array2<N>> {
+    let (n, dim) = header.shape;
+    let end = std::cmp::min(end, n);
+    let start = std::cmp::min(start, end);
+    let size = header.dtype.size();
+    let data =
+        &bytes[header.data_offset + start * dim * size..header.data_offset + end * dim * size];
+
+    let values = match header.dtype {
+        DType::F32 => data
+            .chunks_exact(size)
+            .map(|b| N::from(f32::from_le_slice(b)))
+            .collect::<Option<Vec<N>>>(),
+        DType::F64 => data
+            .chunks_exact(size)
+            .map(|b| N::from(f64::from_le_slice(b)))
+            .collect::<Option<Vec<N>>>(),
+    }
+    .ok_or_else(|| failed("npy values do not fit the data type"))?;
+    Array2::from_shape_vec((end - start, dim), values).map_err(|e| failed(e.to_string()))
+}
+
+/// Memory mapped `.npy` file.
+pub struct NpyFile {
+    header: NpyHeader,
+    mmap: Mmap,
+}
+
+impl NpyFile {
+    /// Memory map an npy file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = File::open(path)?;
+        let mmap = unsafe { Mmap::map(&f)? };
+        let header = NpyHeader::parse(&mmap)?;
+        Ok(NpyFile { header, mmap })
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.header.dtype
+    }
+
+    /// Number of rows and columns.
+    pub fn shape(&self) -> (usize, usize) {
+        self.header.shape
+    }
+
+    /// Zero copy view on the data. Returns `None` if the dtype differs from `N` or if the
+    /// data cannot be viewed in place.
+    pub fn view<N: Numeric + 'static>(&self) -> Option<ArrayView2<'_, N>> {
+        if !self.header.dtype.is::<N>() || cfg!(target_endian = "big") {
+            return None;
+        }
+        let ptr = self.mmap[self.header.data_offset..].as_ptr();
+        if ptr.align_offset(std::mem::align_of::<N>()) != 0 {
+            return None;
+        }
+        let (n, dim) = self.header.shape;
+        // Safety: the length is checked while parsing the header, the alignment and type above.
+        let data = unsafe { std::slice::from_raw_parts(ptr as *const N, n * dim) };
+        ArrayView2::from_shape((n, dim), data).ok()
+    }
+
+    /// Copy rows `start..end` and cast the values to `N`.
+    pub fn read_rows<N: Numeric>(&self, start: usize, end: usize) -> Result<Array2<N>> {
+        decode_rows(&self.header, &self.mmap, start, end)
+    }
+
+    /// Copy all data and cast the values to `N`.
+    pub fn to_array<N: Numeric>(&self) -> Result<Array2<N>> {
+        self.read_rows(0, self.header.shape.0)
+    }
+}
+
+/// Read a complete npy file from a reader.
+pub fn read_npy_from<N: Numeric, R: Read>(mut reader: R) -> Result<Array2<N>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let header = NpyHeader::parse(&bytes)?;
+    decode_rows(&header, &bytes, 0, header.shape.0)
+}
+
+/// Read a complete npy file in memory.
+pub fn read_npy<N: Numeric, P: AsRef<Path>>(path: P) -> Result<Array2<N>> {
+    NpyFile::open(path)?.to_array()
+}
+
+/// Write a 2D array in npy format.
+pub fn write_npy_to<N: NpyElement, W: Write>(mut writer: W, arr: ArrayView2<N>) -> Result<()> {
+    let (n, dim) = arr.dim();
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        N::DESCR,
+        n,
+        dim
+    );
+    // the data is aligned at 64 bytes. 10 bytes preamble and a newline.
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    // iteration is in logical (C) order
+    for &x in arr.iter() {
+        x.write_le(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a 2D array to an npy file.
+pub fn write_npy<N: NpyElement, P: AsRef<Path>>(path: P, arr: ArrayView2<N>) -> Result<()> {
+    write_npy_to(BufWriter::new(File::create(path)?), arr)
+}
+
+/// Read all arrays of an npz archive. Returns the array names (without `.npy`) and arrays.
+pub fn read_npz<N: Numeric, P: AsRef<Path>>(path: P) -> Result<Vec<(String, Array2<N>)>> {
+    read_npz_from(File::open(path)?)
+}
+
+/// Read all arrays of an npz archive from a reader.
+pub fn read_npz_from<N: Numeric, R: Read + Seek>(reader: R) -> Result<Vec<(String, Array2<N>)>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let mut arrays = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name().trim_end_matches(".npy").to_string();
+        arrays.push((name, read_npy_from(file)?));
+    }
+    Ok(arrays)
+}
+
+/// Write arrays to an uncompressed npz archive. Load with `numpy.load(path)[name]`.
+pub fn write_npz<N: NpyElement, P: AsRef<Path>>(
+    path: P,
+    arrays: &[(&str, ArrayView2<N>)],
+) -> Result<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, arr) in arrays {
+        zip.start_file(format!("{}.npy", name), options)?;
+        write_npy_to(&mut zip, arr.view())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_header() {
+        let mut bytes = vec![];
+        write_npy_to(&mut bytes, array![[1f64, 2.], [3., 4.], [5., 6.]].view()).unwrap();
+        assert_eq!(bytes.len(), 128 + 6 * 8);
+        let header = NpyHeader::parse(&bytes).unwrap();
+        assert_eq!(header.dtype, DType::F64);
+        assert_eq!(header.shape, (3, 2));
+
+        // cast to f32
+        let arr = read_npy_from::<f32, _>(Cursor::new(&bytes)).unwrap();
+        assert_eq!(arr, array![[1., 2.], [3., 4.], [5., 6.]]);
+
+        let fortran = String::from_utf8_lossy(&bytes).replace("False", "True ");
+        assert!(NpyHeader::parse(fortran.as_bytes()).is_err());
+        assert!(NpyHeader::parse(&bytes[..bytes.len() - 1]).is_err());
+
+        // the number of bytes overflows
+        let dict = std::str::from_utf8(&bytes[10..128]).unwrap().replace(
+            &format!("(3, 2), }}{}", " ".repeat(18)),
+            "(4294967296, 4294967296), }",
+        );
+        let huge = [&bytes[..10], dict.as_bytes(), &bytes[128..]].concat();
+        assert_eq!(huge.len(), bytes.len());
+        assert!(NpyHeader::parse(&huge).is_err());
+    }
+
+    #[test]
+    fn test_npy_file() {
+        let path = std::env::temp_dir().join("lsh_rs_test.npy");
+        let arr = array![[1f32, 2., 3.], [4., 5., 6.]];
+        write_npy(&path, arr.view()).unwrap();
+
+        let npy = NpyFile::open(&path).unwrap();
+        assert_eq!(npy.shape(), (2, 3));
+        assert_eq!(npy.view::<f32>().unwrap(), arr);
+        assert!(npy.view::<f64>().is_none());
+        assert_eq!(npy.read_rows::<f64>(1, 5).unwrap(), array![[4., 5., 6.]]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_npz() {
+        let path = std::env::temp_dir().join("lsh_rs_test.npz");
+        let a = array![[1f32, 2.], [3., 4.]];
+        let b = array![[5f32, 6., 7.]];
+        write_npz(&path, &[("a", a.view()), ("b", b.view())]).unwrap();
+
+        let arrays = read_npz::<f32, _>(&path).unwrap();
+        assert_eq!(arrays, vec![("a".to_string(), a), ("b".to_string(), b)]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! Sparse data points, i.e. high dimensional TF-IDF vectors.
+//!
+//! Only the non-zero values of a [SparseVec](struct.SparseVec.html) are used to compute the
+//! projections of the hash functions.
+//!
+//! # Example
+//! ```
+//! use lsh_rs::{LshMem, SparseVec};
+//! let dim = 10_000;
+//! let mut lsh = LshMem::<f32, _>::new(9, 20, dim).seed(1).srp().unwrap();
+//! let v = SparseVec::new(vec![3, 500, 9000], vec![0.5, 1., 0.2]).unwrap();
+//! let id = lsh.store_sparse(&v).unwrap();
+//! assert!(lsh.query_sparse(&v).unwrap().contains(&id));
+//! ```
+use crate::{
+    data::Numeric,
+    hash::{
+        DensifiedMinHash, Hash, HashPrimitive, SignRandomProjections, VecHash, WeightedMinHash,
+        DWTA, L2,
+    },
+    table::general::HashTables,
+    Error, Result, LSH,
+};
+use ndarray::prelude::*;
+use num::{Float, NumCast, Zero};
+use serde::{Deserialize, Serialize};
+
+/// Sparse data point. `values[i]` is the value at dimension `indices[i]`, all other values are
+/// zero.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparseVec<N> {
+    pub indices: Vec<u32>,
+    pub values: Vec<N>,
+}
+
+impl<N: Numeric> SparseVec<N> {
+    /// Create a sparse data point. The entries are sorted by index, an index may only occur once.
+    pub fn new(indices: Vec<u32>, values: Vec<N>) -> Result<Self> {
+        if indices.len() != values.len() {
+            return Err(Error::Failed(format!(
+                "sparse vector has {} indices and {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        let mut entries: Vec<_> = indices.into_iter().zip(values).collect();
+        entries.sort_unstable_by_key(|&(i, _)| i);
+        let (indices, values): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+        if let Some(i) = duplicate(&indices) {
+            return Err(Error::Failed(format!(
+                "sparse vector has index {} more than once",
+                i
+            )));
+        }
+        Ok(SparseVec { indices, values })
+    }
+
+    /// Keep the non-zero values of a dense data point.
+    pub fn from_dense(v: &[N]) -> Self {
+        let (indices, values) = v
+            .iter()
+            .enumerate()
+            .filter(|(_, &x)| x != Zero::zero())
+            .map(|(i, &x)| (i as u32, x))
+            .unzip();
+        SparseVec { indices, values }
+    }
+
+    pub fn to_dense(&self, dim: usize) -> Vec<N> {
+        let mut v = vec![Zero::zero(); dim];
+        for (i, x) in self.iter() {
+            v[i] = x
+        }
+        v
+    }
+
+    /// Iterate over the indexes and values of the non-zero entries.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, N)> + '_ {
+        self.indices
+            .iter()
+            .zip(&self.values)
+            .map(|(&i, &x)| (i as usize, x))
+    }
+
+    pub(crate) fn validate(&self, dim: usize) -> Result<()> {
+        if self.indices.len() != self.values.len() {
+            return Err(Error::Failed(
+                "sparse data point has a different number of indices and values".to_string(),
+            ));
+        }
+        if self.indices.iter().any(|&i| i as usize >= dim) {
+            return Err(Error::Failed(
+                "sparse data point is not valid, are the dimensions correct?".to_string(),
+            ));
+        }
+        // the fields are public, so the indices are not necessarily sorted.
+        let is_sorted = self.indices.windows(2).all(|w| w[0] < w[1]);
+        if !is_sorted {
+            let mut indices = self.indices.clone();
+            indices.sort_unstable();
+            if duplicate(&indices).is_some() {
+                return Err(Error::Failed(
+                    "sparse data point has duplicate indices".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// First index that occurs more than once in sorted indices.
+fn duplicate(sorted: &[u32]) -> Option<u32> {
+    sorted.windows(2).find(|w| w[0] == w[1]).map(|w| w[0])
+}
+
+/// Hashers that can hash a [SparseVec](struct.SparseVec.html) without creating the dense data
+/// point.
+pub trait SparseVecHash<N>: VecHash<N> {
+    /// Create a hash for a sparse query data point.
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash;
+    /// Create a hash for a sparse data point that is being stored.
+    fn hash_sparse_put(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_sparse_query(v)
+    }
+    /// Create the hashes of a sparse query data point for the hashers of all hash tables.
+    fn hash_sparse_query_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_sparse_query(v)).collect()
+    }
+    /// Create the hashes of a sparse data point that is being stored for the hashers of all hash
+    /// tables.
+    fn hash_sparse_put_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash>
+    where
+        Self: Sized,
+    {
+        hashers.iter().map(|h| h.hash_sparse_put(v)).collect()
+    }
+}
+
+impl<N: Numeric> SparseVecHash<N> for SignRandomProjections<N> {
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash {
+        let mut proj = Array1::zeros(self.hyperplanes.ncols());
+        for (i, x) in v.iter() {
+            proj.scaled_add(x, &self.hyperplanes.row(i))
+        }
+        proj.mapv(|ai| if ai > Zero::zero() { 1 } else { 0 })
+            .to_vec()
+    }
+}
+
+impl<N: Numeric + Float> SparseVecHash<N> for L2<N> {
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash {
+        let mut proj = Array1::zeros(self.a.nrows());
+        for (i, x) in v.iter() {
+            proj.scaled_add(x, &self.a.column(i))
+        }
+        ((proj + &self.b) / self.r)
+            .mapv(|x| {
+                let hp: HashPrimitive = NumCast::from(x.floor())
+                    .expect("Hash value doesnt fit in the Hash number type i8");
+                hp
+            })
+            .to_vec()
+    }
+}
+
+impl<N: Numeric + Float> SparseVecHash<N> for WeightedMinHash<N> {
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_weights(v.iter())
+    }
+}
+
+/// Indexes of the non-zero values.
+fn non_zero<N: Numeric>(v: &SparseVec<N>) -> impl Iterator<Item = usize> + Clone + '_ {
+    v.indices
+        .iter()
+        .zip(&v.values)
+        .filter(|&(_, &x)| x != Zero::zero())
+        .map(|(&i, _)| i as usize)
+}
+
+impl<N: Numeric> SparseVecHash<N> for DensifiedMinHash {
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_set(non_zero(v))
+    }
+
+    fn hash_sparse_query_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash> {
+        Self::hash_set_hashers(hashers, non_zero(v))
+    }
+
+    fn hash_sparse_put_tables(hashers: &[Self], v: &SparseVec<N>) -> Vec<Hash> {
+        Self::hash_set_hashers(hashers, non_zero(v))
+    }
+}
+
+impl<N: Numeric> SparseVecHash<N> for DWTA {
+    fn hash_sparse_query(&self, v: &SparseVec<N>) -> Hash {
+        self.hash_values(v.iter())
+    }
+}
+
+impl<N: Numeric, H: SparseVecHash<N>, T: HashTables<N>> LSH<N, T, H> {
+    /// Store a single sparse data point in storage. Returns id.
+    ///
+    /// A backend either stores dense or sparse data points. Only the ids can be stored for both.
+    ///
+    /// # Arguments
+    /// * `v` - Sparse data point.
+    pub fn store_sparse(&mut self, v: &SparseVec<N>) -> Result<u32> {
+        self.store_input(v)
+    }
+
+    /// Store multiple sparse data points in storage. Returns the ids.
+    ///
+    /// # Arguments
+    /// * `vs` - Sparse data points.
+    pub fn store_sparse_vecs(&mut self, vs: &[SparseVec<N>]) -> Result<Vec<u32>> {
+        self.hash_tables
+            .as_mut()
+            .unwrap()
+            .increase_storage(vs.len());
+        vs.iter().map(|v| self.store_sparse(v)).collect()
+    }
+
+    /// Query all buckets in the hash tables with a sparse data point and return the data point
+    /// indexes. The union of the matching buckets of `L` hash tables is returned.
+    ///
+    /// # Arguments
+    /// * `v` - Sparse query vector
+    pub fn query_sparse(&self, v: &SparseVec<N>) -> Result<Vec<u32>> {
+        let bucket_union = self.query_input_union(v)?;
+        Ok(bucket_union.iter().copied().collect())
+    }
+
+    /// Query all buckets in the hash tables with a sparse data point. The union of the matching
+    /// buckets over the `L` hash tables is returned.
+    ///
+    /// # Arguments
+    /// * `v` - Sparse query vector
+    pub fn query_sparse_bucket(&self, v: &SparseVec<N>) -> Result<Vec<&SparseVec<N>>> {
+        if self.only_index_storage {
+            return Err(Error::Failed(
+                "cannot query bucket, use query_sparse".to_string(),
+            ));
+        }
+        let bucket_union = self.query_input_union(v)?;
+
+        bucket_union
+            .iter()
+            .map(|&idx| self.hash_tables.as_ref().unwrap().idx_to_sparse(idx))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LshMem, LshSqlMem};
+
+    fn data() -> Vec<Vec<f32>> {
+        vec![
+            vec![0., 1., 0., 0., 2., 0.],
+            vec![0., 0., 0., -3., 0., 0.5],
+            vec![1., 0., 0., 0., 0., 0.],
+            vec![0., 0., 0., 0., 0., 0.],
+        ]
+    }
+
+    #[test]
+    fn test_sparse_dense_conversion() {
+        for v in data() {
+            let sv = SparseVec::from_dense(&v);
+            assert_eq!(sv.indices.len(), v.iter().filter(|&&x| x != 0.).count());
+            assert_eq!(sv.to_dense(v.len()), v);
+        }
+        assert!(SparseVec::new(vec![0, 1], vec![1.]).is_err());
+        assert!(SparseVec::new(vec![4, 1, 4], vec![1., 2., 3.]).is_err());
+
+        let sv = SparseVec::new(vec![4, 1], vec![1., 2.]).unwrap();
+        assert_eq!(sv.indices, vec![1, 4]);
+        assert_eq!(sv.values, vec![2., 1.]);
+    }
+
+    #[test]
+    fn test_hash_unsorted_sparse_equals_dense() {
+        let srp = SignRandomProjections::<f32>::new(8, 6, 1);
+        let l2 = L2::<f32>::new(6, 2., 8, 1);
+        let wmh = WeightedMinHash::<f32>::new(8, 6, 1);
+        let sv = SparseVec::new(vec![5, 0, 3], vec![0.5, 1., 2.]).unwrap();
+        let v = sv.to_dense(6);
+        assert_eq!(v, vec![1., 0., 0., 2., 0., 0.5]);
+        assert_eq!(srp.hash_sparse_query(&sv), srp.hash_vec_query(&v));
+        assert_eq!(l2.hash_sparse_query(&sv), l2.hash_vec_query(&v));
+        assert_eq!(wmh.hash_sparse_query(&sv), wmh.hash_vec_query(&v));
+
+        // duplicates in a struct literal are rejected when the data point is used.
+        let sv = SparseVec {
+            indices: vec![3, 0, 3],
+            values: vec![1., 1., 1.],
+        };
+        let mut lsh = LshMem::new(5, 3, 6).seed(1).srp().unwrap();
+        assert!(lsh.store_sparse(&sv).is_err());
+        assert!(lsh.query_sparse(&sv).is_err());
+    }
+
+    #[test]
+    fn test_hash_sparse_equals_dense() {
+        let srp = SignRandomProjections::<f32>::new(8, 6, 1);
+        let l2 = L2::<f32>::new(6, 2., 8, 1);
+        let wmh = WeightedMinHash::<f32>::new(8, 6, 1);
+        let dmh = DensifiedMinHash::new(8, 1);
+        let dwta = DWTA::new(8, 6, 2, 1);
+        for v in data() {
+            let sv = SparseVec::from_dense(&v);
+            assert_eq!(dwta.hash_sparse_query(&sv), dwta.hash_vec_query(&v));
+            assert_eq!(dmh.hash_sparse_query(&sv), dmh.hash_vec_query(&v));
+            assert_eq!(wmh.hash_sparse_query(&sv), wmh.hash_vec_query(&v));
+            assert_eq!(srp.hash_sparse_query(&sv), srp.hash_vec_query(&v));
+            assert_eq!(l2.hash_sparse_query(&sv), l2.hash_vec_query(&v));
+        }
+    }
+
+    #[test]
+    fn test_store_query_sparse() {
+        let vs: Vec<_> = data().iter().map(|v| SparseVec::from_dense(v)).collect();
+        let mut lsh = LshMem::new(5, 3, 6).seed(1).l2(4.).unwrap();
+        assert_eq!(lsh.store_sparse_vecs(&vs).unwrap(), vec![0, 1, 2, 3]);
+        for (id, v) in vs.iter().enumerate() {
+            assert!(lsh.query_sparse(v).unwrap().contains(&(id as u32)));
+            assert!(lsh.query_sparse_bucket(v).unwrap().contains(&v));
+        }
+        lsh.multi_probe(4);
+        assert!(lsh.query_sparse(&vs[0]).unwrap().contains(&0));
+
+        // out of bounds index
+        let v = SparseVec::new(vec![6], vec![1.]).unwrap();
+        assert!(lsh.store_sparse(&v).is_err());
+        // dense and sparse data points can not be mixed
+        assert!(lsh.store_vec(&data()[0]).is_err());
+
+        let mut lsh = LshSqlMem::new(5, 3, 6).seed(1).srp().unwrap();
+        assert_eq!(lsh.store_sparse_vecs(&vs).unwrap(), vec![0, 1, 2, 3]);
+        assert!(lsh.query_sparse(&vs[1]).unwrap().contains(&1));
+    }
+}
@@ -1,13 +1,16 @@
-use crate::{dist::l2_norm, hash::HashPrimitive, HashTables, LshMem, Result, VecHash};
+use crate::{
+    eval::{brute_force_knn, evaluate, EvalRes, Metric},
+    hash::HashPrimitive,
+    HashTables, LshMem, Result, VecHash,
+};
 use fnv::FnvHashSet;
-use ndarray::aview1;
 use rayon::prelude::*;
 use statrs::{
     consts::SQRT_2PI,
     distribution::{Normal, Univariate},
+    function::factorial::binomial,
 };
 use std::f64::consts::PI;
-use std::time::Instant;
 
 /// Hash collision probability for L2 distance.
 ///
@@ -24,6 +27,19 @@ pub fn l2_ph(r: f64, c: f64) -> f64 {
         - 2. / (SQRT_2PI * r / c) * (1. - (-(r.powf(2.) / (2. * c.powf(2.)))).exp())
 }
 
+/// Hash collision probability for L1 distance.
+///
+/// Assumes R normalized data points. So R = 1.
+/// Compute 𝑃1 if c = 1.
+/// Compute 𝑃2 if c = c
+///
+/// # Arguments
+/// * `r` - Parameter of l1 hash function (also noted as `w`)
+/// * `c` - Approximation factor. cR.
+pub fn l1_ph(r: f64, c: f64) -> f64 {
+    2. * (r / c).atan() / PI - c / (PI * r) * (1. + (r / c).powf(2.)).ln()
+}
+
 /// Hash collision probability for Sign Random Projections
 /// # Arguments
 /// * `cosine_sim` - Cosine similarity.
@@ -31,6 +47,113 @@ pub fn srp_ph(cosine_sim: f64) -> f64 {
     1. - cosine_sim.acos() / PI
 }
 
+/// Hash collision probability for Weighted MinHash and Densified MinHash.
+///
+/// Only 8 bits of every sample are stored, so different samples collide with a probability of
+/// 1/256.
+/// # Arguments
+/// * `jaccard_sim` - (Generalized) Jaccard similarity.
+pub fn weighted_minhash_ph(jaccard_sim: f64) -> f64 {
+    jaccard_sim + (1. - jaccard_sim) / 256.
+}
+
+/// Hash collision probability for Bit Sampling.
+/// # Arguments
+/// * `hamming_dist` - Hamming distance between the binary vectors.
+/// * `n_bits` - Number of bits of the binary vectors.
+pub fn bit_sampling_ph(hamming_dist: u32, n_bits: usize) -> f64 {
+    1. - hamming_dist as f64 / n_bits as f64
+}
+
+/// Hash collision probability for Maximum Inner Product Search.
+///
+/// MIPS transforms the data points and queries such that the L2 hash family can be used.
+/// The collision probability is that of L2 at the distance between the transformed vectors:
+///
+/// ||P(x) - Q(q)||² = ||x||² + 1 - 2xᵀq + Σ (||x||²ⁱ - ½)² for i in 1..=m
+///
+/// # Arguments
+/// * `inner_prod` - Inner product between the scaled data point (||x|| <= U) and the
+///   normalized query (||q|| = 1).
+/// * `norm` - L2 norm of the scaled data point.
+/// * `r` - Parameter of l2 hash function.
+/// * `m` - Parameter of MIPS hash function.
+pub fn mips_ph(inner_prod: f64, norm: f64, r: f64, m: usize) -> f64 {
+    let norm_sq = norm.powf(2.);
+    let extra: f64 = (1..=m)
+        .map(|i| (norm_sq.powf(i as f64) - 0.5).powf(2.))
+        .sum();
+    let dist = (norm_sq + 1. - 2. * inner_prod + extra).sqrt();
+    l2_ph(r, dist)
+}
+
+/// Monte Carlo estimate of the collision probability of a single hash value.
+///
+/// Useful for hash families that have no analytic collision probability. For every trial a new
+/// hasher is created and the fraction of equal hash values between `p` and `q` is measured.
+///
+/// # Arguments
+/// * `make_hasher` - Creates a hasher from a seed.
+/// * `p` - Data point (hashed with `hash_vec_put`).
+/// * `q` - Query (hashed with `hash_vec_query`).
+/// * `n_trials` - Number of hashers created.
+pub fn monte_carlo_ph<H, F>(make_hasher: F, p: &[f32], q: &[f32], n_trials: usize) -> f64
+where
+    H: VecHash<f32>,
+    F: Fn(u64) -> H,
+{
+    let mut equal = 0;
+    let mut total = 0;
+    for seed in 1..=n_trials as u64 {
+        let hasher = make_hasher(seed);
+        let hp = hasher.hash_vec_put(p);
+        let hq = hasher.hash_vec_query(q);
+        equal += hp.iter().zip(&hq).filter(|(a, b)| a == b).count();
+        total += hp.len();
+    }
+    equal as f64 / total as f64
+}
+
+/// Probability of a collision in a single hash table with step wise multi-probing.
+///
+/// Every hash value of the data point is assumed to equal that of the query with probability
+/// `p1` and otherwise to be one step away. With `d` differing hash values, the point is found if
+/// the probing sequence reaches the perturbation of those `d` values. The probing sequence is the
+/// same as used by [LSH](../struct.LSH.html) with `multi_probe`. For query directed probing this is
+/// a lower bound.
+///
+/// # Arguments
+/// * `p1` - Collision probability of a single hash value.
+/// * `k` - Number of hash projections.
+/// * `budget` - Number of probes.
+pub fn multi_probe_ph(p1: f64, k: usize, budget: usize) -> f64 {
+    let mut ph = p1.powf(k as f64);
+    let mut budget = budget as f64;
+    let mut d = 1;
+    while budget > 0. && d <= k {
+        // perturbations of d indexes in the probing sequence
+        let n_probes = binomial(2 * k as u64, d as u64);
+        let probed = budget.min(n_probes);
+        let p_d = binomial(k as u64, d as u64) * p1.powf((k - d) as f64) * (1. - p1).powf(d as f64);
+        ph += p_d * probed / n_probes;
+
+        budget -= binomial(k as u64, d as u64) * 2.;
+        d += 1;
+    }
+    ph.min(1.)
+}
+
+/// Probability of finding a point with collision probability `p1` in any of the `L` hash tables.
+///
+/// # Arguments
+/// * `p1` - Collision probability of a single hash value.
+/// * `k` - Number of hash projections.
+/// * `l` - Number of hash tables.
+/// * `budget` - Number of probes per table. 0 if no multi-probing is used.
+pub fn success_prob(p1: f64, k: usize, l: usize, budget: usize) -> f64 {
+    1. - (1. - multi_probe_ph(p1, k, budget)).powf(l as f64)
+}
+
 ///
 /// Return NN w/ probability 1 - δ. Generic formula.
 ///
@@ -42,6 +165,17 @@ pub fn estimate_l(delta: f64, p1: f64, k: usize) -> usize {
     (delta.ln() / (1. - p1.powf(k as f64)).ln()).round() as usize
 }
 
+/// Return NN w/ probability 1 - δ when multi-probing `budget` buckets per table.
+///
+/// # Arguments
+/// * `delta` - Prob. not returned NN.
+/// * `p1` - P1 in literature.
+/// * `k` - Number of hash projections.
+/// * `budget` - Number of probes per table.
+pub fn estimate_l_multi_probe(delta: f64, p1: f64, k: usize, budget: usize) -> usize {
+    (delta.ln() / (1. - multi_probe_ph(p1, k, budget)).ln()).round() as usize
+}
+
 #[derive(Debug)]
 pub struct OptRes {
     pub k: usize,
@@ -52,54 +186,50 @@ pub struct OptRes {
     pub max_len: usize,
     pub avg_len: f32,
     pub unique_hash_values: FnvHashSet<HashPrimitive>,
+    /// Recall and latency measured on the held out queries.
+    pub eval: EvalRes,
 }
 
 fn lsh_to_result<T: 'static + VecHash<f32> + Send + Sync + Clone>(
     lsh: LshMem<f32, T>,
     vs: &[Vec<f32>],
-    k: usize,
-    l: usize,
+    qs: &[Vec<f32>],
+    ground_truth: &[Vec<u32>],
+    top_k: usize,
+    metric: Metric,
+    (k, l): (usize, usize),
 ) -> Result<OptRes> {
     let mut lsh = lsh;
     lsh.store_vecs(vs)?;
-    let mut search_time = 0.;
-    let mut hash_time = 0.;
-    let mut bucket_lengths = Vec::with_capacity(vs.len());
-    for v in vs {
-        let th = Instant::now();
-        let mut bucket_ids = lsh.query_bucket_ids(v)?;
-        hash_time += th.elapsed().as_secs_f64();
-
-        bucket_lengths.push(bucket_ids.len());
-
-        let t0 = Instant::now();
-        let q = aview1(&v);
-        bucket_ids.par_sort_by_key(|&idx| {
-            let p = &vs[idx as usize];
-            let dist = &aview1(&p) - &q;
-            let l2 = l2_norm(dist.as_slice().unwrap());
-            (l2 * 1e5) as i32
-        });
-        let duration = t0.elapsed();
-        search_time += duration.as_secs_f64();
-    }
-    let min = *bucket_lengths.iter().min().unwrap_or(&(0 as usize));
-    let max = *bucket_lengths.iter().max().unwrap_or(&(0 as usize));
-    let avg = bucket_lengths.iter().sum::<usize>() as f32 / bucket_lengths.len() as f32;
+    let eval = evaluate(&lsh, vs, qs, ground_truth, top_k, metric)?;
     let unique_hash_values = lsh.hash_tables.unwrap().get_unique_hash_int();
     Ok(OptRes {
         k,
         l,
-        search_time,
-        hash_time,
-        min_len: min,
-        max_len: max,
-        avg_len: avg,
+        search_time: eval.rerank_time,
+        hash_time: eval.query_time,
+        min_len: eval.min_candidates,
+        max_len: eval.max_candidates,
+        avg_len: eval.avg_candidates as f32,
         unique_hash_values,
+        eval,
     })
 }
 
+/// Every data point is its own nearest neighbor when the data points are used as queries.
+fn self_ground_truth(vs: &[Vec<f32>]) -> Vec<Vec<u32>> {
+    (0..vs.len() as u32).map(|i| vec![i]).collect()
+}
+
+/// Parameters *(K, L)* of the grid search.
+fn grid(delta: f64, p1: f64, k: &[usize]) -> Vec<(usize, usize)> {
+    k.iter().map(|&k| (k, estimate_l(delta, p1, k))).collect()
+}
+
 /// Does a grid search over parameter *K* where *L* is determined by the `estimate_l` function.
+/// The data points themselves are queried. See
+/// [optimize_srp_params_with_queries](fn.optimize_srp_params_with_queries.html) to measure the
+/// recall on held out queries.
 ///
 /// # Arguments
 /// * `delta` - Probability of not returning NN. P(NN) = 1 - δ
@@ -113,25 +243,59 @@ pub fn optimize_srp_params(
     k: &[usize],
     vs: &[Vec<f32>],
 ) -> Result<Vec<OptRes>> {
-    let mut params = vec![];
-    let p1 = srp_ph(cosine_sim);
-    for _k in k {
-        let l = estimate_l(delta, p1, *_k);
-        params.push((*_k, l))
-    }
-    let result = params
+    let params = grid(delta, srp_ph(cosine_sim), k);
+    srp_grid_search(&params, dim, vs, vs, &self_ground_truth(vs), 1)
+}
+
+/// Same as [optimize_srp_params](fn.optimize_srp_params.html), but the recall@k is measured on
+/// held out query points.
+///
+/// # Arguments
+/// * `delta` - Probability of not returning NN. P(NN) = 1 - δ
+/// * `cosine_sim` - Cosine similarity distance within which the nearest neighbor should exist.
+/// * `dim` - Dimension of the data points.
+/// * `vs` - Data points.
+/// * `qs` - Held out query points used to measure recall.
+/// * `top_k` - Number of nearest neighbors used for recall@k.
+pub fn optimize_srp_params_with_queries(
+    delta: f64,
+    cosine_sim: f64,
+    dim: usize,
+    k: &[usize],
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    top_k: usize,
+) -> Result<Vec<OptRes>> {
+    let params = grid(delta, srp_ph(cosine_sim), k);
+    let ground_truth = brute_force_knn(vs, qs, top_k, Metric::Cosine);
+    srp_grid_search(&params, dim, vs, qs, &ground_truth, top_k)
+}
+
+fn srp_grid_search(
+    params: &[(usize, usize)],
+    dim: usize,
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    ground_truth: &[Vec<u32>],
+    top_k: usize,
+) -> Result<Vec<OptRes>> {
+    params
         .par_iter()
         .map(|&(k, l)| {
             let lsh = LshMem::new(k, l, dim).srp()?;
-            lsh_to_result(lsh, vs, k, l)
+            lsh_to_result(lsh, vs, qs, ground_truth, top_k, Metric::Cosine, (k, l))
         })
-        .collect();
-    result
+        .collect()
 }
 
+/// Parameter *R* of the L2 grid search.
+const L2_R: f32 = 4.0;
+
 /// Does a grid search over parameter *K* where *L* is determined by the `estimate_l` function.
 /// Note that the data already should be normalized by dividing the data points
-/// by the query distance *R*.
+/// by the query distance *R*. The data points themselves are queried. See
+/// [optimize_l2_params_with_queries](fn.optimize_l2_params_with_queries.html) to measure the
+/// recall on held out queries.
 ///
 /// # Arguments
 /// * `delta` - Probability of not returning NN. P(NN) = 1 - δ
@@ -143,26 +307,53 @@ pub fn optimize_l2_params(
     k: &[usize],
     vs: &[Vec<f32>],
 ) -> Result<Vec<OptRes>> {
-    let mut params = vec![];
-    let r = 4.0;
-    let p1 = l2_ph(r as f64, 1.);
-    for _k in k {
-        let l = estimate_l(delta, p1, *_k as usize);
-        params.push((r, *_k, l))
-    }
-    let result = params
+    let params = grid(delta, l2_ph(L2_R as f64, 1.), k);
+    l2_grid_search(&params, dim, vs, vs, &self_ground_truth(vs), 1)
+}
+
+/// Same as [optimize_l2_params](fn.optimize_l2_params.html), but the recall@k is measured on
+/// held out query points.
+///
+/// # Arguments
+/// * `delta` - Probability of not returning NN. P(NN) = 1 - δ
+/// * `dim` - Dimension of the data points.
+/// * `vs` - Data points.
+/// * `qs` - Held out query points used to measure recall.
+/// * `top_k` - Number of nearest neighbors used for recall@k.
+pub fn optimize_l2_params_with_queries(
+    delta: f64,
+    dim: usize,
+    k: &[usize],
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    top_k: usize,
+) -> Result<Vec<OptRes>> {
+    let params = grid(delta, l2_ph(L2_R as f64, 1.), k);
+    let ground_truth = brute_force_knn(vs, qs, top_k, Metric::L2);
+    l2_grid_search(&params, dim, vs, qs, &ground_truth, top_k)
+}
+
+fn l2_grid_search(
+    params: &[(usize, usize)],
+    dim: usize,
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    ground_truth: &[Vec<u32>],
+    top_k: usize,
+) -> Result<Vec<OptRes>> {
+    params
         .par_iter()
-        .map(|&(r, k, l)| {
-            let lsh = LshMem::new(k, l, dim).l2(r as f32)?;
-            lsh_to_result(lsh, vs, k, l)
+        .map(|&(k, l)| {
+            let lsh = LshMem::new(k, l, dim).l2(L2_R)?;
+            lsh_to_result(lsh, vs, qs, ground_truth, top_k, Metric::L2, (k, l))
         })
-        .collect();
-    result
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{SignRandomProjections, L1};
 
     #[test]
     fn test_l2_ph() {
@@ -172,6 +363,16 @@ mod test {
         assert_eq!(0.609548422215397, l2_ph(r, c) as f32);
     }
 
+    #[test]
+    fn test_l1_ph() {
+        // the l1 distance between p and q is 2.
+        let p = [0., 0., 0.];
+        let q = [1., 0.5, -0.5];
+        let ph = monte_carlo_ph(|seed| L1::new(3, 4., 16, seed), &p, &q, 500);
+        assert!((ph - l1_ph(4., 2.)).abs() < 0.02);
+        assert!(l1_ph(4., 1.) > l1_ph(4., 2.));
+    }
+
     #[test]
     fn test_estimate_l() {
         let delta = 0.2;
@@ -179,4 +380,60 @@ mod test {
         let k = 5;
         assert_eq!(20, estimate_l(delta, p1, k));
     }
+
+    #[test]
+    fn test_monte_carlo_ph() {
+        let p = [1., 0.5, 0.2];
+        let q = [0.9, 0.7, -0.1];
+        let cos = crate::dist::cosine_sim(&p, &q) as f64;
+        let ph = monte_carlo_ph(|seed| SignRandomProjections::new(16, 3, seed), &p, &q, 500);
+        assert!((ph - srp_ph(cos)).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_multi_probe_ph() {
+        let (p1, k, l) = (0.8f64, 6, 5);
+        let base = 1. - (1. - p1.powf(k as f64)).powf(l as f64);
+        assert!((success_prob(p1, k, l, 0) - base).abs() < 1e-12);
+        assert!(success_prob(p1, k, l, 4) > base);
+        assert!(multi_probe_ph(p1, k, 1_000_000) <= 1.);
+        assert!(estimate_l_multi_probe(0.1, p1, k, 12) < estimate_l(0.1, p1, k));
+    }
+
+    #[test]
+    fn test_weighted_minhash_ph() {
+        assert_eq!(weighted_minhash_ph(1.), 1.);
+        assert_eq!(weighted_minhash_ph(0.), 1. / 256.);
+    }
+
+    #[test]
+    fn test_bit_sampling_ph() {
+        assert_eq!(bit_sampling_ph(0, 128), 1.);
+        assert_eq!(bit_sampling_ph(32, 128), 0.75);
+    }
+
+    #[test]
+    fn test_mips_ph() {
+        // a larger inner product leads to a higher collision probability
+        assert!(mips_ph(0.8, 0.83, 2.5, 3) > mips_ph(0.2, 0.83, 2.5, 3));
+    }
+
+    #[test]
+    fn test_optimize_srp_params() {
+        let vs: Vec<Vec<f32>> = (0..100)
+            .map(|i| vec![(i as f32).sin(), (i as f32).cos(), 0.5])
+            .collect();
+        let qs = vec![vec![0.1, 0.9, 0.5], vec![-0.5, 0.3, 0.5]];
+        let res = optimize_srp_params_with_queries(0.2, 0.9, 3, &[2, 4], &vs, &qs, 3).unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res
+            .iter()
+            .all(|r| r.eval.recall >= 0. && r.eval.recall <= 1.));
+
+        // the data points themselves are queried and always found
+        let res = optimize_srp_params(0.2, 0.9, 3, &[2, 4], &vs).unwrap();
+        assert!(res.iter().all(|r| r.eval.recall == 1.));
+        let res = optimize_l2_params(0.2, 3, &[2], &vs).unwrap();
+        assert_eq!(res[0].eval.n_queries, 100);
+    }
 }
@@ -1,14 +1,132 @@
 use crate::{
     data::Numeric,
     hash::{Hash, HashPrimitive},
-    Error, Result, VecHash,
+    sparse::SparseVec,
+    Error, Result,
 };
 use fnv::{FnvHashSet as HashSet, FnvHashSet};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Bucket contains indexes to VecStore
 pub type Bucket = HashSet<u32>;
 
+/// Statistics of a single hash table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    /// Number of buckets (unique hashes).
+    pub n_buckets: usize,
+    /// Maps a bucket size to the number of buckets of that size.
+    pub bucket_size_histogram: BTreeMap<usize, usize>,
+    pub min_bucket_size: usize,
+    pub max_bucket_size: usize,
+    pub mean_bucket_size: f64,
+    pub std_dev_bucket_size: f64,
+    /// Number of data point ids stored in the table.
+    pub n_points: usize,
+    /// Approximation of the memory used by the table.
+    pub memory_bytes: usize,
+    /// Unique values found in the hashes.
+    pub unique_hash_values: BTreeSet<HashPrimitive>,
+}
+
+impl TableStats {
+    /// Derive the bucket size statistics from a histogram.
+    pub(crate) fn from_histogram(bucket_size_histogram: BTreeMap<usize, usize>) -> Self {
+        let n_buckets: usize = bucket_size_histogram.values().sum();
+        let n_points: usize = bucket_size_histogram
+            .iter()
+            .map(|(size, count)| size * count)
+            .sum();
+        let mean = if n_buckets > 0 {
+            n_points as f64 / n_buckets as f64
+        } else {
+            0.
+        };
+        let var = if n_buckets > 0 {
+            bucket_size_histogram
+                .iter()
+                .map(|(&size, &count)| (size as f64 - mean).powf(2.) * count as f64)
+                .sum::<f64>()
+                / n_buckets as f64
+        } else {
+            0.
+        };
+        TableStats {
+            n_buckets,
+            min_bucket_size: bucket_size_histogram.keys().next().copied().unwrap_or(0),
+            max_bucket_size: bucket_size_histogram.keys().last().copied().unwrap_or(0),
+            mean_bucket_size: mean,
+            std_dev_bucket_size: var.sqrt(),
+            n_points,
+            bucket_size_histogram,
+            ..Default::default()
+        }
+    }
+}
+
+/// Statistics of all `L` hash tables. Can be serialized to JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub n_hash_tables: usize,
+    /// Number of stored data points.
+    pub n_points: usize,
+    /// Approximation of the memory used by the backend.
+    pub memory_bytes: usize,
+    pub tables: Vec<TableStats>,
+}
+
+impl Stats {
+    pub(crate) fn from_tables(tables: Vec<TableStats>, extra_bytes: usize) -> Self {
+        Stats {
+            n_hash_tables: tables.len(),
+            n_points: tables.iter().map(|t| t.n_points).max().unwrap_or(0),
+            memory_bytes: tables.iter().map(|t| t.memory_bytes).sum::<usize>() + extra_bytes,
+            tables,
+        }
+    }
+
+    /// Statistics over the buckets of all hash tables combined.
+    pub fn combined(&self) -> TableStats {
+        let mut histogram = BTreeMap::new();
+        let mut unique_hash_values = BTreeSet::new();
+        for t in &self.tables {
+            for (&size, &count) in &t.bucket_size_histogram {
+                *histogram.entry(size).or_insert(0) += count
+            }
+            unique_hash_values.extend(t.unique_hash_values.iter().copied());
+        }
+        TableStats {
+            memory_bytes: self.memory_bytes,
+            unique_hash_values,
+            ..TableStats::from_histogram(histogram)
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Human readable summary.
+    pub fn describe(&self) -> String {
+        let c = self.combined();
+        let mut out = format!("No. of tables: {}\n", self.n_hash_tables);
+        out.push_str(&format!("No. of data points: {}\n", self.n_points));
+        out.push_str(&format!("Memory (bytes): {}\n", self.memory_bytes));
+        out.push_str(&format!(
+            "Unique hash values:\n{:?}\n",
+            c.unique_hash_values
+        ));
+        out.push_str("\nHash collisions:\n");
+        out.push_str(&format!("buckets:\t{:?}\n", c.n_buckets));
+        out.push_str(&format!("avg:\t{:?}\n", c.mean_bucket_size));
+        out.push_str(&format!("std-dev:\t{:?}\n", c.std_dev_bucket_size));
+        out.push_str(&format!("min:\t{:?}\n", c.min_bucket_size));
+        out.push_str(&format!("max:\t{:?}\n", c.max_bucket_size));
+        out
+    }
+}
+
 /// Hashtable consisting of `L` Hash tables.
 pub trait HashTables<N: Numeric> {
     fn new(n_hash_tables: usize, only_index_storage: bool, db_path: &str) -> Result<Box<Self>>;
@@ -20,6 +138,17 @@ pub trait HashTables<N: Numeric> {
     /// * `hash_table` - Number of the hash_table to store the vector. Ranging from 0 to L.
     fn put(&mut self, hash: Hash, d: &[N], hash_table: usize) -> Result<u32>;
 
+    /// Same as [put](#tymethod.put) for a sparse data point.
+    fn put_sparse(&mut self, _hash: Hash, _d: &SparseVec<N>, _hash_table: usize) -> Result<u32> {
+        Err(Error::NotImplemented)
+    }
+
+    /// Same as [put](#tymethod.put) for a custom [Input](trait.Input.html) data point that is
+    /// serialized with bincode.
+    fn put_input(&mut self, _hash: Hash, _d: &[u8], _hash_table: usize) -> Result<u32> {
+        Err(Error::NotImplemented)
+    }
+
     fn delete(&mut self, _hash: &Hash, _d: &[N], _hash_table: usize) -> Result<()> {
         Err(Error::NotImplemented)
     }
@@ -37,26 +166,65 @@ pub trait HashTables<N: Numeric> {
     /// Query the whole bucket
     fn query_bucket(&self, hash: &Hash, hash_table: usize) -> Result<Bucket>;
 
+    /// All buckets of a hash table.
+    fn buckets(&self, _hash_table: usize) -> Result<Vec<Bucket>> {
+        Err(Error::NotImplemented)
+    }
+
     fn idx_to_datapoint(&self, _idx: u32) -> Result<&Vec<N>> {
         Err(Error::NotImplemented)
     }
 
+    fn idx_to_sparse(&self, _idx: u32) -> Result<&SparseVec<N>> {
+        Err(Error::NotImplemented)
+    }
+
+    /// Custom [Input](trait.Input.html) data point serialized with bincode.
+    fn idx_to_input(&self, _idx: u32) -> Result<&[u8]> {
+        Err(Error::NotImplemented)
+    }
+
     fn increase_storage(&mut self, _size: usize) {}
 
-    fn describe(&self) -> Result<String> {
+    /// Collect statistics of every hash table.
+    fn stats(&self) -> Result<Stats> {
         Err(Error::NotImplemented)
     }
 
+    /// Human readable summary of [stats](#method.stats).
+    fn describe(&self) -> Result<String> {
+        Ok(self.stats()?.describe())
+    }
+
     // Should fail if hashers already stored.
-    fn store_hashers<H: VecHash<N> + Serialize>(&mut self, _hashers: &[H]) -> Result<()> {
+    fn store_hashers<H: Serialize>(&mut self, _hashers: &[H]) -> Result<()> {
         Ok(())
     }
 
     // If store_hashers fails, load_hasher can be executed
-    fn load_hashers<H: VecHash<N> + DeserializeOwned>(&self) -> Result<Vec<H>> {
+    fn load_hashers<H: DeserializeOwned>(&self) -> Result<Vec<H>> {
         // just chose an error to make a default trait implementation
         Err(Error::NotImplemented)
     }
 
     fn get_unique_hash_int(&self) -> FnvHashSet<HashPrimitive>;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats_from_histogram() {
+        let mut hist = BTreeMap::new();
+        hist.insert(1, 2);
+        hist.insert(4, 1);
+        let s = TableStats::from_histogram(hist);
+        assert_eq!(s.n_buckets, 3);
+        assert_eq!(s.n_points, 6);
+        assert_eq!(s.min_bucket_size, 1);
+        assert_eq!(s.max_bucket_size, 4);
+        assert_eq!(s.mean_bucket_size, 2.);
+        assert_eq!(s.std_dev_bucket_size, 2f64.sqrt());
+    }
+}
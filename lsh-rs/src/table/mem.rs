@@ -1,34 +1,75 @@
 use crate::{
-    constants::DESCRIBE_MAX,
     data::Numeric,
     hash::{Hash, HashPrimitive},
-    table::general::{Bucket, HashTables},
+    sparse::SparseVec,
+    table::general::{Bucket, HashTables, Stats, TableStats},
     utils::{all_eq, increase_capacity},
     Error, Result,
 };
 use fnv::{FnvHashMap as HashMap, FnvHashSet};
-use serde::{Deserialize, Serialize};
-use std::iter::FromIterator;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem::size_of;
 
 /// Indexible vector storage.
 /// indexes will be stored in hashtables. The original vectors can be looked up in this data structure.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct VecStore<N> {
     pub map: Vec<Vec<N>>,
+    /// Sparse data points.
+    pub sparse: Vec<SparseVec<N>>,
+    /// Custom [Input](../input/trait.Input.html) data points serialized with bincode.
+    ///
+    /// Only one of `map`, `sparse` and `inputs` is used, so that the index is the id.
+    pub inputs: Vec<Vec<u8>>,
 }
 
 impl<N: Numeric> VecStore<N> {
-    fn push(&mut self, d: Vec<N>) -> u32 {
+    fn push(&mut self, d: Vec<N>) -> Result<u32> {
+        if !self.sparse.is_empty() || !self.inputs.is_empty() {
+            return Err(mixed_err());
+        }
         self.map.push(d);
-        (self.map.len() - 1) as u32
+        Ok((self.map.len() - 1) as u32)
+    }
+
+    fn push_sparse(&mut self, d: SparseVec<N>) -> Result<u32> {
+        if !self.map.is_empty() || !self.inputs.is_empty() {
+            return Err(mixed_err());
+        }
+        self.sparse.push(d);
+        Ok((self.sparse.len() - 1) as u32)
+    }
+
+    fn push_input(&mut self, d: Vec<u8>) -> Result<u32> {
+        if !self.map.is_empty() || !self.sparse.is_empty() {
+            return Err(mixed_err());
+        }
+        self.inputs.push(d);
+        Ok((self.inputs.len() - 1) as u32)
     }
 
     fn position(&self, d: &[N]) -> Option<u32> {
         self.map.iter().position(|x| all_eq(x, d)).map(|x| x as u32)
     }
 
-    fn get(&self, idx: u32) -> &Vec<N> {
-        &self.map[idx as usize]
+    fn get(&self, idx: u32) -> Result<&Vec<N>> {
+        self.map
+            .get(idx as usize)
+            .ok_or_else(|| Error::Failed(format!("no dense data point is stored with id {}", idx)))
+    }
+
+    fn get_sparse(&self, idx: u32) -> Result<&SparseVec<N>> {
+        self.sparse
+            .get(idx as usize)
+            .ok_or_else(|| Error::Failed(format!("no sparse data point is stored with id {}", idx)))
+    }
+
+    fn get_input(&self, idx: u32) -> Result<&[u8]> {
+        self.inputs
+            .get(idx as usize)
+            .map(|d| d.as_slice())
+            .ok_or_else(|| Error::Failed(format!("no custom data point is stored with id {}", idx)))
     }
 
     fn increase_storage(&mut self, size: usize) {
@@ -36,6 +77,12 @@ impl<N: Numeric> VecStore<N> {
     }
 }
 
+fn mixed_err() -> Error {
+    Error::Failed(
+        "dense, sparse and custom data points can not be stored in the same table".to_string(),
+    )
+}
+
 /// In memory backend for [LSH](struct.LSH.html).
 #[derive(Deserialize, Serialize)]
 pub struct MemoryTable<N> {
@@ -46,6 +93,52 @@ pub struct MemoryTable<N> {
     counter: u32,
 }
 
+/// Layout of a [MemoryTable](struct.MemoryTable.html) in dumps that were created before sparse
+/// data points could be stored.
+#[derive(Deserialize, Serialize)]
+struct MemoryTableV0<N> {
+    hash_tables: Vec<HashMap<Hash, Bucket>>,
+    n_hash_tables: usize,
+    vec_store: VecStoreV0<N>,
+    only_index_storage: bool,
+    counter: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+struct VecStoreV0<N> {
+    map: Vec<Vec<N>>,
+}
+
+impl<N> From<MemoryTableV0<N>> for MemoryTable<N> {
+    fn from(t: MemoryTableV0<N>) -> Self {
+        MemoryTable {
+            hash_tables: t.hash_tables,
+            n_hash_tables: t.n_hash_tables,
+            vec_store: VecStore {
+                map: t.vec_store.map,
+                sparse: vec![],
+                inputs: vec![],
+            },
+            only_index_storage: t.only_index_storage,
+            counter: t.counter,
+        }
+    }
+}
+
+/// Deserialize a backend that is serialized with bincode. Dumps in the old layout without
+/// sparse data points are converted.
+pub(crate) fn deserialize_tables<N: DeserializeOwned>(
+    buf: &[u8],
+) -> Result<Option<MemoryTable<N>>> {
+    match bincode::deserialize(buf) {
+        Ok(tables) => Ok(tables),
+        Err(e) => match bincode::deserialize::<Option<MemoryTableV0<N>>>(buf) {
+            Ok(tables) => Ok(tables.map(MemoryTable::from)),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
 impl<N> MemoryTable<N> {
     fn remove_idx(&mut self, idx: u32, hash: &Hash, hash_table: usize) -> Result<()> {
         let tbl = &mut self.hash_tables[hash_table];
@@ -71,7 +164,11 @@ impl<N: Numeric> HashTables<N> for MemoryTable<N> {
         // this way the capacity can be approximated by the number of DataPoints that will
         // be stored.
         let hash_tables = vec![HashMap::default(); n_hash_tables];
-        let vector_store = VecStore { map: vec![] };
+        let vector_store = VecStore {
+            map: vec![],
+            sparse: vec![],
+            inputs: vec![],
+        };
         let m = MemoryTable {
             hash_tables,
             n_hash_tables,
@@ -83,16 +180,43 @@ impl<N: Numeric> HashTables<N> for MemoryTable<N> {
     }
 
     fn put(&mut self, hash: Hash, d: &[N], hash_table: usize) -> Result<u32> {
+        // There are N hash_tables per unique vector. So we only store
+        // the unique v hash_table 0 and increment the counter (the id)
+        // after we've update the last (N) hash_table.
+        if (hash_table == 0) && (!self.only_index_storage) {
+            self.vec_store.push(d.to_vec())?;
+        }
         // Store hash and id/idx
         let idx = self.counter;
         self.insert_idx(idx, hash, hash_table);
 
-        // There are N hash_tables per unique vector. So we only store
-        // the unique v hash_table 0 and increment the counter (the id)
-        // after we've update the last (N) hash_table.
+        if hash_table == self.n_hash_tables - 1 {
+            self.counter += 1
+        }
+        Ok(idx)
+    }
+
+    fn put_sparse(&mut self, hash: Hash, d: &SparseVec<N>, hash_table: usize) -> Result<u32> {
+        if (hash_table == 0) && (!self.only_index_storage) {
+            self.vec_store.push_sparse(d.clone())?;
+        }
+        let idx = self.counter;
+        self.insert_idx(idx, hash, hash_table);
+
+        if hash_table == self.n_hash_tables - 1 {
+            self.counter += 1
+        }
+        Ok(idx)
+    }
+
+    fn put_input(&mut self, hash: Hash, d: &[u8], hash_table: usize) -> Result<u32> {
         if (hash_table == 0) && (!self.only_index_storage) {
-            self.vec_store.push(d.to_vec());
-        } else if hash_table == self.n_hash_tables - 1 {
+            self.vec_store.push_input(d.to_vec())?;
+        }
+        let idx = self.counter;
+        self.insert_idx(idx, hash, hash_table);
+
+        if hash_table == self.n_hash_tables - 1 {
             self.counter += 1
         }
         Ok(idx)
@@ -131,8 +255,23 @@ impl<N: Numeric> HashTables<N> for MemoryTable<N> {
         }
     }
 
+    fn buckets(&self, hash_table: usize) -> Result<Vec<Bucket>> {
+        match self.hash_tables.get(hash_table) {
+            None => Err(Error::TableNotExist),
+            Some(tbl) => Ok(tbl.values().cloned().collect()),
+        }
+    }
+
     fn idx_to_datapoint(&self, idx: u32) -> Result<&Vec<N>> {
-        Ok(self.vec_store.get(idx))
+        self.vec_store.get(idx)
+    }
+
+    fn idx_to_sparse(&self, idx: u32) -> Result<&SparseVec<N>> {
+        self.vec_store.get_sparse(idx)
+    }
+
+    fn idx_to_input(&self, idx: u32) -> Result<&[u8]> {
+        self.vec_store.get_input(idx)
     }
 
     fn increase_storage(&mut self, size: usize) {
@@ -140,47 +279,50 @@ impl<N: Numeric> HashTables<N> for MemoryTable<N> {
         self.vec_store.increase_storage(size);
     }
 
-    fn describe(&self) -> Result<String> {
-        let mut lengths = vec![];
-        let mut max_len = 0;
-        let mut min_len = 1000000;
-        let mut set: FnvHashSet<HashPrimitive> = FnvHashSet::default();
-        // iterator over hash tables 0..L
-        for map in self.hash_tables.iter() {
-            // iterator over all hashes
-            // zip to truncate at the describe maximum
-            for ((k, v), _) in map.iter().zip(0..DESCRIBE_MAX) {
-                let len = v.len();
-                let hash_values: FnvHashSet<HashPrimitive> =
-                    FnvHashSet::from_iter(k.iter().copied());
-                set = set.union(&hash_values).copied().collect();
-                lengths.push(len);
-                if len > max_len {
-                    max_len = len
+    fn stats(&self) -> Result<Stats> {
+        let tables = self
+            .hash_tables
+            .iter()
+            .map(|map| {
+                let mut histogram = BTreeMap::new();
+                let mut unique_hash_values = BTreeSet::new();
+                let mut memory_bytes = map.capacity() * size_of::<(Hash, Bucket)>();
+                for (hash, bucket) in map.iter() {
+                    *histogram.entry(bucket.len()).or_insert(0) += 1;
+                    unique_hash_values.extend(hash.iter().copied());
+                    memory_bytes += hash.capacity() * size_of::<HashPrimitive>()
+                        + bucket.capacity() * size_of::<u32>();
                 }
-                if len < min_len {
-                    min_len = len
+                TableStats {
+                    memory_bytes,
+                    unique_hash_values,
+                    ..TableStats::from_histogram(histogram)
                 }
-            }
-        }
-
-        let avg = lengths.iter().sum::<usize>() as f32 / lengths.len() as f32;
-        let var = lengths
+            })
+            .collect();
+        let vec_store_bytes = self
+            .vec_store
+            .map
             .iter()
-            .map(|&v| (avg - v as f32).powf(2.))
-            .sum::<f32>()
-            / lengths.len() as f32;
-        let std_dev = var.powf(0.5);
-
-        let mut out = String::from(&format!("No. of tables: {}\n", self.n_hash_tables));
-        out.push_str(&format!("Unique hash values:\n{:?}\n", set));
-        out.push_str("\nHash collisions:\n");
-        out.push_str(&format!("avg:\t{:?}\n", avg));
-        out.push_str(&format!("std-dev:\t{:?}\n", std_dev));
-        out.push_str(&format!("min:\t{:?}\n", min_len));
-        out.push_str(&format!("max:\t{:?}\n", max_len));
-
-        Ok(out)
+            .map(|v| size_of::<Vec<N>>() + v.capacity() * size_of::<N>())
+            .sum::<usize>()
+            + self
+                .vec_store
+                .sparse
+                .iter()
+                .map(|v| {
+                    size_of::<SparseVec<N>>()
+                        + v.indices.capacity() * size_of::<u32>()
+                        + v.values.capacity() * size_of::<N>()
+                })
+                .sum::<usize>()
+            + self
+                .vec_store
+                .inputs
+                .iter()
+                .map(|v| size_of::<Vec<u8>>() + v.capacity())
+                .sum::<usize>();
+        Ok(Stats::from_tables(tables, vec_store_bytes))
     }
 
     fn get_unique_hash_int(&self) -> FnvHashSet<HashPrimitive> {
@@ -206,3 +348,60 @@ impl<N> std::fmt::Debug for MemoryTable<N> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::LshMem;
+
+    #[test]
+    fn test_query_bucket_of_sparse() {
+        let mut lsh = LshMem::new(5, 3, 4).seed(1).srp().unwrap();
+        let v = SparseVec::new(vec![0, 2], vec![1., 2.]).unwrap();
+        lsh.store_sparse(&v).unwrap();
+        assert!(lsh.query_bucket(&[1., 0., 2., 0.]).is_err());
+        assert!(lsh.query_sparse_bucket(&v).unwrap().contains(&&v));
+
+        let mut lsh = LshMem::new(5, 3, 4).seed(1).srp().unwrap();
+        let v = [1., 0., 2., 0.];
+        lsh.store_vec(&v).unwrap();
+        assert!(lsh.query_sparse_bucket(&SparseVec::from_dense(&v)).is_err());
+    }
+
+    #[test]
+    fn test_load_dump_without_sparse() {
+        let vs = vec![vec![1., 0., 2.], vec![-1., 2., 0.]];
+        let mut lsh = LshMem::new(5, 3, 3).seed(1).srp().unwrap();
+        lsh.store_vecs(&vs).unwrap();
+        let blob = lsh.dump_bytes().unwrap();
+
+        // rewrite the dump in the old layout
+        let mut tables: Vec<u8> = vec![];
+        let mut rest: &[u8] = &blob;
+        let len: u64 = bincode::deserialize_from(&mut rest).unwrap();
+        let table: Option<MemoryTable<f32>> = bincode::deserialize(&rest[..len as usize]).unwrap();
+        let table = table.unwrap();
+        let old = Some(MemoryTableV0 {
+            hash_tables: table.hash_tables,
+            n_hash_tables: table.n_hash_tables,
+            vec_store: VecStoreV0 {
+                map: table.vec_store.map,
+            },
+            only_index_storage: table.only_index_storage,
+            counter: table.counter,
+        });
+        bincode::serialize_into(&mut tables, &old).unwrap();
+        assert!(bincode::deserialize::<Option<MemoryTable<f32>>>(&tables).is_err());
+        let mut old_blob = bincode::serialize(&tables).unwrap();
+        old_blob.extend_from_slice(&rest[len as usize..]);
+
+        let mut loaded = LshMem::<f32, _>::new(5, 3, 3).srp().unwrap();
+        loaded.load_bytes(&old_blob).unwrap();
+        for v in &vs {
+            assert_eq!(
+                loaded.query_bucket(v).unwrap(),
+                lsh.query_bucket(v).unwrap()
+            );
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use super::general::Bucket;
+use super::general::{Bucket, Stats, TableStats};
 use crate::data::Numeric;
 use crate::{
-    constants::DESCRIBE_MAX,
     hash::{Hash, HashPrimitive},
-    Error, HashTables, Result, VecHash,
+    sparse::SparseVec,
+    Error, HashTables, Result,
 };
 use fnv::FnvHashSet;
 use rusqlite::{params, Connection, NO_PARAMS};
@@ -11,6 +11,7 @@ use serde::de::DeserializeOwned;
 use serde::export::PhantomData;
 use serde::Serialize;
 use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
 
 fn vec_to_blob<T>(hash: &[T]) -> &[u8] {
     let data = hash.as_ptr() as *const u8;
@@ -39,6 +40,29 @@ WHERE hash = ?
     Ok(bucket)
 }
 
+fn query_buckets(table_name: &str, connection: &Connection) -> Result<Vec<Bucket>> {
+    let mut stmt = connection.prepare(&format!(
+        "
+SELECT hash, id FROM {}
+ORDER BY hash;
+        ",
+        table_name
+    ))?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+
+    let mut buckets = vec![];
+    let mut prev_hash: Option<Vec<u8>> = None;
+    while let Some(row) = rows.next()? {
+        let blob: Vec<u8> = row.get(0)?;
+        if prev_hash.as_ref() != Some(&blob) {
+            buckets.push(FnvHashSet::default());
+            prev_hash = Some(blob);
+        }
+        buckets.last_mut().unwrap().insert(row.get(1)?);
+    }
+    Ok(buckets)
+}
+
 fn make_table(table_name: &str, connection: &Connection) -> Result<()> {
     connection.execute_batch(&format!(
         "CREATE TABLE IF NOT EXISTS {} (
@@ -64,37 +88,33 @@ VALUES (?1, ?2)
     Ok(idx)
 }
 
-fn hash_table_stats(
-    table_name: &str,
-    limit: u32,
-    conn: &Connection,
-) -> Result<(f64, f64, u32, u32)> {
-    let mut stmt = conn.prepare_cached(&format!(
+fn hash_table_stats(table_name: &str, conn: &Connection) -> Result<TableStats> {
+    let mut stmt = conn.prepare(&format!(
         "
-SELECT
-	avg(c) as mean,
-	avg(c * c) - avg(c) * avg(c) as variance,
-	min(c) as minimum,
-	max(c) as maximum
-
-FROM (
-	SELECT count(id) as c
-	FROM {}
-	GROUP BY hash
-	LIMIT ?
-);
+SELECT hash, count(id) FROM {}
+GROUP BY hash;
     ",
         table_name
     ))?;
-    let out = stmt.query_row(params![limit], |row| {
-        let mean: f64 = row.get(0)?;
-        let variance: f64 = row.get(1)?;
-        let stdev = variance.powf(0.5);
-        let minimum: u32 = row.get(2)?;
-        let maximum: u32 = row.get(3)?;
-        Ok((mean, stdev, minimum, maximum))
-    })?;
-    Ok(out)
+    let mut rows = stmt.query(NO_PARAMS)?;
+
+    let mut histogram = BTreeMap::new();
+    let mut unique_hash_values = BTreeSet::new();
+    let mut memory_bytes = 0;
+    while let Some(row) = rows.next()? {
+        let blob: Vec<u8> = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        let count = count as usize;
+        // every row stores a hash and an id
+        memory_bytes += count * (blob.len() + std::mem::size_of::<u32>());
+        unique_hash_values.extend(blob_to_vec::<HashPrimitive>(&blob).iter().copied());
+        *histogram.entry(count).or_insert(0) += 1;
+    }
+    Ok(TableStats {
+        memory_bytes,
+        unique_hash_values,
+        ..TableStats::from_histogram(histogram)
+    })
 }
 
 /// Sqlite backend for [LSH](struct.LSH.html).
@@ -178,10 +198,19 @@ impl<N: Numeric> SqlTable<N> {
         let table_names = get_table_names(n_hash_tables);
         init_db_setttings(&conn)?;
         init_table(&conn, &table_names)?;
+        // continue the ids of an existing database.
+        let counter: i64 = match table_names.first() {
+            Some(table_name) => conn.query_row(
+                &format!("SELECT IFNULL(MAX(id) + 1, 0) FROM {}", table_name),
+                NO_PARAMS,
+                |row| row.get(0),
+            )?,
+            None => 0,
+        };
         let sql = SqlTable {
             n_hash_tables,
             only_index_storage,
-            counter: 0,
+            counter: counter as u32,
             conn,
             table_names,
             committed: Cell::new(false),
@@ -256,6 +285,14 @@ impl<N: Numeric> HashTables<N> for SqlTable<N> {
         }
     }
 
+    fn put_sparse(&mut self, hash: Hash, _d: &SparseVec<N>, hash_table: usize) -> Result<u32> {
+        self.put(hash, &[], hash_table)
+    }
+
+    fn put_input(&mut self, hash: Hash, _d: &[u8], hash_table: usize) -> Result<u32> {
+        self.put(hash, &[], hash_table)
+    }
+
     /// Query the whole bucket
     fn query_bucket(&self, hash: &Hash, hash_table: usize) -> Result<Bucket> {
         self.commit()?;
@@ -269,46 +306,23 @@ impl<N: Numeric> HashTables<N> for SqlTable<N> {
         }
     }
 
-    fn describe(&self) -> Result<String> {
-        let mut stmt = self.conn.prepare(
-            r#"SELECT count(*) FROM sqlite_master
-WHERE type='table' AND type LIKE '%hash%';"#,
-        )?;
+    fn buckets(&self, hash_table: usize) -> Result<Vec<Bucket>> {
+        let table_name = self
+            .table_names
+            .get(hash_table)
+            .ok_or(Error::TableNotExist)?;
+        query_buckets(table_name, &self.conn)
+    }
 
-        let row: String = stmt.query_row(NO_PARAMS, |row| {
-            let i: i64 = row.get_unwrap(0);
-            Ok(i.to_string())
-        })?;
-        let mut out = String::from(format!("No. of tables: {}\n", row));
-
-        out.push_str("Unique hash values:\n");
-        let hv = get_unique_hash_int(self.n_hash_tables, &self.conn).unwrap();
-        out.push_str(&format!("{:?}", hv));
-
-        let tables = get_table_names(self.n_hash_tables);
-        let mut avg = Vec::with_capacity(self.n_hash_tables);
-        let mut std_dev = Vec::with_capacity(self.n_hash_tables);
-        let mut min = Vec::with_capacity(self.n_hash_tables);
-        let mut max = Vec::with_capacity(self.n_hash_tables);
-
-        // maximum 3 tables will be used in stats
-        let i = std::cmp::min(3, self.n_hash_tables);
-        for table_name in &tables[..i] {
-            let stats = hash_table_stats(&table_name, DESCRIBE_MAX, &self.conn)?;
-            avg.push(stats.0);
-            std_dev.push(stats.1);
-            min.push(stats.2);
-            max.push(stats.3);
-        }
-        out.push_str("\nHash collisions:\n");
-        out.push_str(&format!("avg:\t{:?}\n", avg));
-        out.push_str(&format!("std-dev:\t{:?}\n", std_dev));
-        out.push_str(&format!("min:\t{:?}\n", min));
-        out.push_str(&format!("max:\t{:?}\n", max));
-        Ok(out)
+    fn stats(&self) -> Result<Stats> {
+        let tables = get_table_names(self.n_hash_tables)
+            .iter()
+            .map(|table_name| hash_table_stats(table_name, &self.conn))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Stats::from_tables(tables, 0))
     }
 
-    fn store_hashers<H: VecHash<N> + Serialize>(&mut self, hashers: &[H]) -> Result<()> {
+    fn store_hashers<H: Serialize>(&mut self, hashers: &[H]) -> Result<()> {
         let buf: Vec<u8> = bincode::serialize(hashers)?;
 
         // fails if already exists
@@ -328,7 +342,7 @@ WHERE type='table' AND type LIKE '%hash%';"#,
         Ok(())
     }
 
-    fn load_hashers<H: VecHash<N> + DeserializeOwned>(&self) -> Result<Vec<H>> {
+    fn load_hashers<H: DeserializeOwned>(&self) -> Result<Vec<H>> {
         let mut stmt = self.conn.prepare("SELECT * FROM state;")?;
         let buf: Vec<u8> = stmt.query_row(NO_PARAMS, |row| {
             let v: Vec<u8> = row.get_unwrap(0);
@@ -376,6 +390,15 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_stats_keeps_transaction() {
+        let mut sql = *SqlTableMem::new(1, true, ".").unwrap();
+        sql.put(vec![1, 2], &[1., 2.], 0).unwrap();
+        // uncommitted data points are counted and the transaction stays open.
+        assert_eq!(sql.stats().unwrap().n_points, 1);
+        assert!(!sql.committed.get());
+    }
+
     #[test]
     fn test_blob_hash_casting() {
         for hash in vec![
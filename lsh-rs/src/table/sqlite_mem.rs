@@ -2,7 +2,8 @@ use super::sqlite::SqlTable;
 use crate::{
     data::Numeric,
     hash::{Hash, HashPrimitive},
-    table::general::Bucket,
+    sparse::SparseVec,
+    table::general::{Bucket, Stats},
     HashTables, Result,
 };
 use fnv::FnvHashSet;
@@ -57,6 +58,14 @@ impl<N: Numeric> HashTables<N> for SqlTableMem<N> {
         self.sql_table.put(hash, d, hash_table)
     }
 
+    fn put_sparse(&mut self, hash: Hash, d: &SparseVec<N>, hash_table: usize) -> Result<u32> {
+        self.sql_table.put_sparse(hash, d, hash_table)
+    }
+
+    fn put_input(&mut self, hash: Hash, d: &[u8], hash_table: usize) -> Result<u32> {
+        self.sql_table.put_input(hash, d, hash_table)
+    }
+
     fn delete(&mut self, hash: &Hash, d: &[N], hash_table: usize) -> Result<()> {
         self.sql_table.delete(hash, d, hash_table)
     }
@@ -66,12 +75,16 @@ impl<N: Numeric> HashTables<N> for SqlTableMem<N> {
         self.sql_table.query_bucket(hash, hash_table)
     }
 
+    fn buckets(&self, hash_table: usize) -> Result<Vec<Bucket>> {
+        self.sql_table.buckets(hash_table)
+    }
+
     fn idx_to_datapoint(&self, idx: u32) -> Result<&Vec<N>> {
         self.sql_table.idx_to_datapoint(idx)
     }
 
-    fn describe(&self) -> Result<String> {
-        self.sql_table.describe()
+    fn stats(&self) -> Result<Stats> {
+        self.sql_table.stats()
     }
 
     fn get_unique_hash_int(&self) -> FnvHashSet<HashPrimitive> {
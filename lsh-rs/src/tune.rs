@@ -0,0 +1,413 @@
+//! Automatic parameter tuning.
+//!
+//! Searches over `K`, `L`, `r` (L2 only) and the multi-probe budget, measures the recall@k of
+//! every configuration on a data sample and returns the cheapest configuration that reaches a
+//! target recall within a memory limit.
+//!
+//! # Example
+//!
+//! ```
+//! use lsh_rs::{LshMem, tune::{tune, Family, TuneConfig}};
+//! let vs: Vec<Vec<f32>> = (0..200)
+//!     .map(|i| vec![(i as f32).sin(), (i as f32).cos(), 0.5])
+//!     .collect();
+//! let qs = vec![vec![0.1, 0.9, 0.5], vec![-0.5, 0.3, 0.5]];
+//!
+//! let mut cfg = TuneConfig::new(Family::Srp, 0.8, 5);
+//! cfg.k = vec![4, 8];
+//! cfg.l = vec![5, 10, 20];
+//! let res = tune(&vs, &qs, &cfg).unwrap();
+//!
+//! // ready to use builder configuration
+//! let lsh: LshMem<f32, _> = res.builder(3).srp().unwrap();
+//! ```
+use crate::{
+    eval::{brute_force_knn, evaluate, EvalRes, Metric},
+    hash::{Hash, SignRandomProjections, VecHash, L2},
+    Error, HashTables, LshMem, Result, LSH,
+};
+use fnv::FnvHashSet;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+
+/// Hash families supported by the tuner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Family {
+    /// Sign Random Projections (cosine similarity).
+    Srp,
+    /// L2 distance.
+    L2,
+}
+
+impl Family {
+    fn metric(self) -> Metric {
+        match self {
+            Family::Srp => Metric::Cosine,
+            Family::L2 => Metric::L2,
+        }
+    }
+}
+
+/// Search space and constraints of the tuner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuneConfig {
+    pub family: Family,
+    /// Minimal recall@k the configuration should reach.
+    pub target_recall: f64,
+    /// `k` of recall@k.
+    pub top_k: usize,
+    /// Maximum memory in bytes the index may use for `n_total` data points.
+    pub memory_limit: usize,
+    /// Number of data points the final index will hold. If 0, the sample size is used.
+    pub n_total: usize,
+    /// Candidate values for `K`.
+    pub k: Vec<usize>,
+    /// Candidate values for `L`. These are tried in ascending order.
+    pub l: Vec<usize>,
+    /// Candidate values for the L2 parameter `r`. If empty, these are derived from the distance
+    /// to the nearest neighbors in the sample.
+    pub r: Vec<f32>,
+    /// Candidate multi-probe budgets. A budget of 0 means no multi-probing.
+    pub multi_probe_budget: Vec<usize>,
+    pub seed: u64,
+}
+
+impl TuneConfig {
+    /// Create a configuration with a default search space.
+    ///
+    /// # Arguments
+    /// * `family` - Hash family.
+    /// * `target_recall` - Minimal recall@k.
+    /// * `top_k` - `k` of recall@k.
+    pub fn new(family: Family, target_recall: f64, top_k: usize) -> Self {
+        TuneConfig {
+            family,
+            target_recall,
+            top_k,
+            memory_limit: usize::MAX,
+            n_total: 0,
+            k: (4..=24).step_by(4).collect(),
+            l: vec![5, 10, 20, 40, 80],
+            r: vec![],
+            multi_probe_budget: vec![0, 8, 32],
+            seed: 1,
+        }
+    }
+}
+
+/// Configuration found by the tuner and its predicted cost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuneResult {
+    pub family: Family,
+    /// Number of projections.
+    pub k: usize,
+    /// Number of hash tables.
+    pub l: usize,
+    /// L2 parameter `r`. `None` for SRP.
+    pub r: Option<f32>,
+    /// Multi-probe budget. `None` if multi-probing is not used.
+    pub multi_probe_budget: Option<usize>,
+    pub seed: u64,
+    /// Measured on the sample.
+    pub eval: EvalRes,
+    /// Predicted memory usage in bytes for `n_total` data points.
+    pub memory_bytes: usize,
+    /// Predicted average number of candidates per query for `n_total` data points.
+    pub predicted_candidates: f64,
+    /// Predicted average latency per query (seconds) for `n_total` data points.
+    pub predicted_latency: f64,
+}
+
+impl TuneResult {
+    /// LSH builder with the tuned parameters. Finish the builder with the hash family method
+    /// (i.e. `.srp()` or `.l2(r)`).
+    ///
+    /// # Arguments
+    /// * `dim` - Dimensions of the data points.
+    pub fn builder<N, T, H>(&self, dim: usize) -> LSH<N, T, H>
+    where
+        N: crate::data::Numeric,
+        T: HashTables<N>,
+        H: VecHash<N>,
+    {
+        let mut lsh = LSH::new(self.k, self.l, dim);
+        lsh.seed(self.seed);
+        if let Some(budget) = self.multi_probe_budget {
+            lsh.multi_probe(budget);
+        }
+        lsh
+    }
+
+    /// Build an L2 LSH with the tuned parameters.
+    pub fn build_l2<T: HashTables<f32>>(&self, dim: usize) -> Result<LSH<f32, T, L2<f32>>> {
+        let r = self
+            .r
+            .ok_or_else(|| Error::Failed("parameters are not tuned for L2".to_string()))?;
+        self.builder(dim).l2(r)
+    }
+
+    /// Build a Sign Random Projections LSH with the tuned parameters.
+    pub fn build_srp<T: HashTables<f32>>(
+        &self,
+        dim: usize,
+    ) -> Result<LSH<f32, T, SignRandomProjections<f32>>> {
+        self.builder(dim).srp()
+    }
+}
+
+/// Predicted memory of an in memory index.
+///
+/// Every hash table stores an id per data point (hash set with a load factor of ~50%) and
+/// a hash plus bucket per unique hash. The hashers store a `K x dim` projection matrix. The
+/// data points are stored once, as the tuned index doesn't use `only_index`.
+///
+/// # Arguments
+/// * `n_points` - Number of data points.
+/// * `n_buckets` - Number of unique hashes per table.
+/// * `k` - Number of projections.
+/// * `l` - Number of hash tables.
+/// * `dim` - Dimensions of the data points.
+pub fn estimate_memory(n_points: usize, n_buckets: usize, k: usize, l: usize, dim: usize) -> usize {
+    let id_bytes = 2 * size_of::<u32>() * n_points;
+    let bucket_bytes = n_buckets * (size_of::<Hash>() + k + size_of::<FnvHashSet<u32>>());
+    let hasher_bytes = k * dim * size_of::<f32>();
+    let vec_bytes = n_points * (size_of::<Vec<f32>>() + dim * size_of::<f32>());
+    l * (id_bytes + bucket_bytes + hasher_bytes) + vec_bytes
+}
+
+/// Average number of unique hashes over the hash tables.
+fn unique_hashes<H: VecHash<f32>>(hashers: &[H], vs: &[Vec<f32>]) -> usize {
+    let total: usize = hashers
+        .iter()
+        .map(|h| {
+            vs.iter()
+                .map(|v| h.hash_vec_put(v))
+                .collect::<FnvHashSet<Hash>>()
+                .len()
+        })
+        .sum();
+    total / std::cmp::max(hashers.len(), 1)
+}
+
+/// Candidate `r` values derived from the mean distance to the `top_k` nearest neighbors.
+fn default_r(vs: &[Vec<f32>], qs: &[Vec<f32>], ground_truth: &[Vec<u32>]) -> Vec<f32> {
+    let mut total = 0.;
+    let mut n = 0;
+    for (q, ids) in qs.iter().zip(ground_truth) {
+        for &idx in ids {
+            total += Metric::L2.distance(q, &vs[idx as usize]);
+            n += 1
+        }
+    }
+    let mean = if n > 0 { total / n as f32 } else { 1. };
+    [1., 2., 4., 8.].iter().map(|f| f * mean).collect()
+}
+
+struct Candidate {
+    k: usize,
+    r: Option<f32>,
+    budget: Option<usize>,
+}
+
+fn build_and_eval(
+    c: &Candidate,
+    l: usize,
+    cfg: &TuneConfig,
+    vs: &[Vec<f32>],
+    qs: &[Vec<f32>],
+    ground_truth: &[Vec<u32>],
+) -> Result<(EvalRes, usize)> {
+    let dim = vs[0].len();
+    let metric = cfg.family.metric();
+    macro_rules! run {
+        ($family:ident $(, $arg:expr)*) => {{
+            let mut builder = LshMem::new(c.k, l, dim);
+            builder.seed(cfg.seed);
+            if let Some(budget) = c.budget {
+                builder.multi_probe(budget);
+            }
+            let mut lsh = builder.$family($($arg),*)?;
+            lsh.store_vecs(vs)?;
+            let eval = evaluate(&lsh, vs, qs, ground_truth, cfg.top_k, metric)?;
+            (eval, unique_hashes(&lsh.hashers, vs))
+        }};
+    }
+    let out = match (cfg.family, c.r) {
+        (Family::L2, Some(r)) => run!(l2, r),
+        _ => run!(srp),
+    };
+    Ok(out)
+}
+
+/// Search the cheapest configuration that reaches the target recall within the memory limit.
+///
+/// For every combination of `K`, `r` and multi-probe budget, `L` is increased until the target
+/// recall is reached or the memory limit is exceeded. Of all configurations that meet the
+/// constraints, the one with the lowest predicted latency is returned.
+///
+/// # Arguments
+/// * `vs` - Data sample.
+/// * `qs` - Held out query points.
+/// * `cfg` - Search space and constraints.
+pub fn tune(vs: &[Vec<f32>], qs: &[Vec<f32>], cfg: &TuneConfig) -> Result<TuneResult> {
+    if vs.is_empty() || qs.is_empty() {
+        return Err(Error::Failed(
+            "data sample or queries are empty".to_string(),
+        ));
+    }
+    let dim = vs[0].len();
+    if vs.iter().chain(qs).any(|v| v.len() != dim) {
+        return Err(Error::Failed(
+            "data points have different dimensions".to_string(),
+        ));
+    }
+    let n_total = if cfg.n_total == 0 {
+        vs.len()
+    } else {
+        cfg.n_total
+    };
+    let scale = n_total as f64 / vs.len() as f64;
+    let ground_truth = brute_force_knn(vs, qs, cfg.top_k, cfg.family.metric());
+
+    let rs: Vec<Option<f32>> = match cfg.family {
+        Family::Srp => vec![None],
+        Family::L2 if cfg.r.is_empty() => default_r(vs, qs, &ground_truth)
+            .into_iter()
+            .map(Some)
+            .collect(),
+        Family::L2 => cfg.r.iter().copied().map(Some).collect(),
+    };
+    let mut candidates = vec![];
+    for &k in &cfg.k {
+        for &r in &rs {
+            for &budget in &cfg.multi_probe_budget {
+                let budget = if budget == 0 { None } else { Some(budget) };
+                // Query directed probing has at most 4^K - 1 perturbations of a hash.
+                if let (Family::L2, Some(b)) = (cfg.family, budget) {
+                    if b as f64 >= 4f64.powi(k as i32) {
+                        continue;
+                    }
+                }
+                candidates.push(Candidate { k, r, budget })
+            }
+        }
+    }
+    let mut ls = cfg.l.clone();
+    ls.sort_unstable();
+
+    let results = candidates
+        .par_iter()
+        .map(|c| {
+            for &l in &ls {
+                let (eval, n_buckets) = build_and_eval(c, l, cfg, vs, qs, &ground_truth)?;
+                let n_buckets = (n_buckets as f64 * scale) as usize;
+                let memory_bytes = estimate_memory(n_total, n_buckets, c.k, l, dim);
+                if memory_bytes > cfg.memory_limit {
+                    return Ok(None);
+                }
+                if eval.recall >= cfg.target_recall {
+                    let n_queries = eval.n_queries as f64;
+                    return Ok(Some(TuneResult {
+                        family: cfg.family,
+                        k: c.k,
+                        l,
+                        r: c.r,
+                        multi_probe_budget: c.budget,
+                        seed: cfg.seed,
+                        memory_bytes,
+                        predicted_candidates: eval.avg_candidates * scale,
+                        predicted_latency: (eval.query_time + eval.rerank_time * scale) / n_queries,
+                        eval,
+                    }));
+                }
+            }
+            Ok(None)
+        })
+        .collect::<Result<Vec<Option<TuneResult>>>>()?;
+
+    results
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| {
+            a.predicted_latency
+                .partial_cmp(&b.predicted_latency)
+                .unwrap()
+        })
+        .ok_or_else(|| {
+            Error::Failed("no configuration reaches the target recall within limits".to_string())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn data() -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        let vs: Vec<Vec<f32>> = (0..300)
+            .map(|i| {
+                let i = i as f32;
+                vec![i.sin(), i.cos(), (i * 0.3).sin()]
+            })
+            .collect();
+        let qs = vs[..10]
+            .iter()
+            .map(|v| v.iter().map(|x| x + 0.01).collect())
+            .collect();
+        (vs, qs)
+    }
+
+    #[test]
+    fn test_tune_l2() {
+        let (vs, qs) = data();
+        let mut cfg = TuneConfig::new(Family::L2, 0.9, 3);
+        cfg.k = vec![2, 6];
+        cfg.l = vec![10, 40];
+        cfg.multi_probe_budget = vec![0, 4];
+        let res = tune(&vs, &qs, &cfg).unwrap();
+        assert!(res.eval.recall >= 0.9);
+        assert!(res.r.is_some());
+        let lsh: LshMem<f32, _> = res.build_l2(3).unwrap();
+        assert_eq!(lsh.n_hash_tables, res.l);
+    }
+
+    #[test]
+    fn test_tune_memory_limit() {
+        let (vs, qs) = data();
+        let mut cfg = TuneConfig::new(Family::Srp, 0.9, 3);
+        cfg.memory_limit = 10;
+        assert!(tune(&vs, &qs, &cfg).is_err());
+    }
+
+    #[test]
+    fn test_tune_errors() {
+        let (vs, _) = data();
+        let qs = vec![vec![0.1, 0.2]];
+        let cfg = TuneConfig::new(Family::Srp, 0.9, 3);
+        match tune(&vs, &qs, &cfg) {
+            Err(Error::Failed(msg)) => assert!(msg.contains("dimensions")),
+            _ => panic!("expected a dimension error"),
+        }
+
+        // a budget larger than the query directed probes is skipped.
+        let (vs, qs) = data();
+        let mut cfg = TuneConfig::new(Family::L2, 0.5, 3);
+        cfg.k = vec![1];
+        cfg.l = vec![10];
+        cfg.multi_probe_budget = vec![4];
+        assert!(tune(&vs, &qs, &cfg).is_err());
+        cfg.multi_probe_budget = vec![0, 4];
+        assert_eq!(tune(&vs, &qs, &cfg).unwrap().multi_probe_budget, None);
+    }
+
+    #[test]
+    fn test_estimate_memory() {
+        // the data points are stored once, the ids and hashers per hash table.
+        let vec_bytes = 100 * (size_of::<Vec<f32>>() + 3 * size_of::<f32>());
+        assert_eq!(estimate_memory(100, 0, 0, 0, 3), vec_bytes);
+        assert_eq!(
+            estimate_memory(100, 0, 2, 2, 3),
+            vec_bytes + 2 * (800 + 2 * 3 * 4)
+        );
+    }
+}